@@ -12,6 +12,11 @@ pub struct Config {
     #[serde(default = "default_prefix")]
     pub prefix: String,
 
+    #[serde(default = "default_prefetch_seconds")]
+    pub prefetch_seconds: u64,
+    #[serde(default)]
+    pub crossfade_seconds: u64,
+
     #[serde(default = "default_database_user")]
     pub database_user: String,
     #[serde(default = "default_database_host")]
@@ -19,12 +24,45 @@ pub struct Config {
     #[serde(default = "default_database_port")]
     pub database_port: u64,
     pub database_password: String,
+
+    #[serde(default)]
+    pub lastfm_api_key: Option<String>,
+    #[serde(default)]
+    pub lastfm_api_secret: Option<String>,
+    #[serde(default = "default_listenbrainz_api_url")]
+    pub listenbrainz_api_url: String,
+
+    #[serde(default)]
+    pub cookies_file: Option<String>,
+    #[serde(default)]
+    pub proxy: Option<String>,
+    #[serde(default)]
+    pub source_addresses: Vec<String>,
+
+    #[serde(default)]
+    pub web_enabled: bool,
+    #[serde(default = "default_web_address")]
+    pub web_address: String,
+    #[serde(default)]
+    pub web_token: Option<String>,
 }
 
 fn default_prefix() -> String {
     "~".into()
 }
 
+fn default_prefetch_seconds() -> u64 {
+    10
+}
+
+fn default_listenbrainz_api_url() -> String {
+    crate::music::LISTENBRAINZ_API_URL.into()
+}
+
+fn default_web_address() -> String {
+    "127.0.0.1:8080".into()
+}
+
 fn default_database_user() -> String {
     "postgres".into()
 }
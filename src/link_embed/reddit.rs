@@ -82,14 +82,20 @@ async fn resolve_link_redirect(url: &Url) -> Option<Url> {
         header::USER_AGENT,
         header::HeaderValue::from_static(REDDIT_USER_AGENT),
     );
-    if let Some(auth) = REDDIT_ACCESS_TOKEN.authentication(&CLIENT).await {
+    if let Some(auth) = REDDIT_ACCESS_TOKEN.authentication(CLIENT.next()).await {
         headers.insert(
             header::AUTHORIZATION,
             header::HeaderValue::from_str(&auth).unwrap(),
         );
     }
 
-    if let Ok(response) = CLIENT.head(url.as_str()).headers(headers).send().await {
+    if let Ok(response) = CLIENT
+        .next()
+        .head(url.as_str())
+        .headers(headers)
+        .send()
+        .await
+    {
         Url::parse(response.url().as_str()).ok()
     } else {
         None
@@ -117,7 +123,7 @@ async fn reddit_post_media(submission_id: &str) -> Option<Url> {
         header::USER_AGENT,
         header::HeaderValue::from_static(REDDIT_USER_AGENT),
     );
-    if let Some(auth) = REDDIT_ACCESS_TOKEN.authentication(&CLIENT).await {
+    if let Some(auth) = REDDIT_ACCESS_TOKEN.authentication(CLIENT.next()).await {
         headers.insert(
             header::AUTHORIZATION,
             header::HeaderValue::from_str(&auth).unwrap(),
@@ -148,6 +154,7 @@ async fn reddit_post_media(submission_id: &str) -> Option<Url> {
 
     let endpoint_url = format!("https://oauth.reddit.com/comments/{}/", submission_id);
     let response = CLIENT
+        .next()
         .get(endpoint_url)
         .headers(headers)
         .query(&[("api_type", "json")])
@@ -2,10 +2,12 @@ mod config;
 mod link_embed;
 mod message;
 mod music;
+mod network;
 mod package_update;
 mod patchbot_forwarder;
 
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::LazyLock;
 use std::time::Duration;
 
@@ -19,22 +21,28 @@ use tokio::signal::unix::{signal, SignalKind};
 
 use crate::config::Config;
 use crate::message::{SendMessage, SendableMessage};
-use crate::music::{handle_voice_state_event, MusicError, QueueMutexMap};
+use crate::music::{
+    check_music_channel, handle_channel_delete, handle_voice_state_event, join_always_on_channels,
+    start_announcer, start_schedules, start_scrobbler, start_stats_recorder, start_web_server,
+    GuildSettingsMap, MusicError, NotificationMap, QueueMutexMap, ScheduleTaskMap, ScrobbleClient,
+    SleepTimerMap, TrackHistoryMap, VoiceTrackerMap, LASTFM_API_URL,
+};
+use crate::network::{network, ClientPool, NetworkOptions};
 
 pub type PoiseError = Box<dyn std::error::Error + Send + Sync>;
 pub type PoiseContext<'a> = poise::Context<'a, Data, PoiseError>;
 #[derive(Debug)]
 pub struct Data {
     db_uri: String,
+    prefetch_time: Duration,
+    crossfade_time: Duration,
+    scrobble_client: ScrobbleClient,
+    web_address: Option<SocketAddr>,
+    web_token: Option<String>,
 }
 
-pub static CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
-    reqwest::Client::builder()
-        .use_rustls_tls()
-        .timeout(Duration::from_secs(5))
-        .build()
-        .unwrap()
-});
+pub static CLIENT: LazyLock<ClientPool> =
+    LazyLock::new(|| ClientPool::new(network()).expect("Invalid proxy or cookies file"));
 
 /// Registers slash commands in this guild or globally
 #[poise::command(prefix_command, hide_in_help, owners_only)]
@@ -66,24 +74,34 @@ async fn pre_command(ctx: PoiseContext<'_>) {
     );
 }
 
+/// Notify the caller of a command that returned a MusicError
+async fn reply_music_error(ctx: PoiseContext<'_>, error: &PoiseError) {
+    if let Some(e) = error.downcast_ref::<MusicError>() {
+        match e {
+            MusicError::Internal(e) => {
+                eprintln!("Internal error: {:?}", e);
+                SendMessage::Error("an internal error occured")
+                    .send_msg(ctx)
+                    .await;
+            }
+            _ => {
+                SendMessage::Error(e.to_string()).send_msg(ctx).await;
+            }
+        }
+    }
+}
+
 async fn on_error(error: poise::FrameworkError<'_, Data, PoiseError>) {
     match error {
         poise::FrameworkError::Setup { error, .. } => panic!("Failed to start bot: {:?}", error),
         poise::FrameworkError::Command { error, ctx } => {
-            if let Some(e) = error.downcast_ref::<MusicError>() {
-                // If command returned a MusicError, notify the caller by sending a reply
-                match e {
-                    MusicError::Internal(e) => {
-                        eprintln!("Internal error: {:?}", e);
-                        SendMessage::Error("an internal error occured")
-                            .send_msg(ctx)
-                            .await;
-                    }
-                    _ => {
-                        SendMessage::Error(e.to_string()).send_msg(ctx).await;
-                    }
-                }
-            }
+            reply_music_error(ctx, &error).await;
+        }
+        poise::FrameworkError::CommandCheckFailed {
+            error: Some(error),
+            ctx,
+        } => {
+            reply_music_error(ctx, &error).await;
         }
         poise::FrameworkError::ArgumentParse { error, ctx, .. } => {
             SendMessage::Error(error.to_string()).send_msg(ctx).await;
@@ -104,9 +122,20 @@ async fn on_event<U, E>(
 ) -> Result<(), PoiseError> {
     #[allow(clippy::single_match)]
     match event {
+        Event::Ready { .. } => {
+            start_announcer(ctx);
+            join_always_on_channels(ctx).await;
+            start_schedules(ctx, data).await;
+            start_scrobbler(ctx, data);
+            start_stats_recorder();
+            start_web_server(ctx, data);
+        }
         Event::VoiceStateUpdate { new: state, .. } => {
             handle_voice_state_event(ctx, state).await;
         }
+        Event::ChannelDelete { channel } => {
+            handle_channel_delete(ctx, channel).await;
+        }
         Event::Message {
             new_message: message,
         } => {
@@ -132,20 +161,67 @@ async fn on_event<U, E>(
 async fn main() -> Result<()> {
     let config = Config::get_config()?;
 
+    // Route outbound requests before any are made
+    let source_addresses = config
+        .source_addresses
+        .iter()
+        .map(|a| a.parse())
+        .collect::<Result<_, _>>()?;
+    network::init(NetworkOptions::new(
+        config.cookies_file.clone(),
+        config.proxy.clone(),
+        source_addresses,
+    ));
+    LazyLock::force(&CLIENT);
+
     // Set up message forwarder
     let db_uri = patchbot_forwarder::create_table(&config).await;
 
+    // Load music settings
+    music::create_tables(&db_uri).await?;
+    let music_settings = music::load_settings().await?;
+
+    let prefetch_time = Duration::from_secs(config.prefetch_seconds);
+    let crossfade_time = Duration::from_secs(config.crossfade_seconds);
+    let scrobble_client = ScrobbleClient::new(
+        LASTFM_API_URL,
+        config.lastfm_api_key,
+        config.lastfm_api_secret,
+        &config.listenbrainz_api_url,
+    );
+    let web_address = if config.web_enabled {
+        Some(config.web_address.parse()?)
+    } else {
+        None
+    };
+    let web_token = config.web_token;
+
     // Add bot commands
     let commands = vec![
         register(),
         help(),
         music::commands::album(),
+        music::commands::artist(),
+        music::commands::clear(),
+        music::commands::dedupe(),
         music::commands::list(),
+        music::commands::move_here(),
         music::commands::pause(),
         music::commands::play(),
+        music::commands::playnext(),
+        music::commands::playnow(),
+        music::commands::previous(),
+        music::commands::queue(),
         music::commands::remove(),
+        music::commands::replay(),
+        music::commands::schedule(),
+        music::commands::scrobble(),
+        music::commands::settings(),
         music::commands::skip(),
+        music::commands::skipto(),
+        music::commands::sleep(),
         music::commands::song(),
+        music::commands::stats(),
         music::commands::stop(),
         music::commands::video(),
         patchbot_forwarder::commands::patchbot_forward(),
@@ -161,6 +237,7 @@ async fn main() -> Result<()> {
             ..Default::default()
         },
         pre_command: |ctx| Box::pin(pre_command(ctx)),
+        command_check: Some(|ctx| Box::pin(check_music_channel(ctx))),
         on_error: |error| Box::pin(on_error(error)),
         event_handler: |ctx, event, framework, data| {
             Box::pin(on_event(ctx, event, framework, data))
@@ -179,6 +256,11 @@ async fn main() -> Result<()> {
             Box::pin(async move {
                 Ok(Data {
                     db_uri,
+                    prefetch_time,
+                    crossfade_time,
+                    scrobble_client,
+                    web_address,
+                    web_token,
                 })
             })
         })
@@ -190,6 +272,12 @@ async fn main() -> Result<()> {
     {
         let mut data = client.data.write().await;
         data.insert::<QueueMutexMap>(HashMap::new());
+        data.insert::<TrackHistoryMap>(HashMap::new());
+        data.insert::<GuildSettingsMap>(music_settings);
+        data.insert::<VoiceTrackerMap>(HashMap::new());
+        data.insert::<SleepTimerMap>(HashMap::new());
+        data.insert::<ScheduleTaskMap>(HashMap::new());
+        data.insert::<NotificationMap>(HashMap::new());
     }
 
     // Register signal handlers
@@ -31,14 +31,14 @@ pub enum CustomSendMessage<'a> {
 #[async_trait]
 pub trait SendableMessage {
     /// Send a regular message to a channel
-    async fn send_msg_http(self, http: &Http, channel_id: ChannelId)
+    async fn send_msg_http(self, http: &Http, channel_id: ChannelId) -> serenity::Result<()>
     where
         Self: Sized,
     {
         channel_id
             .send_message(http, |m| m.embed(|e| self.build_embed(e)))
             .await
-            .unwrap();
+            .map(|_| ())
     }
 
     /// Send a reply to a command
@@ -150,3 +150,22 @@ fn to_string_or_default(val: impl Display) -> String {
         s
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serenity::HttpBuilder;
+
+    #[tokio::test]
+    async fn test_send_msg_http_error() {
+        // Nothing listens on this port, so sending fails instead of reaching Discord
+        let http = HttpBuilder::new("token")
+            .proxy("http://127.0.0.1:1")
+            .unwrap()
+            .build();
+        let result = SendMessage::Normal("test")
+            .send_msg_http(&http, ChannelId(1))
+            .await;
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,273 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use poise::serenity_prelude as serenity;
+use serenity::{ChannelId, GuildId, MessageId, Mutex, TypeMapKey};
+
+use super::bus::{spawn_subscriber, MusicEvent};
+use super::error::{log_error, InternalError};
+use super::message::PlayUpdate;
+use super::queue::RequestChannel;
+use super::settings::get_settings;
+use crate::message::{SendMessage, SendableMessage};
+
+/// Time to wait for more tracks before announcing that the queue ended
+const QUEUE_END_DELAY: Duration = Duration::from_secs(2);
+/// At most this many announcements are sent to a guild within `RATE_LIMIT_WINDOW`
+const RATE_LIMIT_COUNT: usize = 5;
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(10);
+
+/// Set once the announcer is subscribed, since the bot can become ready more than once
+static ANNOUNCER_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Announcement state of each guild
+pub struct NotificationMap;
+
+impl TypeMapKey for NotificationMap {
+    type Value = HashMap<GuildId, GuildNotifications>;
+}
+
+#[derive(Debug, Default)]
+pub struct GuildNotifications {
+    /// Last now playing message, edited in quiet mode and deleted in clean mode
+    now_playing: Option<(ChannelId, MessageId)>,
+    /// Incremented whenever a track starts or ends, so only the last end is announced
+    generation: usize,
+    /// When recent announcements were sent
+    sent: VecDeque<Instant>,
+}
+
+impl GuildNotifications {
+    /// Reserve a message if the rate limit allows it
+    fn try_send(&mut self, now: Instant) -> bool {
+        while self
+            .sent
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= RATE_LIMIT_WINDOW)
+        {
+            self.sent.pop_front();
+        }
+        if self.sent.len() >= RATE_LIMIT_COUNT {
+            return false;
+        }
+        self.sent.push_back(now);
+        true
+    }
+}
+
+/// Announce music events in the channel each track was requested from
+pub fn start_announcer(ctx: &serenity::Context) {
+    if ANNOUNCER_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let ctx = ctx.clone();
+    spawn_subscriber("announcer", move |event| {
+        let ctx = ctx.clone();
+        async move { announce_event(&ctx, event).await }
+    });
+}
+
+async fn announce_event(ctx: &serenity::Context, event: MusicEvent) {
+    let guild_id = event.guild_id();
+    let track = match &event {
+        MusicEvent::TrackStarted { track, .. }
+        | MusicEvent::TrackResumed { track, .. }
+        | MusicEvent::TrackPaused {
+            track, auto: true, ..
+        }
+        | MusicEvent::TrackEnded {
+            track,
+            finished: true,
+            ..
+        } => track,
+        // Everything else is a reply to a command
+        _ => return,
+    };
+    let channel_id = match track.typemap().read().await.get::<RequestChannel>() {
+        Some(c) => *c,
+        None => return,
+    };
+
+    match event {
+        MusicEvent::TrackStarted {
+            track, queue_len, ..
+        } => {
+            let update = PlayUpdate::Play(track, queue_len);
+            announce_now_playing(ctx, guild_id, channel_id, update).await;
+        }
+        MusicEvent::TrackResumed { track, auto, .. } => {
+            let update = if auto {
+                PlayUpdate::AutoResume(track)
+            } else {
+                PlayUpdate::Resume(track)
+            };
+            announce_now_playing(ctx, guild_id, channel_id, update).await;
+        }
+        MusicEvent::TrackPaused { track, .. } => {
+            announce(ctx, guild_id, channel_id, PlayUpdate::AutoPause(track)).await;
+        }
+        MusicEvent::TrackEnded { .. } => {
+            let call = songbird::get(ctx).await.and_then(|m| m.get(guild_id));
+            if let Some(call) = call {
+                announce_queue_end(ctx, guild_id, channel_id, call).await;
+            }
+        }
+        _ => (),
+    }
+}
+
+/// Run a function on the notification state of a guild
+async fn with_notifications<T>(
+    ctx: &serenity::Context,
+    guild_id: GuildId,
+    f: impl FnOnce(&mut GuildNotifications) -> T,
+) -> Option<T> {
+    let mut data = ctx.data.write().await;
+    let map = data.get_mut::<NotificationMap>()?;
+    Some(f(map.entry(guild_id).or_default()))
+}
+
+/// Send a playback update unless too many were sent recently
+async fn announce(
+    ctx: &serenity::Context,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    update: PlayUpdate,
+) {
+    if with_notifications(ctx, guild_id, |n| n.try_send(Instant::now())).await != Some(true) {
+        return;
+    }
+    let fmt = update.format().await;
+    let result = channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                fmt(e);
+                e
+            })
+        })
+        .await;
+    log_error(
+        guild_id,
+        result.map(|_| ()).map_err(InternalError::SendMessage),
+    );
+}
+
+/// Announce a track that started playing
+///
+/// In quiet mode the previous now playing message is edited instead of sending a new one, in
+/// clean mode it is deleted
+async fn announce_now_playing(
+    ctx: &serenity::Context,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    update: PlayUpdate,
+) {
+    let settings = get_settings(ctx, guild_id).await;
+    let reserved = with_notifications(ctx, guild_id, |n| {
+        n.generation += 1;
+        n.try_send(Instant::now()).then_some(n.now_playing)
+    })
+    .await;
+    let previous = match reserved {
+        Some(Some(previous)) => previous,
+        _ => return,
+    };
+
+    if let Some((prev_channel_id, message_id)) = previous {
+        if settings.quiet_mode && prev_channel_id == channel_id {
+            let fmt = update.format().await;
+            let edited = channel_id
+                .edit_message(&ctx.http, message_id, |m| {
+                    m.embed(|e| {
+                        fmt(e);
+                        e
+                    })
+                })
+                .await;
+            if edited.is_ok() {
+                return;
+            }
+        } else if settings.clean_now_playing {
+            let _ = prev_channel_id.delete_message(&ctx.http, message_id).await;
+        }
+    }
+
+    // Previous message was deleted, is in another channel, or quiet mode is off
+    let fmt = update.format().await;
+    let sent = channel_id
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                fmt(e);
+                e
+            })
+        })
+        .await;
+    match sent {
+        Ok(message) => {
+            with_notifications(ctx, guild_id, |n| {
+                n.now_playing = Some((channel_id, message.id))
+            })
+            .await;
+        }
+        Err(e) => log_error(guild_id, Err(InternalError::SendMessage(e))),
+    }
+}
+
+/// Announce that the queue ended once no other track starts or ends for a while
+///
+/// Only called for tracks that ended by themselves, so stopping the queue is not announced
+async fn announce_queue_end(
+    ctx: &serenity::Context,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    call: Arc<Mutex<songbird::Call>>,
+) {
+    let generation = match with_notifications(ctx, guild_id, |n| {
+        n.generation += 1;
+        n.generation
+    })
+    .await
+    {
+        Some(g) => g,
+        None => return,
+    };
+
+    let ctx = ctx.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(QUEUE_END_DELAY).await;
+        if !call.lock().await.queue().is_empty() {
+            return;
+        }
+        let send = with_notifications(&ctx, guild_id, |n| {
+            n.generation == generation && n.try_send(Instant::now())
+        })
+        .await;
+        if send == Some(true) {
+            let result = SendMessage::Normal("Queue ended")
+                .send_msg_http(&ctx.http, channel_id)
+                .await;
+            log_error(guild_id, result.map_err(InternalError::SendMessage));
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit() {
+        let mut notifications = GuildNotifications::default();
+        let start = Instant::now();
+        for i in 0..RATE_LIMIT_COUNT {
+            assert!(notifications.try_send(start + Duration::from_secs(i as u64)));
+        }
+        assert!(!notifications.try_send(start + Duration::from_secs(9)));
+
+        // The first message is outside of the window
+        assert!(notifications.try_send(start + RATE_LIMIT_WINDOW));
+        assert!(!notifications.try_send(start + RATE_LIMIT_WINDOW));
+    }
+}
@@ -0,0 +1,521 @@
+use std::time::Duration;
+
+use futures::future::join_all;
+use poise::serenity_prelude as serenity;
+use serenity::builder::CreateEmbed;
+use serenity::{
+    ButtonStyle, CollectComponentInteraction, CreateComponents, InteractionResponseType,
+    MessageComponentInteraction, MessageId,
+};
+use songbird::input::Metadata;
+
+use super::error::MusicError;
+use super::message::{
+    format_album, format_album_details, format_album_results, format_artist_page,
+};
+use super::queue::{add_tracks, Query};
+use super::youtube::music::{
+    yt_music_album, yt_music_album_results, yt_music_artist, Album, AlbumResult, Artist, MusicTrack,
+};
+use crate::PoiseContext;
+
+/// Components are removed after no one has used them for this long
+const INACTIVITY_TIMEOUT: Duration = Duration::from_secs(120);
+/// Discord limits select menus to 25 options
+const MAX_OPTIONS: usize = 25;
+/// Select menus for the tracks of an album, Discord allows 5 rows and one holds the buttons
+const MAX_TRACK_MENUS: usize = 4;
+/// Discord limits option labels and descriptions to 100 characters
+const MAX_OPTION_LENGTH: usize = 100;
+/// Number of top songs, albums and singles shown for an artist
+const MAX_ARTIST_ITEMS: usize = 10;
+
+/// Show albums matching a search, then queue all or some tracks of the chosen one
+pub async fn choose_album(ctx: PoiseContext<'_>, query: String) -> Result<(), MusicError> {
+    let results = yt_music_album_results(query).await?;
+
+    // Track counts are only known once albums are fetched
+    let albums: Vec<_> = join_all(results.into_iter().map(|result| async move {
+        let album = yt_music_album(result.browse_id.clone()?).await.ok()?;
+        Some((result, album))
+    }))
+    .await
+    .into_iter()
+    .flatten()
+    .collect();
+    if albums.is_empty() {
+        return Err(MusicError::NoResults);
+    }
+
+    // Nothing to choose from if there is a single album
+    let mut selected = if albums.len() == 1 { Some(0) } else { None };
+    let ids = AlbumIds::new(ctx.id());
+    let reply = ctx
+        .send(|m| {
+            m.embed(|e| {
+                album_embed(&albums, selected)(e);
+                e
+            })
+            .components(|c| ids.build(c, &albums, selected))
+        })
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?;
+    let message_id = reply
+        .message()
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?
+        .id;
+
+    let mut chosen = None;
+    while let Some(interaction) = next_interaction(ctx, message_id, &ids.prefix).await {
+        let data = &interaction.data;
+        let action = ids.action(&data.custom_id, &data.values);
+        if let AlbumAction::Select(i) = action {
+            selected = Some(i).filter(|i| *i < albums.len());
+        } else if let AlbumAction::Back = action {
+            selected = None;
+        }
+        let done = selected.is_some()
+            && matches!(action, AlbumAction::QueueAll | AlbumAction::QueueTracks(_));
+
+        let _ = interaction
+            .create_interaction_response(ctx, |r| {
+                r.kind(InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|d| {
+                        d.embed(|e| {
+                            album_embed(&albums, selected)(e);
+                            e
+                        });
+                        if done {
+                            d.components(|c| c)
+                        } else {
+                            d.components(|c| ids.build(c, &albums, selected))
+                        }
+                    })
+            })
+            .await;
+
+        if done {
+            chosen = selected.map(|i| (i, action));
+            break;
+        }
+    }
+
+    let (i, action) = match chosen {
+        Some(c) => c,
+        None => {
+            // Remove components once they expire
+            let _ = reply.edit(ctx, |m| m.components(|c| c)).await;
+            return Ok(());
+        }
+    };
+    let album = &albums[i].1;
+    let tracks = match action {
+        AlbumAction::QueueTracks(indices) => album.track_metadata(Some(&indices)),
+        _ => album.track_metadata(None),
+    };
+    queue_tracks(ctx, tracks).await
+}
+
+fn album_embed<'a>(
+    albums: &'a [(AlbumResult, Album)],
+    selected: Option<usize>,
+) -> Box<dyn FnOnce(&mut CreateEmbed) + Send + Sync + 'a> {
+    match selected {
+        Some(i) => {
+            let format = format_album(&albums[i].0, &albums[i].1);
+            let playable = playable_tracks(&albums[i].1).count();
+            Box::new(move |e| {
+                format(e);
+                if playable > MAX_OPTIONS * MAX_TRACK_MENUS {
+                    e.footer(|f| {
+                        f.text(format!(
+                            "Only the first {} tracks can be selected, queue the album for the rest",
+                            MAX_OPTIONS * MAX_TRACK_MENUS
+                        ))
+                    });
+                }
+            })
+        }
+        None => format_album_results(albums),
+    }
+}
+
+/// Tracks of an album with their index, leaving out those that can't be played
+fn playable_tracks(album: &Album) -> impl Iterator<Item = (usize, &MusicTrack)> {
+    album
+        .tracks
+        .iter()
+        .enumerate()
+        .filter(|(_, t)| t.video_id.is_some())
+}
+
+/// Show an artist's top songs, albums and singles, queueing any of them when chosen
+pub async fn show_artist(ctx: PoiseContext<'_>, query: String) -> Result<(), MusicError> {
+    let artist = yt_music_artist(query).await?;
+
+    let ids = ArtistIds::new(ctx.id());
+    let reply = ctx
+        .send(|m| {
+            m.embed(|e| {
+                format_artist_page(&artist, MAX_ARTIST_ITEMS)(e);
+                e
+            })
+            .components(|c| ids.build(c, &artist))
+        })
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?;
+    let message_id = reply
+        .message()
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?
+        .id;
+
+    let result = async {
+        while let Some(interaction) = next_interaction(ctx, message_id, &ids.prefix).await {
+            let _ = interaction.defer(ctx).await;
+            let data = &interaction.data;
+            let tracks = match ids.action(&data.custom_id, &data.values) {
+                Some(action) => artist_tracks(&artist, action).await?,
+                None => continue,
+            };
+            queue_tracks(ctx, tracks).await?;
+        }
+        Ok(())
+    }
+    .await;
+
+    // Remove components once they expire
+    let _ = reply.edit(ctx, |m| m.components(|c| c)).await;
+
+    result
+}
+
+/// Tracks to queue for a choice on an artist's page
+async fn artist_tracks(artist: &Artist, action: ArtistAction) -> Result<Vec<Metadata>, MusicError> {
+    let songs = artist.songs().iter().take(MAX_ARTIST_ITEMS);
+    let album = match action {
+        ArtistAction::TopSongs => {
+            return Ok(songs.filter_map(|s| s.metadata(None)).collect());
+        }
+        ArtistAction::Song(i) => {
+            return Ok(songs
+                .skip(i)
+                .take(1)
+                .filter_map(|s| s.metadata(None))
+                .collect());
+        }
+        ArtistAction::Album(i) => artist.albums().get(i),
+        ArtistAction::Single(i) => artist.singles().get(i),
+    };
+
+    match album.and_then(|a| a.browse_id.clone()) {
+        Some(browse_id) => Ok(yt_music_album(browse_id).await?.track_metadata(None)),
+        None => Ok(vec![]),
+    }
+}
+
+async fn queue_tracks(ctx: PoiseContext<'_>, tracks: Vec<Metadata>) -> Result<(), MusicError> {
+    if tracks.is_empty() {
+        return Err(MusicError::NoResults);
+    }
+    let num_tracks = tracks.len();
+    let queries = futures::stream::iter(tracks.into_iter().map(Query::Known));
+    add_tracks(ctx, queries, num_tracks).await
+}
+
+/// Wait for the author of the command to use a component of the message
+async fn next_interaction(
+    ctx: PoiseContext<'_>,
+    message_id: MessageId,
+    prefix: &str,
+) -> Option<std::sync::Arc<MessageComponentInteraction>> {
+    let prefix = prefix.to_owned();
+    CollectComponentInteraction::new(ctx)
+        .message_id(message_id)
+        .author_id(ctx.author().id)
+        .filter(move |ci| ci.data.custom_id.starts_with(&prefix))
+        .timeout(INACTIVITY_TIMEOUT)
+        .await
+}
+
+/// First value of a select menu as an index
+fn selected_index(values: &[String]) -> Option<usize> {
+    values.first().and_then(|v| v.parse().ok())
+}
+
+/// Shorten text to fit in a select menu option
+fn option_text(text: &str) -> String {
+    if text.chars().count() <= MAX_OPTION_LENGTH {
+        return text.to_owned();
+    }
+    let mut text: String = text.chars().take(MAX_OPTION_LENGTH - 1).collect();
+    text.push('…');
+    text
+}
+
+enum AlbumAction {
+    Select(usize),
+    Back,
+    QueueAll,
+    QueueTracks(Vec<usize>),
+    None,
+}
+
+/// Custom IDs of the components attached to a single album message
+struct AlbumIds {
+    prefix: String,
+    select: String,
+    back: String,
+    queue_all: String,
+    tracks: String,
+}
+
+impl AlbumIds {
+    fn new(id: u64) -> Self {
+        let prefix = format!("album-{}-", id);
+        Self {
+            select: format!("{}select", prefix),
+            back: format!("{}back", prefix),
+            queue_all: format!("{}all", prefix),
+            tracks: format!("{}tracks", prefix),
+            prefix,
+        }
+    }
+
+    fn action(&self, id: &str, values: &[String]) -> AlbumAction {
+        if id == self.select {
+            selected_index(values).map_or(AlbumAction::None, AlbumAction::Select)
+        } else if id == self.back {
+            AlbumAction::Back
+        } else if id == self.queue_all {
+            AlbumAction::QueueAll
+        } else if id.starts_with(&self.tracks) {
+            AlbumAction::QueueTracks(values.iter().filter_map(|v| v.parse().ok()).collect())
+        } else {
+            AlbumAction::None
+        }
+    }
+
+    fn build<'a>(
+        &self,
+        c: &'a mut CreateComponents,
+        albums: &[(AlbumResult, Album)],
+        selected: Option<usize>,
+    ) -> &'a mut CreateComponents {
+        let album = match selected {
+            Some(i) => &albums[i].1,
+            None => {
+                return c.create_action_row(|ar| {
+                    ar.create_select_menu(|m| {
+                        m.custom_id(&self.select)
+                            .placeholder("Choose an album")
+                            .options(|o| {
+                                for (i, (result, album)) in albums.iter().enumerate() {
+                                    o.create_option(|opt| {
+                                        opt.label(option_text(&album.title))
+                                            .description(option_text(&format_album_details(
+                                                result, album,
+                                            )))
+                                            .value(i)
+                                    });
+                                }
+                                o
+                            })
+                    })
+                });
+            }
+        };
+
+        c.create_action_row(|ar| {
+            ar.create_button(|b| {
+                b.style(ButtonStyle::Primary)
+                    .label("Queue album")
+                    .custom_id(&self.queue_all)
+            });
+            if albums.len() > 1 {
+                ar.create_button(|b| {
+                    b.style(ButtonStyle::Secondary)
+                        .label("Back")
+                        .custom_id(&self.back)
+                });
+            }
+            ar
+        });
+
+        // Longer albums are split across several menus
+        let tracks: Vec<_> = playable_tracks(album)
+            .take(MAX_OPTIONS * MAX_TRACK_MENUS)
+            .collect();
+        let split = tracks.len() > MAX_OPTIONS;
+        for (n, chunk) in tracks.chunks(MAX_OPTIONS).enumerate() {
+            let placeholder = match (chunk.first(), chunk.last()) {
+                (Some((first, _)), Some((last, _))) if split => {
+                    format!("Queue selected tracks {}–{}", first + 1, last + 1)
+                }
+                _ => "Queue selected tracks".to_owned(),
+            };
+            c.create_action_row(|ar| {
+                ar.create_select_menu(|m| {
+                    m.custom_id(format!("{}-{}", self.tracks, n))
+                        .placeholder(placeholder)
+                        .min_values(1)
+                        .max_values(chunk.len() as u64)
+                        .options(|o| {
+                            for (i, track) in chunk {
+                                o.create_option(|opt| {
+                                    opt.label(option_text(&format!("{}. {}", i + 1, track.title)))
+                                        .value(i)
+                                });
+                            }
+                            o
+                        })
+                })
+            });
+        }
+        c
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ArtistAction {
+    TopSongs,
+    Song(usize),
+    Album(usize),
+    Single(usize),
+}
+
+/// Custom IDs of the components attached to a single artist message
+struct ArtistIds {
+    prefix: String,
+    top_songs: String,
+    song: String,
+    album: String,
+    single: String,
+}
+
+impl ArtistIds {
+    fn new(id: u64) -> Self {
+        let prefix = format!("artist-{}-", id);
+        Self {
+            top_songs: format!("{}top", prefix),
+            song: format!("{}song", prefix),
+            album: format!("{}album", prefix),
+            single: format!("{}single", prefix),
+            prefix,
+        }
+    }
+
+    fn action(&self, id: &str, values: &[String]) -> Option<ArtistAction> {
+        if id == self.top_songs {
+            return Some(ArtistAction::TopSongs);
+        }
+        let i = selected_index(values)?;
+        if id == self.song {
+            Some(ArtistAction::Song(i))
+        } else if id == self.album {
+            Some(ArtistAction::Album(i))
+        } else if id == self.single {
+            Some(ArtistAction::Single(i))
+        } else {
+            None
+        }
+    }
+
+    fn build<'a>(&self, c: &'a mut CreateComponents, artist: &Artist) -> &'a mut CreateComponents {
+        let songs: Vec<_> = artist
+            .songs()
+            .iter()
+            .take(MAX_ARTIST_ITEMS)
+            .map(|s| s.title.clone())
+            .collect();
+        if !songs.is_empty() {
+            c.create_action_row(|ar| {
+                ar.create_button(|b| {
+                    b.style(ButtonStyle::Primary)
+                        .label("Queue top songs")
+                        .custom_id(&self.top_songs)
+                })
+            });
+        }
+
+        let album_titles = |albums: &[AlbumResult]| -> Vec<String> {
+            albums
+                .iter()
+                .take(MAX_ARTIST_ITEMS)
+                .map(|a| a.title.clone())
+                .collect()
+        };
+        for (id, placeholder, titles) in [
+            (&self.song, "Queue a song", songs),
+            (&self.album, "Queue an album", album_titles(artist.albums())),
+            (
+                &self.single,
+                "Queue a single",
+                album_titles(artist.singles()),
+            ),
+        ] {
+            if titles.is_empty() {
+                continue;
+            }
+            c.create_action_row(|ar| {
+                ar.create_select_menu(|m| {
+                    m.custom_id(id).placeholder(placeholder).options(|o| {
+                        for (i, title) in titles.iter().enumerate() {
+                            o.create_option(|opt| {
+                                opt.label(option_text(&format!("{}. {}", i + 1, title)))
+                                    .value(i)
+                            });
+                        }
+                        o
+                    })
+                })
+            });
+        }
+        c
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_artist_action() {
+        let ids = ArtistIds::new(1);
+        let values = ["2".to_owned()];
+        assert_eq!(
+            ids.action("artist-1-top", &[]),
+            Some(ArtistAction::TopSongs)
+        );
+        assert_eq!(
+            ids.action("artist-1-album", &values),
+            Some(ArtistAction::Album(2))
+        );
+        assert_eq!(ids.action("artist-1-song", &[]), None);
+        assert_eq!(ids.action("artist-2-single", &values), None);
+    }
+
+    #[test]
+    fn test_album_action() {
+        let ids = AlbumIds::new(1);
+        let values = ["30".to_owned(), "31".to_owned()];
+        assert!(matches!(
+            ids.action("album-1-tracks-1", &values),
+            AlbumAction::QueueTracks(v) if v == [30, 31]
+        ));
+        assert!(matches!(
+            ids.action("album-1-select", &values[..1]),
+            AlbumAction::Select(30)
+        ));
+        assert!(matches!(ids.action("album-2-all", &[]), AlbumAction::None));
+    }
+
+    #[test]
+    fn test_option_text() {
+        assert_eq!(option_text("short"), "short");
+        let long = "a".repeat(150);
+        assert_eq!(option_text(&long).chars().count(), MAX_OPTION_LENGTH);
+        assert!(option_text(&long).ends_with('…'));
+    }
+}
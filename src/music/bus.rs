@@ -0,0 +1,143 @@
+use std::future::Future;
+use std::sync::LazyLock;
+use std::time::Duration;
+
+use poise::serenity_prelude::{ChannelId, GuildId};
+use songbird::tracks::TrackHandle;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+/// Subscribers that fall this many events behind miss the oldest events
+const CAPACITY: usize = 256;
+
+static MUSIC_EVENTS: LazyLock<broadcast::Sender<MusicEvent>> =
+    LazyLock::new(|| broadcast::channel(CAPACITY).0);
+
+/// Changes of music playback, for announcements and other integrations
+#[derive(Clone, Debug)]
+pub enum MusicEvent {
+    TrackQueued {
+        guild_id: GuildId,
+        track: TrackHandle,
+        /// Length of the queue including the new track
+        queue_len: usize,
+    },
+    TrackStarted {
+        guild_id: GuildId,
+        track: TrackHandle,
+        queue_len: usize,
+    },
+    TrackPaused {
+        guild_id: GuildId,
+        track: TrackHandle,
+        /// Paused because no one is listening
+        auto: bool,
+    },
+    TrackResumed {
+        guild_id: GuildId,
+        track: TrackHandle,
+        /// Resumed because someone started listening again
+        auto: bool,
+    },
+    TrackSkipped {
+        guild_id: GuildId,
+        track: TrackHandle,
+    },
+    TrackRemoved {
+        guild_id: GuildId,
+        track: TrackHandle,
+    },
+    TrackMoved {
+        guild_id: GuildId,
+        track: TrackHandle,
+        /// Queue indices before and after moving
+        from: usize,
+        to: usize,
+    },
+    TrackEnded {
+        guild_id: GuildId,
+        track: TrackHandle,
+        play_time: Duration,
+        /// Played until the end instead of being stopped or skipped
+        finished: bool,
+    },
+    QueueCleared {
+        guild_id: GuildId,
+    },
+    VoiceJoined {
+        guild_id: GuildId,
+        channel_id: ChannelId,
+    },
+    VoiceLeft {
+        guild_id: GuildId,
+    },
+}
+
+impl MusicEvent {
+    pub fn guild_id(&self) -> GuildId {
+        match self {
+            Self::TrackQueued { guild_id, .. }
+            | Self::TrackStarted { guild_id, .. }
+            | Self::TrackPaused { guild_id, .. }
+            | Self::TrackResumed { guild_id, .. }
+            | Self::TrackSkipped { guild_id, .. }
+            | Self::TrackRemoved { guild_id, .. }
+            | Self::TrackMoved { guild_id, .. }
+            | Self::TrackEnded { guild_id, .. }
+            | Self::QueueCleared { guild_id }
+            | Self::VoiceJoined { guild_id, .. }
+            | Self::VoiceLeft { guild_id } => *guild_id,
+        }
+    }
+}
+
+/// Send an event to all subscribers
+pub fn publish(event: MusicEvent) {
+    // Sending only fails if there are no subscribers
+    let _ = MUSIC_EVENTS.send(event);
+}
+
+pub fn subscribe() -> broadcast::Receiver<MusicEvent> {
+    MUSIC_EVENTS.subscribe()
+}
+
+/// Handle every event published from now on in a new task
+pub fn spawn_subscriber<F, Fut>(name: &'static str, mut handler: F)
+where
+    F: FnMut(MusicEvent) -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    let mut events = subscribe();
+    tokio::spawn(async move {
+        loop {
+            match events.recv().await {
+                Ok(event) => handler(event).await,
+                Err(RecvError::Lagged(n)) => eprintln!("{} missed {} music events", name, n),
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_publish() {
+        let mut events = subscribe();
+        publish(MusicEvent::QueueCleared {
+            guild_id: GuildId(1),
+        });
+        publish(MusicEvent::VoiceLeft {
+            guild_id: GuildId(2),
+        });
+
+        // Other tests may publish events at the same time
+        let mut guilds = vec![];
+        while let Ok(event) = events.try_recv() {
+            guilds.push(event.guild_id());
+        }
+        assert!(guilds.contains(&GuildId(1)));
+        assert!(guilds.contains(&GuildId(2)));
+    }
+}
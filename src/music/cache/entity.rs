@@ -0,0 +1,19 @@
+use sea_orm::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "music_cache")]
+pub struct Model {
+    /// Kind of the cached value followed by a query or video ID, such as "loudness:<id>"
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub key: String,
+    /// JSON encoded value
+    #[sea_orm(column_type = "Text")]
+    pub value: String,
+    /// Unix timestamp after which the value is resolved again
+    pub expires_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
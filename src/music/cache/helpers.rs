@@ -0,0 +1,56 @@
+use sea_orm::sea_query::OnConflict;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+
+use super::entity;
+use crate::music::database;
+
+pub async fn create_table(db: &DatabaseConnection) -> anyhow::Result<()> {
+    database::create_table(db, entity::Entity).await?;
+    Ok(())
+}
+
+/// Cached JSON value of a key, unless it expired
+pub async fn get_value(
+    db: &DatabaseConnection,
+    key: &str,
+    now: i64,
+) -> anyhow::Result<Option<String>> {
+    let model = entity::Entity::find_by_id(key.to_owned())
+        .filter(entity::Column::ExpiresAt.gt(now))
+        .one(db)
+        .await?;
+    Ok(model.map(|m| m.value))
+}
+
+pub async fn set_value(
+    db: &DatabaseConnection,
+    key: &str,
+    value: String,
+    expires_at: i64,
+) -> anyhow::Result<()> {
+    let model = entity::ActiveModel {
+        key: Set(key.to_owned()),
+        value: Set(value),
+        expires_at: Set(expires_at),
+    };
+    entity::Entity::insert(model)
+        .on_conflict(
+            OnConflict::column(entity::Column::Key)
+                .update_columns([entity::Column::Value, entity::Column::ExpiresAt])
+                .to_owned(),
+        )
+        .exec(db)
+        .await?;
+
+    Ok(())
+}
+
+/// Delete all expired values, returns the number of deleted rows
+pub async fn delete_expired(db: &DatabaseConnection, now: i64) -> anyhow::Result<u64> {
+    let res = entity::Entity::delete_many()
+        .filter(entity::Column::ExpiresAt.lte(now))
+        .exec(db)
+        .await?;
+    Ok(res.rows_affected)
+}
@@ -0,0 +1,198 @@
+mod entity;
+mod helpers;
+
+use std::time::Duration;
+
+use sea_orm::DatabaseConnection;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use songbird::input::Metadata;
+use time::OffsetDateTime;
+use url::Url;
+
+use super::database;
+
+/// Search results change as new videos are uploaded
+pub const SEARCH_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+pub const METADATA_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+pub const LOUDNESS_TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+/// SponsorBlock segments are submitted over time, so they are refreshed more often
+pub const SKIPS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Create the cache table and drop expired values
+pub async fn init(db: &DatabaseConnection) -> anyhow::Result<()> {
+    helpers::create_table(db).await?;
+    helpers::delete_expired(db, now()).await?;
+
+    Ok(())
+}
+
+/// Cached value of a key, errors are treated as a cache miss
+///
+/// The cache is disabled until the database is connected
+pub async fn get<T: DeserializeOwned>(key: &str) -> Option<T> {
+    let db = database::connection().ok()?;
+    let value = match helpers::get_value(db, key, now()).await {
+        Ok(v) => v?,
+        Err(e) => {
+            eprintln!("Error reading cache: {e}");
+            return None;
+        }
+    };
+    serde_json::from_str(&value).ok()
+}
+
+/// Cache a value for `ttl`
+pub async fn put<T: Serialize>(key: &str, value: &T, ttl: Duration) {
+    let db = match database::connection() {
+        Ok(db) => db,
+        Err(_) => return,
+    };
+    let value = match serde_json::to_string(value) {
+        Ok(v) => v,
+        Err(_) => return,
+    };
+    let expires_at = now() + ttl.as_secs() as i64;
+    if let Err(e) = helpers::set_value(db, key, value, expires_at).await {
+        eprintln!("Error writing cache: {e}");
+    }
+}
+
+fn now() -> i64 {
+    OffsetDateTime::now_utc().unix_timestamp()
+}
+
+/// Key of a search, ignoring case and surrounding whitespace
+pub fn search_key(kind: &str, query: &str) -> String {
+    format!("search:{}:{}", kind, query.trim().to_lowercase())
+}
+
+/// Key of a value of a video, so different URLs of the same YouTube video share it
+pub fn video_key(kind: &str, url: &str) -> String {
+    match youtube_id(url) {
+        Some(id) => format!("{}:youtube:{}", kind, id),
+        None => format!("{}:{}", kind, url),
+    }
+}
+
+/// ID of a YouTube video URL
+pub fn youtube_id(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str()?;
+    if host == "youtu.be" {
+        return url.path_segments()?.next().map(str::to_owned);
+    }
+    if host == "youtube.com" || host.ends_with(".youtube.com") {
+        return url
+            .query_pairs()
+            .find(|(k, _)| k == "v")
+            .map(|(_, v)| v.into_owned());
+    }
+    None
+}
+
+/// Track metadata that can be stored in the cache
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CachedMetadata {
+    pub track: Option<String>,
+    pub artist: Option<String>,
+    pub date: Option<String>,
+    pub channels: Option<u8>,
+    pub channel: Option<String>,
+    pub start_time: Option<Duration>,
+    pub duration: Option<Duration>,
+    pub sample_rate: Option<u32>,
+    pub source_url: Option<String>,
+    pub title: Option<String>,
+    pub thumbnail: Option<String>,
+}
+
+impl From<&Metadata> for CachedMetadata {
+    fn from(m: &Metadata) -> Self {
+        Self {
+            track: m.track.clone(),
+            artist: m.artist.clone(),
+            date: m.date.clone(),
+            channels: m.channels,
+            channel: m.channel.clone(),
+            start_time: m.start_time,
+            duration: m.duration,
+            sample_rate: m.sample_rate,
+            source_url: m.source_url.clone(),
+            title: m.title.clone(),
+            thumbnail: m.thumbnail.clone(),
+        }
+    }
+}
+
+impl From<CachedMetadata> for Metadata {
+    fn from(m: CachedMetadata) -> Self {
+        Self {
+            track: m.track,
+            artist: m.artist,
+            date: m.date,
+            channels: m.channels,
+            channel: m.channel,
+            start_time: m.start_time,
+            duration: m.duration,
+            sample_rate: m.sample_rate,
+            source_url: m.source_url,
+            title: m.title,
+            thumbnail: m.thumbnail,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_video_key() {
+        let key = "loudness:youtube:5gvfp-haKXc";
+        assert_eq!(
+            video_key("loudness", "https://www.youtube.com/watch?v=5gvfp-haKXc"),
+            key
+        );
+        assert_eq!(
+            video_key(
+                "loudness",
+                "https://music.youtube.com/watch?v=5gvfp-haKXc&list=x"
+            ),
+            key
+        );
+        assert_eq!(video_key("loudness", "https://youtu.be/5gvfp-haKXc"), key);
+        assert_eq!(
+            video_key("loudness", "https://soundcloud.com/a/b"),
+            "loudness:https://soundcloud.com/a/b"
+        );
+    }
+
+    #[test]
+    fn test_search_key() {
+        assert_eq!(
+            search_key("song", "  Dreamcatcher Deja Vu "),
+            "search:song:dreamcatcher deja vu"
+        );
+    }
+
+    #[test]
+    fn test_metadata_round_trip() {
+        let metadata = Metadata {
+            title: Some("title".to_owned()),
+            duration: Some(Duration::from_millis(1500)),
+            source_url: Some("https://youtu.be/x".to_owned()),
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&CachedMetadata::from(&metadata)).unwrap();
+        let cached: CachedMetadata = serde_json::from_str(&json).unwrap();
+        assert_eq!(Metadata::from(cached), metadata);
+    }
+
+    #[tokio::test]
+    async fn test_disabled_cache() {
+        // Without a database every lookup is a miss
+        put("search:song:test", &"id".to_owned(), SEARCH_TTL).await;
+        assert_eq!(get::<String>("search:song:test").await, None);
+    }
+}
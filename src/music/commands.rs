@@ -1,17 +1,47 @@
-use if_chain::if_chain;
-use songbird::tracks::{PlayMode, TrackHandle};
+use std::time::Duration;
 
+use poise::serenity_prelude::{
+    Attachment, AttachmentType, ButtonStyle, Channel, ChannelType, CollectComponentInteraction,
+    User,
+};
+use songbird::tracks::TrackHandle;
+use time::OffsetDateTime;
+
+use super::browse::{choose_album, show_artist};
+use super::bus::{publish, MusicEvent};
 use super::error::MusicError;
-use super::message::PlayUpdate;
-use super::queue::{add_tracks, remove_track, Query};
-use super::voice::{CanGetVoice, CanJoinVoice};
-use super::youtube::music::{yt_music_album_search, yt_music_song_search};
-use super::youtube::playlist::add_youtube_playlist;
+use super::export::{ExportFormat, ExportedQueue, MAX_IMPORT_SIZE, MAX_IMPORT_TRACKS};
+use super::list::{list_queue, track_index};
+use super::message::{format_duration, format_stats, format_track_summary, PlayUpdate};
+use super::playlist::{add_list_link, add_playlist, ListLink};
+use super::queue::{
+    add_tracks, add_tracks_at, clear_queue, dedupe_queue, pause_track, play_previous, remove_track,
+    replay_track, resume_track, skip_to, skip_track, Placement, Query,
+};
+use super::schedule::{add_schedule, cancel_schedule, guild_schedules, parse_time_of_day};
+use super::scrobble::{link_account, unlink_account, user_accounts, ScrobbleService};
+use super::settings::{
+    format_utc_offset, get_settings, parse_utc_offset, save_settings, timeout_from_minutes,
+    MusicChannelMode,
+};
+use super::sleep::{cancel_sleep_timer, parse_duration, set_sleep_timer};
+use super::stats::{play_stats, StatsPeriod};
+use super::voice::{get_channel_id, join_channel, CanGetVoice, CanJoinVoice};
+use super::youtube::music::yt_music_song_search;
 use crate::message::{CustomSendMessage, SendMessage, SendableMessage};
 use crate::{PoiseContext, PoiseError};
 
+/// How long to wait for a Last.fm account to be authorized
+const SCROBBLE_AUTH_TIMEOUT: Duration = Duration::from_secs(300);
+
 /// Play a song via YouTube Music or URL, if no argument is given, resume the paused track
-#[poise::command(slash_command, prefix_command, guild_only, broadcast_typing)]
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    broadcast_typing
+)]
 pub async fn play(
     ctx: PoiseContext<'_>,
     #[rest]
@@ -20,41 +50,89 @@ pub async fn play(
     arg: Option<String>,
 ) -> Result<(), PoiseError> {
     if let Some(arg) = arg {
-        ctx.defer_or_broadcast().await?;
-        if let Ok(url) = url::Url::parse(&arg) {
-            // Try parsing url as youtube playlist
-            if add_youtube_playlist(ctx, url.as_str()).await.is_ok() {
-                return Ok(());
-            }
-            // Try adding url as a track
-            let query = Query::Url(url.to_string());
-            add_tracks(ctx, futures::stream::once(async { query }), 1).await?;
-        } else {
-            // Otherwise search YouTube Music
-            search_add_song(&ctx, arg).await?;
-        }
+        add_query(ctx, arg, Placement::End).await?;
     } else {
         // If no arguments, resume current track
         let handler_lock = ctx.get_voice().await?;
-        if_chain! {
-            let mut handler = handler_lock.lock().await;
-            let queue = handler.queue();
-            if let Some(track) = queue.current();
-            if let Ok(info) = track.get_info().await;
-            if info.playing == PlayMode::Pause;
-            then {
-                let _ = track.play();
-                handler.remove_all_global_events();
-            } else {
-                return Err(MusicError::NoPausedTrack.into());
-            }
+        let mut handler = handler_lock.lock().await;
+        resume_track(&mut handler).await?;
+    }
+    Ok(())
+}
+
+/// Play a song, album or playlist after the current track
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    broadcast_typing
+)]
+pub async fn playnext(
+    ctx: PoiseContext<'_>,
+    #[rest]
+    #[description = "Song title or URL"]
+    #[rename = "song_or_url"]
+    arg: String,
+) -> Result<(), PoiseError> {
+    add_query(ctx, arg, Placement::Next).await
+}
+
+/// Play a song, album or playlist right away, resuming the current track afterwards
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    broadcast_typing
+)]
+pub async fn playnow(
+    ctx: PoiseContext<'_>,
+    #[rest]
+    #[description = "Song title or URL"]
+    #[rename = "song_or_url"]
+    arg: String,
+) -> Result<(), PoiseError> {
+    add_query(ctx, arg, Placement::Now).await
+}
+
+/// Add a URL, playlist or YouTube Music search result at the given placement
+async fn add_query(
+    ctx: PoiseContext<'_>,
+    arg: String,
+    placement: Placement,
+) -> Result<(), PoiseError> {
+    ctx.defer_or_broadcast().await?;
+    if let Ok(url) = url::Url::parse(&arg) {
+        // Ask what to queue from videos opened in a playlist or mix
+        if let Some(link) = ListLink::parse(&url) {
+            return Ok(add_list_link(ctx, &link, placement).await?);
         }
+        // Try parsing url as a playlist
+        match add_playlist(ctx, url.as_str(), placement).await {
+            Err(MusicError::BadPlaylist) => (),
+            res => return Ok(res?),
+        }
+        // Try adding url as a track
+        let query = Query::Url(url.to_string());
+        add_tracks_at(ctx, futures::stream::once(async { query }), 1, placement).await?;
+    } else {
+        // Otherwise search YouTube Music
+        let url = yt_music_song_search(arg).await?;
+        let query = Query::Url(url);
+        add_tracks_at(ctx, futures::stream::once(async { query }), 1, placement).await?;
     }
     Ok(())
 }
 
 /// Play a song via YouTube Music
-#[poise::command(slash_command, prefix_command, guild_only, broadcast_typing)]
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    broadcast_typing
+)]
 pub async fn song(
     ctx: PoiseContext<'_>,
     #[rest]
@@ -62,14 +140,10 @@ pub async fn song(
     #[rename = "song"]
     arg: String,
 ) -> Result<(), PoiseError> {
-    search_add_song(&ctx, arg).await
-}
-
-async fn search_add_song(ctx: &PoiseContext<'_>, song: String) -> Result<(), PoiseError> {
     ctx.defer_or_broadcast().await?;
-    let url = yt_music_song_search(song).await?;
-    let query = Query::Url(url.to_string());
-    add_tracks(*ctx, futures::stream::once(async { query }), 1).await?;
+    let url = yt_music_song_search(arg).await?;
+    let query = Query::Url(url);
+    add_tracks(ctx, futures::stream::once(async { query }), 1).await?;
 
     Ok(())
 }
@@ -79,6 +153,7 @@ async fn search_add_song(ctx: &PoiseContext<'_>, song: String) -> Result<(), Poi
     slash_command,
     prefix_command,
     guild_only,
+    category = "Music",
     broadcast_typing,
     aliases("yt", "youtube")
 )]
@@ -103,7 +178,13 @@ pub async fn video(
 }
 
 /// Play an album via YouTube Music
-#[poise::command(slash_command, prefix_command, guild_only, broadcast_typing)]
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    broadcast_typing
+)]
 pub async fn album(
     ctx: PoiseContext<'_>,
     #[rest]
@@ -111,28 +192,41 @@ pub async fn album(
     #[rename = "album"]
     arg: String,
 ) -> Result<(), PoiseError> {
-    // Otherwise search YouTube Music
     ctx.defer_or_broadcast().await?;
-    let url = yt_music_album_search(arg).await?;
-    add_youtube_playlist(ctx, url.as_str()).await?;
+    choose_album(ctx, arg).await?;
+
+    Ok(())
+}
+
+/// Show an artist's top songs, albums and singles via YouTube Music
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    broadcast_typing
+)]
+pub async fn artist(
+    ctx: PoiseContext<'_>,
+    #[rest]
+    #[description = "Artist name"]
+    #[rename = "artist"]
+    arg: String,
+) -> Result<(), PoiseError> {
+    ctx.defer_or_broadcast().await?;
+    show_artist(ctx, arg).await?;
 
     Ok(())
 }
 
 /// Pause the currently playing track
-#[poise::command(slash_command, prefix_command, guild_only)]
+#[poise::command(slash_command, prefix_command, guild_only, category = "Music")]
 pub async fn pause(ctx: PoiseContext<'_>) -> Result<(), PoiseError> {
+    let guild_id = ctx.guild_id().ok_or(MusicError::GetVoice)?;
     let handler_lock = ctx.join_voice().await?;
     let handler = handler_lock.lock().await;
-    let queue = handler.queue();
-    let track = queue.current().ok_or(MusicError::NoPlayingTrack)?;
-    let info = track
-        .get_info()
-        .await
-        .map_err(|e| MusicError::Internal(e.into()))?;
-    if info.playing == PlayMode::Play {
-        track.pause()?;
-        CustomSendMessage::Custom(PlayUpdate::Pause(track.clone()).format().await)
+    if let Some(track) = pause_track(guild_id, &handler).await? {
+        CustomSendMessage::Custom(PlayUpdate::Pause(track).format().await)
             .send_msg(ctx)
             .await;
     }
@@ -141,27 +235,108 @@ pub async fn pause(ctx: PoiseContext<'_>) -> Result<(), PoiseError> {
 }
 
 /// Skip the currently playing track
-#[poise::command(slash_command, prefix_command, guild_only)]
+#[poise::command(slash_command, prefix_command, guild_only, category = "Music")]
 pub async fn skip(ctx: PoiseContext<'_>) -> Result<(), PoiseError> {
+    let guild_id = ctx.guild_id().ok_or(MusicError::GetVoice)?;
     let handler_lock = ctx.get_voice().await?;
-    let handler = handler_lock.lock().await;
-    let track = handler
-        .queue()
-        .dequeue(0)
-        .ok_or(MusicError::NoPlayingTrack)?;
-    let _ = track.stop();
-    CustomSendMessage::Custom(PlayUpdate::Skip(track.clone()).format().await)
+    let track = skip_track(guild_id, &*handler_lock.lock().await)?;
+    CustomSendMessage::Custom(PlayUpdate::Skip(track).format().await)
+        .send_msg(ctx)
+        .await;
+
+    Ok(())
+}
+
+/// Skip to a track in the queue, discarding all tracks before it
+#[poise::command(slash_command, prefix_command, guild_only, category = "Music")]
+pub async fn skipto(
+    ctx: PoiseContext<'_>,
+    #[description = "Track number to skip to"] track: usize,
+) -> Result<(), PoiseError> {
+    let idx = track_index(track).ok_or(MusicError::BadIndex)?;
+    let skipped = skip_to(ctx, idx).await?;
+    send_track_summary(ctx, "Skipped", skipped).await;
+
+    Ok(())
+}
+
+/// Remove all upcoming tracks, keeping the current track playing
+#[poise::command(slash_command, prefix_command, guild_only, category = "Music")]
+pub async fn clear(ctx: PoiseContext<'_>) -> Result<(), PoiseError> {
+    let removed = clear_queue(ctx).await?;
+    publish(MusicEvent::QueueCleared {
+        guild_id: ctx.guild_id().ok_or(MusicError::GetVoice)?,
+    });
+    send_track_summary(ctx, "Cleared", removed).await;
+
+    Ok(())
+}
+
+/// Remove duplicate tracks from the queue
+#[poise::command(slash_command, prefix_command, guild_only, category = "Music")]
+pub async fn dedupe(ctx: PoiseContext<'_>) -> Result<(), PoiseError> {
+    let removed = dedupe_queue(ctx).await?;
+    send_track_summary(ctx, "Removed duplicate", removed).await;
+
+    Ok(())
+}
+
+async fn send_track_summary(ctx: PoiseContext<'_>, action: &str, tracks: Vec<TrackHandle>) {
+    const MAX_NUM_DISPLAYED_TRACKS: usize = 10;
+
+    let title = match tracks.len() {
+        0 => {
+            SendMessage::Normal("No tracks changed").send_msg(ctx).await;
+            return;
+        }
+        1 => format!("{} 1 track", action),
+        n => format!("{} {} tracks", action, n),
+    };
+    let num_tracks = tracks.len();
+    let displayed = tracks
+        .into_iter()
+        .take(MAX_NUM_DISPLAYED_TRACKS)
+        .collect::<Vec<_>>();
+    CustomSendMessage::Custom(format_track_summary(
+        title,
+        displayed.into_iter(),
+        num_tracks,
+    ))
+    .send_msg(ctx)
+    .await;
+}
+
+/// Play the previously finished track, then continue with the current track
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    aliases("prev", "back")
+)]
+pub async fn previous(ctx: PoiseContext<'_>) -> Result<(), PoiseError> {
+    ctx.defer_or_broadcast().await?;
+    let track = play_previous(ctx).await?;
+    CustomSendMessage::Custom(PlayUpdate::Previous(track).format().await)
+        .send_msg(ctx)
+        .await;
+
+    Ok(())
+}
+
+/// Restart the currently playing track from the beginning
+#[poise::command(slash_command, prefix_command, guild_only, category = "Music")]
+pub async fn replay(ctx: PoiseContext<'_>) -> Result<(), PoiseError> {
+    let track = replay_track(ctx).await?;
+    CustomSendMessage::Custom(PlayUpdate::Replay(track).format().await)
         .send_msg(ctx)
         .await;
-    if let Some(next) = handler.queue().current() {
-        let _ = next.play();
-    }
 
     Ok(())
 }
 
 /// Stop playing and clear queue
-#[poise::command(slash_command, prefix_command, guild_only)]
+#[poise::command(slash_command, prefix_command, guild_only, category = "Music")]
 pub async fn stop(ctx: PoiseContext<'_>) -> Result<(), PoiseError> {
     let handler_lock = ctx.get_voice().await?;
     let handler = handler_lock.lock().await;
@@ -170,6 +345,9 @@ pub async fn stop(ctx: PoiseContext<'_>) -> Result<(), PoiseError> {
         let _ = track.stop();
     }
     queue.stop();
+    publish(MusicEvent::QueueCleared {
+        guild_id: ctx.guild_id().ok_or(MusicError::GetVoice)?,
+    });
     CustomSendMessage::Custom(PlayUpdate::Stop.format().await)
         .send_msg(ctx)
         .await;
@@ -178,75 +356,141 @@ pub async fn stop(ctx: PoiseContext<'_>) -> Result<(), PoiseError> {
 }
 
 /// List tracks in queue
-#[poise::command(slash_command, prefix_command, guild_only, aliases("ls", "queue"))]
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    aliases("ls")
+)]
 pub async fn list(
     ctx: PoiseContext<'_>,
-    #[rename = "index"]
-    #[description = "start listing from this index in queue, use \"end\" to list end of queue"]
-    arg: Option<String>,
+    #[description = "Page of the queue to show"] page: Option<usize>,
 ) -> Result<(), PoiseError> {
-    const NUM_TRACKS: usize = 25;
-
-    let handler_lock = ctx.get_voice().await?;
-    let handler = handler_lock.lock().await;
-    let queue = handler.queue().current_queue();
+    list_page(ctx, page).await
+}
 
-    // Parse arguments
-    let arg = match arg {
-        Some(a) => a,
-        None => "1".to_owned(),
-    };
-    let start = match arg.to_lowercase().as_str() {
-        "max" => None,
-        "end" => None,
-        s => Some(s.parse::<usize>().map_err(|_| MusicError::BadIndex)?),
+async fn list_page(ctx: PoiseContext<'_>, page: Option<usize>) -> Result<(), PoiseError> {
+    let queue = {
+        let handler_lock = ctx.get_voice().await?;
+        let handler = handler_lock.lock().await;
+        handler.queue().clone()
     };
 
-    // Compute start index
-    let start = if let Some(i) = start {
-        i
-    } else {
-        std::cmp::max(1, queue.len().saturating_sub(NUM_TRACKS))
-    };
-    if start == 0 {
-        return Err(MusicError::BadIndex.into());
+    if queue.is_empty() {
+        SendMessage::Normal("Queue is empty").send_msg(ctx).await;
+        return Ok(());
     }
 
-    let format_track = |track: &TrackHandle| {
-        let title = track
-            .metadata()
-            .title
-            .clone()
-            .unwrap_or_else(|| "Unknown".into());
-
-        title.replace('`', "")
+    let page = match page {
+        Some(0) => return Err(MusicError::BadIndex.into()),
+        Some(p) => p - 1,
+        None => 0,
     };
+    list_queue(ctx, queue, page).await?;
 
-    // Build output string
-    let list = queue
-        .iter()
-        .skip(start - 1)
-        .zip(start..start + NUM_TRACKS)
-        .map(|(t, i)| format!("{:>2}: {}", i, format_track(t)))
-        .collect::<Vec<_>>()
-        .join("\n");
-
-    // Respond
-    let out_msg = match queue.len() {
-        0 => "Queue is empty".to_owned(),
-        1 => format!("{} track in queue\n```{}```", 1, list),
-        n => format!("{} tracks in queue\n```{}```", n, list),
+    Ok(())
+}
+
+/// Export or import the queue, without a subcommand list tracks in queue
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    subcommands("queue_export", "queue_import")
+)]
+pub async fn queue(ctx: PoiseContext<'_>) -> Result<(), PoiseError> {
+    list_page(ctx, None).await
+}
+
+/// Save the queue as a file
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    rename = "export"
+)]
+pub async fn queue_export(
+    ctx: PoiseContext<'_>,
+    #[description = "File format, defaults to json"] format: Option<ExportFormat>,
+) -> Result<(), PoiseError> {
+    let tracks = {
+        let handler_lock = ctx.get_voice().await?;
+        let handler = handler_lock.lock().await;
+        handler.queue().current_queue()
     };
-    SendMessage::Normal(&out_msg).send_msg(ctx).await;
+    let exported = ExportedQueue::from_tracks(&tracks);
+    if exported.tracks.is_empty() {
+        SendMessage::Normal("Queue is empty").send_msg(ctx).await;
+        return Ok(());
+    }
+
+    let format = format.unwrap_or(ExportFormat::Json);
+    let data = exported.export(format).into_bytes();
+    ctx.send(|m| {
+        m.content(format!("Exported {} tracks", exported.tracks.len()))
+            .attachment(AttachmentType::Bytes {
+                data: data.into(),
+                filename: format.file_name().to_owned(),
+            })
+    })
+    .await?;
 
     Ok(())
 }
 
+/// Add tracks from a JSON, M3U or XSPF file to the queue
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    rename = "import"
+)]
+pub async fn queue_import(
+    ctx: PoiseContext<'_>,
+    #[description = "Exported queue file"] file: Attachment,
+) -> Result<(), PoiseError> {
+    if file.size > MAX_IMPORT_SIZE {
+        return Err(MusicError::BadImport.into());
+    }
+    ctx.defer_or_broadcast().await?;
+
+    let data = file
+        .download()
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?;
+    let data = String::from_utf8(data).map_err(|_| MusicError::BadImport)?;
+    let mut imported = ExportedQueue::import(&data)?;
+    let dropped = imported.limit_tracks();
+
+    let num_tracks = imported.tracks.len();
+    let queries = imported
+        .tracks
+        .into_iter()
+        .map(|t| Query::Known(t.metadata()));
+    let result = add_tracks(ctx, futures::stream::iter(queries), num_tracks).await;
+
+    if dropped > 0 {
+        SendMessage::Normal(format!(
+            "Skipped the last {} tracks, only {} can be imported at once",
+            dropped, MAX_IMPORT_TRACKS
+        ))
+        .send_msg(ctx)
+        .await;
+    }
+
+    Ok(result?)
+}
+
 /// Remove tracks from queue
 #[poise::command(
     slash_command,
     prefix_command,
     guild_only,
+    category = "Music",
     broadcast_typing,
     aliases("rm")
 )]
@@ -256,9 +500,9 @@ pub async fn remove(
     #[description = "Remove tracks between indices (inclusive)"] track_end: Option<usize>,
 ) -> Result<(), PoiseError> {
     // Parse arguments
-    let start_idx = track - 1;
+    let start_idx = track_index(track).ok_or(MusicError::BadIndex)?;
     let end_idx = match track_end {
-        Some(i) => i - 1,
+        Some(i) => track_index(i).ok_or(MusicError::BadIndex)?,
         None => start_idx,
     };
 
@@ -276,3 +520,481 @@ pub async fn remove(
 
     Ok(())
 }
+
+/// Move the bot to your voice channel, keeping the queue
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    rename = "move-here",
+    aliases("movehere")
+)]
+pub async fn move_here(ctx: PoiseContext<'_>) -> Result<(), PoiseError> {
+    let handler_lock = ctx.join_voice().await?;
+    let channel_id = {
+        let mut handler = handler_lock.lock().await;
+        if !handler.queue().is_empty() {
+            handler.remove_all_global_events();
+        }
+        handler.current_channel()
+    };
+
+    if let Some(channel_id) = channel_id {
+        SendMessage::Normal(format!("Moved to <#{}>", channel_id))
+            .send_msg(ctx)
+            .await;
+    }
+
+    Ok(())
+}
+
+/// Show or change music settings of this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "MANAGE_GUILD"
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn settings(
+    ctx: PoiseContext<'_>,
+    #[description = "Minutes to stay in the voice channel after the queue ends"]
+    idle_timeout: Option<u64>,
+    #[description = "Minutes to stay in the voice channel after everyone else leaves"]
+    alone_timeout: Option<u64>,
+    #[description = "Never leave the voice channel"] always_on: Option<bool>,
+    #[description = "Voice channel to stay in, defaults to your current voice channel"]
+    channel: Option<Channel>,
+    #[description = "Fixed UTC offset for scheduled playback, e.g. UTC+9, not adjusted for DST"]
+    timezone: Option<String>,
+    #[description = "Text channel for music announcements, defaults to the current channel"]
+    music_channel: Option<Channel>,
+    #[description = "Whether music commands elsewhere are redirected or rejected, or off"]
+    music_channel_mode: Option<MusicChannelMode>,
+    #[description = "Keep a single now playing message updated instead of announcing every track"]
+    quiet_mode: Option<bool>,
+    #[description = "Delete the previous now playing message when the next track starts"]
+    clean_now_playing: Option<bool>,
+) -> Result<(), PoiseError> {
+    let guild_id = ctx.guild_id().ok_or(MusicError::GetVoice)?;
+    let mut settings = get_settings(ctx.serenity_context(), guild_id).await;
+    let changed = idle_timeout.is_some()
+        || alone_timeout.is_some()
+        || always_on.is_some()
+        || channel.is_some()
+        || timezone.is_some()
+        || music_channel.is_some()
+        || music_channel_mode.is_some()
+        || quiet_mode.is_some()
+        || clean_now_playing.is_some();
+
+    if let Some(t) = idle_timeout {
+        settings.idle_timeout = timeout_from_minutes(t).ok_or(MusicError::BadTimeout)?;
+    }
+    if let Some(t) = alone_timeout {
+        settings.alone_timeout = timeout_from_minutes(t).ok_or(MusicError::BadTimeout)?;
+    }
+    if let Some(channel) = channel {
+        match channel.guild() {
+            Some(c) if c.kind == ChannelType::Voice => settings.always_on_channel = Some(c.id),
+            _ => return Err(MusicError::NotVoiceChannel.into()),
+        }
+    }
+    if let Some(always_on) = always_on {
+        settings.always_on = always_on;
+    }
+    if let Some(timezone) = timezone {
+        settings.utc_offset = parse_utc_offset(&timezone).ok_or(MusicError::BadTimezone)?;
+    }
+    if let Some(channel) = music_channel {
+        match channel.guild() {
+            Some(c) if c.is_text_based() => settings.music_channel = Some(c.id),
+            _ => return Err(MusicError::NotTextChannel.into()),
+        }
+    }
+    match music_channel_mode {
+        Some(MusicChannelMode::Off) => {
+            settings.music_channel = None;
+            settings.reject_other_channels = false;
+        }
+        Some(mode) => {
+            settings.music_channel = settings.music_channel.or(Some(ctx.channel_id()));
+            settings.reject_other_channels = matches!(mode, MusicChannelMode::Reject);
+        }
+        None => {}
+    }
+    if let Some(quiet_mode) = quiet_mode {
+        settings.quiet_mode = quiet_mode;
+    }
+    if let Some(clean_now_playing) = clean_now_playing {
+        settings.clean_now_playing = clean_now_playing;
+    }
+
+    if changed {
+        // Stay in the current voice channel if none was given
+        if settings.always_on && settings.always_on_channel.is_none() {
+            settings.always_on_channel = Some(get_channel_id(&ctx).await?);
+        }
+        save_settings(&ctx, guild_id, settings.clone()).await?;
+
+        // Join the 24/7 channel right away
+        if let (true, Some(channel_id)) = (settings.always_on, settings.always_on_channel) {
+            let manager = songbird::get(ctx.serenity_context())
+                .await
+                .ok_or(MusicError::GetVoice)?;
+            let handler_lock = join_channel(&manager, guild_id, channel_id).await?;
+            handler_lock.lock().await.remove_all_global_events();
+        }
+    }
+
+    let channel = match settings.always_on_channel {
+        Some(c) => format!("<#{}>", c),
+        None => "none".to_owned(),
+    };
+    let music_channel = match (settings.music_channel, settings.reject_other_channels) {
+        (Some(c), false) => format!("<#{}> (redirect)", c),
+        (Some(c), true) => format!("<#{}> (reject)", c),
+        (None, _) => "none".to_owned(),
+    };
+    SendMessage::Normal(format!(
+        "Idle timeout: {} minutes\nAlone timeout: {} minutes\n24/7 mode: {}\n24/7 channel: {}\nTimezone: {}\nMusic channel: {}\nQuiet mode: {}\nClean now playing: {}",
+        settings.idle_timeout.as_secs() / 60,
+        settings.alone_timeout.as_secs() / 60,
+        if settings.always_on { "on" } else { "off" },
+        channel,
+        format_utc_offset(settings.utc_offset),
+        music_channel,
+        if settings.quiet_mode { "on" } else { "off" },
+        if settings.clean_now_playing { "on" } else { "off" },
+    ))
+    .send_msg(ctx)
+    .await;
+
+    Ok(())
+}
+
+/// Stop playing and leave the voice channel after a while, use "off" to cancel
+#[poise::command(slash_command, prefix_command, guild_only, category = "Music")]
+pub async fn sleep(
+    ctx: PoiseContext<'_>,
+    #[description = "Duration such as 30m or 1h30m, or \"off\""] duration: String,
+    #[description = "Let the current track finish before leaving"] finish_track: Option<bool>,
+) -> Result<(), PoiseError> {
+    let guild_id = ctx.guild_id().ok_or(MusicError::GetVoice)?;
+    ctx.get_voice().await?;
+
+    if matches!(duration.to_lowercase().as_str(), "off" | "cancel") {
+        let msg = if cancel_sleep_timer(ctx.serenity_context(), guild_id).await {
+            "Sleep timer cancelled"
+        } else {
+            "No sleep timer set"
+        };
+        SendMessage::Normal(msg).send_msg(ctx).await;
+        return Ok(());
+    }
+
+    let duration = parse_duration(&duration).ok_or(MusicError::BadDuration)?;
+    let finish_track = finish_track.unwrap_or(false);
+    let channel_id = get_settings(ctx.serenity_context(), guild_id)
+        .await
+        .announcement_channel(ctx.channel_id());
+    set_sleep_timer(
+        ctx.serenity_context(),
+        guild_id,
+        channel_id,
+        duration,
+        finish_track,
+    )
+    .await;
+
+    let mut msg = format!("Stopping in {}", format_duration(duration));
+    if finish_track {
+        msg.push_str(" after the current track finishes");
+    }
+    SendMessage::Normal(msg).send_msg(ctx).await;
+
+    Ok(())
+}
+
+/// Queue songs at a time of day, without a subcommand list scheduled playback
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    subcommands("schedule_add", "schedule_list", "schedule_cancel")
+)]
+pub async fn schedule(ctx: PoiseContext<'_>) -> Result<(), PoiseError> {
+    schedule_list_inner(ctx).await
+}
+
+/// Queue a song or URL in your voice channel at a time of day
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    rename = "add"
+)]
+pub async fn schedule_add(
+    ctx: PoiseContext<'_>,
+    #[description = "Time of day at the server's UTC offset, such as 19:30 or 7pm"] time: String,
+    #[rest]
+    #[description = "Song title or URL"]
+    query: String,
+) -> Result<(), PoiseError> {
+    let time = parse_time_of_day(&time).ok_or(MusicError::BadTime)?;
+    let voice_channel_id = get_channel_id(&ctx).await?;
+
+    let schedule = add_schedule(ctx, time, voice_channel_id, &query).await?;
+    SendMessage::Normal(format!(
+        "Scheduled {}) \"{}\" in <#{}> <t:{}:R>",
+        schedule.id, schedule.query, voice_channel_id, schedule.run_at
+    ))
+    .send_msg(ctx)
+    .await;
+
+    Ok(())
+}
+
+/// List scheduled playback
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    rename = "list"
+)]
+pub async fn schedule_list(ctx: PoiseContext<'_>) -> Result<(), PoiseError> {
+    schedule_list_inner(ctx).await
+}
+
+async fn schedule_list_inner(ctx: PoiseContext<'_>) -> Result<(), PoiseError> {
+    let guild_id = ctx.guild_id().ok_or(MusicError::GetVoice)?;
+    let schedules = guild_schedules(guild_id).await?;
+
+    let mut text = String::new();
+    for schedule in &schedules {
+        text.push_str(&format!(
+            r#"{}) "{}" in <#{}> <t:{}:f>"#,
+            schedule.id,
+            schedule.query,
+            schedule.voice_channel_id().unwrap_or_default(),
+            schedule.run_at().map_or(0, |t| t.unix_timestamp()),
+        ));
+        text.push('\n');
+    }
+    if schedules.is_empty() {
+        text.push_str("Nothing scheduled");
+    }
+    SendMessage::Normal(text).send_msg(ctx).await;
+
+    Ok(())
+}
+
+/// Cancel scheduled playback
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    rename = "cancel"
+)]
+pub async fn schedule_cancel(
+    ctx: PoiseContext<'_>,
+    #[description = "Schedule number from the schedule list"] id: i64,
+) -> Result<(), PoiseError> {
+    if !cancel_schedule(ctx, id).await? {
+        return Err(MusicError::BadIndex.into());
+    }
+    SendMessage::Normal(format!("Cancelled schedule {}", id))
+        .send_msg(ctx)
+        .await;
+
+    Ok(())
+}
+
+/// Link a Last.fm or ListenBrainz account to scrobble tracks you listen to
+#[poise::command(
+    slash_command,
+    prefix_command,
+    subcommands("scrobble_link", "scrobble_unlink", "scrobble_status")
+)]
+pub async fn scrobble(ctx: PoiseContext<'_>) -> Result<(), PoiseError> {
+    scrobble_status_inner(ctx).await
+}
+
+/// Link an account, a ListenBrainz user token is needed from listenbrainz.org/settings
+#[poise::command(slash_command, ephemeral, rename = "link")]
+pub async fn scrobble_link(
+    ctx: PoiseContext<'_>,
+    #[description = "Scrobbling service"] service: ScrobbleService,
+    #[description = "ListenBrainz user token"] token: Option<String>,
+) -> Result<(), PoiseError> {
+    let client = &ctx.data().scrobble_client;
+    let (token, username) = match service {
+        ScrobbleService::ListenBrainz => {
+            let token = token.ok_or(MusicError::BadToken)?;
+            let username = client.listenbrainz_validate(&token).await?;
+            (token, username)
+        }
+        ScrobbleService::Lastfm => {
+            if !client.lastfm_enabled() {
+                return Err(MusicError::ScrobblingDisabled.into());
+            }
+            let (request_token, url) = client.lastfm_auth_url().await?;
+            let done_id = format!("scrobble-{}", ctx.id());
+            let reply = ctx
+                .send(|m| {
+                    m.content("Authorize the bot on Last.fm, then press Done")
+                        .ephemeral(true)
+                        .components(|c| {
+                            c.create_action_row(|ar| {
+                                ar.create_button(|b| {
+                                    b.style(ButtonStyle::Link).label("Authorize").url(url)
+                                })
+                                .create_button(|b| {
+                                    b.style(ButtonStyle::Primary)
+                                        .label("Done")
+                                        .custom_id(&done_id)
+                                })
+                            })
+                        })
+                })
+                .await?;
+
+            let author_id = ctx.author().id;
+            let interaction = CollectComponentInteraction::new(ctx)
+                .author_id(author_id)
+                .filter(move |ci| ci.data.custom_id == done_id)
+                .timeout(SCROBBLE_AUTH_TIMEOUT)
+                .await;
+            let _ = reply.edit(ctx, |m| m.components(|c| c)).await;
+            let interaction = interaction.ok_or(MusicError::BadToken)?;
+            let _ = interaction.defer(ctx).await;
+
+            client.lastfm_session(&request_token).await?
+        }
+    };
+
+    link_account(ctx.author().id, service, &token, &username).await?;
+    ctx.send(|m| {
+        m.content(format!("Linked {} account {}", service, username))
+            .ephemeral(true)
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Stop scrobbling to an account
+#[poise::command(slash_command, prefix_command, rename = "unlink")]
+pub async fn scrobble_unlink(
+    ctx: PoiseContext<'_>,
+    #[description = "Scrobbling service"] service: ScrobbleService,
+) -> Result<(), PoiseError> {
+    let removed = unlink_account(ctx.author().id, service).await?;
+    let msg = if removed > 0 {
+        format!("Unlinked {} account", service)
+    } else {
+        format!("No {} account is linked", service)
+    };
+    SendMessage::Normal(msg).send_msg(ctx).await;
+
+    Ok(())
+}
+
+/// List your linked accounts
+#[poise::command(slash_command, prefix_command, rename = "status")]
+pub async fn scrobble_status(ctx: PoiseContext<'_>) -> Result<(), PoiseError> {
+    scrobble_status_inner(ctx).await
+}
+
+async fn scrobble_status_inner(ctx: PoiseContext<'_>) -> Result<(), PoiseError> {
+    let accounts = user_accounts(&[ctx.author().id]).await?;
+
+    let mut text = String::new();
+    for account in &accounts {
+        let service = match ScrobbleService::parse(&account.service) {
+            Some(s) => s,
+            None => continue,
+        };
+        text.push_str(&format!("{}: {}\n", service, account.username));
+    }
+    if text.is_empty() {
+        text.push_str("No accounts linked, use /scrobble link");
+    }
+    SendMessage::Normal(text).send_msg(ctx).await;
+
+    Ok(())
+}
+
+/// Show the most played tracks, artists and requesters of the server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    subcommands("stats_server", "stats_me")
+)]
+pub async fn stats(
+    ctx: PoiseContext<'_>,
+    #[description = "Period to show stats for"] period: Option<StatsPeriod>,
+) -> Result<(), PoiseError> {
+    stats_inner(ctx, None, period.unwrap_or_default()).await
+}
+
+/// Show the most played tracks, artists and requesters of the server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    rename = "server"
+)]
+pub async fn stats_server(
+    ctx: PoiseContext<'_>,
+    #[description = "Period to show stats for"] period: Option<StatsPeriod>,
+) -> Result<(), PoiseError> {
+    stats_inner(ctx, None, period.unwrap_or_default()).await
+}
+
+/// Show the tracks and artists you requested the most in this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    category = "Music",
+    rename = "me"
+)]
+pub async fn stats_me(
+    ctx: PoiseContext<'_>,
+    #[description = "Period to show stats for"] period: Option<StatsPeriod>,
+) -> Result<(), PoiseError> {
+    stats_inner(ctx, Some(ctx.author()), period.unwrap_or_default()).await
+}
+
+async fn stats_inner(
+    ctx: PoiseContext<'_>,
+    user: Option<&User>,
+    period: StatsPeriod,
+) -> Result<(), PoiseError> {
+    let guild_id = ctx.guild_id().ok_or(MusicError::GetVoice)?;
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let stats = play_stats(guild_id, user.map(|u| u.id), period.since(now)).await?;
+
+    let title = match user {
+        Some(u) => format!("Stats for {} ({})", u.name, period),
+        None => format!("Server stats ({})", period),
+    };
+    ctx.send(|m| {
+        m.embed(|e| {
+            format_stats(title, &stats)(e);
+            e
+        })
+    })
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use std::sync::OnceLock;
+
+use anyhow::anyhow;
+use sea_orm::{ConnectionTrait, Database, DatabaseConnection, DbErr, EntityTrait, Schema};
+
+use super::error::MusicError;
+
+/// Connection shared by all music tables, set by `connect`
+static DB: OnceLock<DatabaseConnection> = OnceLock::new();
+
+/// Connect to the database used by all music tables
+pub async fn connect(db_uri: &str) -> anyhow::Result<&'static DatabaseConnection> {
+    let db = Database::connect(db_uri).await?;
+    Ok(DB.get_or_init(|| db))
+}
+
+/// Shared connection, with errors that can be shown to users
+pub fn connection() -> Result<&'static DatabaseConnection, MusicError> {
+    DB.get()
+        .ok_or_else(|| MusicError::Internal(anyhow!("database is not connected")))
+}
+
+/// Create the table of an entity if it does not exist yet
+pub async fn create_table<E: EntityTrait>(db: &DatabaseConnection, entity: E) -> Result<(), DbErr> {
+    let backend = db.get_database_backend();
+    let schema = Schema::new(backend);
+    let mut stmt = schema.create_table_from_entity(entity);
+    stmt.if_not_exists();
+    db.execute(backend.build(&stmt)).await?;
+
+    Ok(())
+}
+
+/// Discord IDs are stored as hex strings
+pub fn stringify(x: u64) -> String {
+    format!("{:x}", x)
+}
+
+pub fn parse_id(s: &str) -> Option<u64> {
+    u64::from_str_radix(s, 16).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_ids() {
+        assert_eq!("ff", stringify(255));
+        assert_eq!(Some(u64::MAX), parse_id(&stringify(u64::MAX)));
+        assert_eq!(None, parse_id("not hex"));
+    }
+}
@@ -1,21 +1,41 @@
 use std::error::Error;
 use std::fmt::Display;
 
+use poise::serenity_prelude::{self as serenity, ChannelId, GuildId};
+use songbird::error::{JoinError, TrackError};
+
+use super::extract::ExtractError;
+
 #[derive(Debug)]
 pub enum MusicError {
     Internal(anyhow::Error),
     AddTracks { failed: usize, total: usize },
+    BadDuration,
+    BadImport,
     BadIndex,
     BadPlaylist,
     BadSource(String),
+    BadTime,
+    BadTimeout,
+    BadTimezone,
+    BadToken,
+    DifferentVoiceChannel,
+    Extract(ExtractError),
     GetVoice,
     JoinVoice,
     Loudness,
     NoPausedTrack,
     NoPlayingTrack,
+    NoPreviousTrack,
     NoResults,
     NotInVoiceChannel,
+    NotTextChannel,
+    NotVoiceChannel,
     RemoveTrack,
+    ScrobblingDisabled,
+    Seek,
+    VoiceChannelBusy,
+    WrongTextChannel(ChannelId),
 }
 
 impl Display for MusicError {
@@ -29,6 +49,8 @@ impl Display for MusicError {
                     write!(f, "could not add {} tracks to queue", failed)
                 }
             }
+            Self::BadDuration => write!(f, "invalid duration, use a duration up to 24h like 1h30m"),
+            Self::BadImport => write!(f, "invalid or empty queue file"),
             Self::BadIndex => write!(f, "invalid index"),
             Self::BadPlaylist => write!(f, "invalid or empty playlist"),
             Self::BadSource(s) => {
@@ -39,31 +61,69 @@ impl Display for MusicError {
                     .trim();
                 write!(f, "could not load source\n{}", s)
             }
+            Self::BadTime => write!(f, "invalid time, use a time of day like 19:30"),
+            Self::BadTimeout => write!(f, "invalid timeout, use 0 to 10080 minutes"),
+            Self::BadTimezone => write!(f, "invalid timezone, use a UTC offset like UTC+9"),
+            Self::BadToken => write!(f, "invalid token or the account was not authorized"),
+            Self::DifferentVoiceChannel => {
+                write!(f, "you are not in the same voice channel as the bot")
+            }
+            Self::Extract(e) => write!(f, "could not load source\n{}", e),
             Self::GetVoice => write!(f, "could not get voice channel"),
             Self::JoinVoice => write!(f, "could not join voice channel"),
             Self::Loudness => write!(f, "could not get track loudness"),
             Self::NoPausedTrack => write!(f, "no currently paused track"),
             Self::NoPlayingTrack => write!(f, "no currently playing track"),
+            Self::NoPreviousTrack => write!(f, "no previously played track"),
             Self::NoResults => write!(f, "no results found"),
             Self::NotInVoiceChannel => write!(f, "you are not in a voice channel"),
+            Self::NotTextChannel => write!(f, "not a text channel"),
+            Self::NotVoiceChannel => write!(f, "not a voice channel"),
             Self::RemoveTrack => write!(f, "could not remove track"),
+            Self::ScrobblingDisabled => write!(f, "Last.fm scrobbling is not configured"),
+            Self::Seek => write!(f, "could not seek track"),
+            Self::VoiceChannelBusy => {
+                write!(f, "the bot is already playing in another voice channel")
+            }
+            Self::WrongTextChannel(c) => write!(f, "music commands can only be used in <#{}>", c),
         }
     }
 }
 
 impl Error for MusicError {}
 
+impl From<InternalError> for MusicError {
+    fn from(e: InternalError) -> Self {
+        Self::Internal(e.into())
+    }
+}
+
 #[derive(Debug)]
 pub enum InternalError {
+    AddEvent(TrackError),
+    LeaveVoice(JoinError),
     QueueLock,
+    Seek(TrackError),
+    SendMessage(serenity::Error),
 }
 
 impl Display for InternalError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Self::AddEvent(e) => write!(f, "could not add track event: {}", e),
+            Self::LeaveVoice(e) => write!(f, "could not leave voice channel: {}", e),
             Self::QueueLock => write!(f, "could not get queue lock"),
+            Self::Seek(e) => write!(f, "could not seek track: {}", e),
+            Self::SendMessage(e) => write!(f, "could not send message: {}", e),
         }
     }
 }
 
 impl Error for InternalError {}
+
+/// Log the error of something that did not run in a command, so there is no one to reply to
+pub fn log_error<E: Into<MusicError>>(guild_id: GuildId, result: Result<(), E>) {
+    if let Err(e) = result {
+        eprintln!("Error in guild {}: {}", guild_id, e.into());
+    }
+}
@@ -2,17 +2,59 @@ use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
+use anyhow::Result;
 use poise::serenity_prelude as serenity;
-use serenity::http::Http;
 use serenity::{async_trait, *};
+use songbird::tracks::{PlayMode, TrackHandle};
 use songbird::{Event, EventContext, EventHandler as VoiceEventHandler};
 
-use super::message::PlayUpdate;
-use crate::message::{CustomSendMessage, SendableMessage};
+use super::bus::{publish, MusicEvent};
+use super::error::{log_error, InternalError, MusicError};
+use super::history::push_history;
+use super::prefetch::pause_crossfade;
+use super::queue::RequestGuild;
+use super::resolve::resolve_upcoming;
+use super::settings::{get_settings, GuildSettingsMap};
+use super::tracker::{track_channel_delete, track_voice_state, VoiceChange};
+use super::voice::{get_bot_channel_id, join_channel};
+use super::youtube::sponsorblock::SBSegments;
+
+/// Incremented whenever the segment skipper of a track is replaced
+pub struct SkipperGeneration;
+
+impl TypeMapKey for SkipperGeneration {
+    type Value = usize;
+}
+
+/// Replace the segment skipper of a track, skipping the segments after the given position
+pub async fn set_segment_skipper(track: &TrackHandle, position: Duration) {
+    let mut typemap = track.typemap().write().await;
+    let generation = typemap.get::<SkipperGeneration>().map_or(0, |g| g + 1);
+    typemap.insert::<SkipperGeneration>(generation);
+
+    let segments: Vec<_> = typemap
+        .get::<SBSegments>()
+        .into_iter()
+        .flatten()
+        .filter(|(_, end)| *end > position)
+        .cloned()
+        .collect();
+    if let Some(segment) = segments.first().cloned() {
+        let _ = track.add_event(
+            Event::Delayed(segment.0.saturating_sub(position)),
+            TrackSegmentSkipper {
+                segments,
+                idx: 0.into(),
+                generation,
+            },
+        );
+    }
+}
 
 pub struct TrackSegmentSkipper {
     pub segments: Vec<(Duration, Duration)>,
     pub idx: AtomicUsize,
+    pub generation: usize,
 }
 
 #[async_trait]
@@ -21,19 +63,33 @@ impl VoiceEventHandler for TrackSegmentSkipper {
         let idx = self.idx.fetch_add(1, Ordering::SeqCst);
         if idx < self.segments.len() {
             if let EventContext::Track(&[(state, track)]) = ctx {
+                // Stop if this skipper was replaced
+                let (generation, guild_id) = {
+                    let typemap = track.typemap().read().await;
+                    (
+                        typemap.get::<SkipperGeneration>().copied(),
+                        typemap.get::<RequestGuild>().copied(),
+                    )
+                };
+                if generation != Some(self.generation) {
+                    return Some(Event::Cancel);
+                }
+
                 let (seek_from, seek_to) = self.segments[idx];
 
                 // Workaround for https://github.com/serenity-rs/songbird/issues/97
                 let diff = seek_from.saturating_sub(state.position);
                 if diff > Duration::from_secs(1) {
                     // Too early? Delay again
-                    println!("{:?} too early!", diff);
                     self.idx.store(idx, Ordering::SeqCst);
                     return Some(Event::Delayed(diff));
                 }
 
-                // Skip to specified time
-                track.seek_time(seek_to).unwrap();
+                // Skip to specified time, the track can't be seeked anymore if this fails
+                if let Err(e) = track.seek_time(seek_to) {
+                    log_error(guild_id.unwrap_or_default(), Err(InternalError::Seek(e)));
+                    return Some(Event::Cancel);
+                }
 
                 // If another skip exists, add an event
                 if idx + 1 < self.segments.len() {
@@ -47,36 +103,59 @@ impl VoiceEventHandler for TrackSegmentSkipper {
     }
 }
 
+/// Marks a track that was paused because everyone left the voice channel
+pub struct AutoPaused;
+
+impl TypeMapKey for AutoPaused {
+    type Value = ();
+}
+
 pub struct TrackStartNotifier {
     pub ctx: Arc<Mutex<Context>>,
-    pub chan_id: ChannelId,
     pub guild_id: GuildId,
-    pub http: Arc<Http>,
 }
 
 #[async_trait]
 impl VoiceEventHandler for TrackStartNotifier {
     async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
         let guild_ctx = self.ctx.lock().await;
-        let manager = songbird::get(&guild_ctx)
-            .await
-            .expect("Songbird Voice client placed in at initialization.")
-            .clone();
+        let manager = match songbird::get(&guild_ctx).await {
+            Some(m) => m,
+            None => {
+                log_error(self.guild_id, Err(MusicError::GetVoice));
+                return None;
+            }
+        };
         if let EventContext::Track(&[(state, track)]) = ctx {
-            let update = if state.position < Duration::from_secs(1) {
+            let guild_id = self.guild_id;
+            let track = track.clone();
+            let event = if state.position < Duration::from_secs(1) {
                 let queue_len = if let Some(handler_lock) = manager.get(self.guild_id) {
                     let handler = handler_lock.lock().await;
+                    resolve_upcoming(handler.queue());
                     handler.queue().len()
                 } else {
                     1
                 };
-                PlayUpdate::Play(track.clone(), queue_len)
+                MusicEvent::TrackStarted {
+                    guild_id,
+                    track,
+                    queue_len,
+                }
             } else {
-                PlayUpdate::Resume(track.clone())
+                let auto = track
+                    .typemap()
+                    .write()
+                    .await
+                    .remove::<AutoPaused>()
+                    .is_some();
+                MusicEvent::TrackResumed {
+                    guild_id,
+                    track,
+                    auto,
+                }
             };
-            CustomSendMessage::Custom(update.format().await)
-                .send_msg_http(&self.http, self.chan_id)
-                .await;
+            publish(event);
         }
 
         None
@@ -84,109 +163,352 @@ impl VoiceEventHandler for TrackStartNotifier {
 }
 
 /// Sets a global event which will leave the voice channel after while
-#[allow(unused)]
 pub struct TrackEndNotifier {
     pub ctx: Arc<Mutex<Context>>,
-    pub chan_id: ChannelId,
     pub guild_id: GuildId,
-    pub http: Arc<Http>,
 }
 
 #[async_trait]
 impl VoiceEventHandler for TrackEndNotifier {
-    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
-        let ctx = self.ctx.lock().await;
-        let manager = songbird::get(&ctx)
-            .await
-            .expect("Songbird Voice client placed in at initialization.")
-            .clone();
-        if let Some(handler_lock) = manager.get(self.guild_id) {
-            // Need to figure out how to prevent this message spamming chat when stopping queue
-            // with multiple tracks
-            // send_msg(&self.http, self.chan_id, SendMessage::Normal("Queue ended")).await;
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        let guild_ctx = self.ctx.lock().await;
 
-            set_leave_timer(handler_lock).await;
+        // Remember tracks that were actually played
+        if let EventContext::Track(&[(state, track)]) = ctx {
+            if !state.play_time.is_zero() {
+                push_history(&guild_ctx, self.guild_id, track.metadata().clone()).await;
+            }
+            publish(MusicEvent::TrackEnded {
+                guild_id: self.guild_id,
+                track: track.clone(),
+                play_time: state.play_time,
+                finished: state.playing == PlayMode::End,
+            });
+        }
+
+        let manager = match songbird::get(&guild_ctx).await {
+            Some(m) => m,
+            None => {
+                log_error(self.guild_id, Err(MusicError::GetVoice));
+                return None;
+            }
+        };
+        if let Some(handler_lock) = manager.get(self.guild_id) {
+            let settings = get_settings(&guild_ctx, self.guild_id).await;
+            if !settings.always_on {
+                let timeout = settings.idle_timeout;
+                set_leave_timer(self.guild_id, handler_lock, timeout, LeaveReason::Idle).await;
+            }
         }
 
         None
     }
 }
 
-async fn set_leave_timer(call: Arc<Mutex<songbird::Call>>) {
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LeaveReason {
+    /// Nothing left to play
+    Idle,
+    /// No one else in the voice channel
+    Alone,
+}
+
+async fn set_leave_timer(
+    guild_id: GuildId,
+    call: Arc<Mutex<songbird::Call>>,
+    timeout: Duration,
+    reason: LeaveReason,
+) {
     let mut handle = call.lock().await;
     handle.add_global_event(
-        Event::Delayed(Duration::from_secs(600)),
-        ChannelIdleLeaver { call: call.clone() },
+        Event::Delayed(timeout),
+        ChannelIdleLeaver {
+            guild_id,
+            call: call.clone(),
+            reason,
+        },
     );
 }
 
 struct ChannelIdleLeaver {
+    guild_id: GuildId,
     call: Arc<Mutex<songbird::Call>>,
+    reason: LeaveReason,
 }
 
 #[async_trait]
 impl VoiceEventHandler for ChannelIdleLeaver {
     async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
         let mut handler = self.call.lock().await;
-        if let Some(track) = handler.queue().current() {
-            let _ = track.stop();
+
+        // More tracks were queued after the last one ended
+        if self.reason == LeaveReason::Idle && !handler.queue().is_empty() {
+            return None;
         }
-        handler.queue().stop();
-        handler.leave().await.unwrap();
-        handler.remove_all_global_events();
+
+        log_error(self.guild_id, stop_and_leave(&mut handler).await);
         None
     }
 }
 
-/// If bot is in a voice channel when the last other user leaves, set an idle timeout
+/// Stop all tracks and leave the voice channel
+pub async fn stop_and_leave(handler: &mut songbird::Call) -> Result<(), InternalError> {
+    if let Some(track) = handler.queue().current() {
+        let _ = track.stop();
+    }
+    handler.queue().stop();
+    handler.remove_all_global_events();
+    handler.leave().await.map_err(InternalError::LeaveVoice)
+}
+
+/// Join the configured voice channels of guilds in 24/7 mode
+pub async fn join_always_on_channels(ctx: &serenity::Context) {
+    let guilds: Vec<_> = match ctx.data.read().await.get::<GuildSettingsMap>() {
+        Some(map) => map
+            .iter()
+            .filter(|(_, settings)| settings.always_on)
+            .filter_map(|(guild_id, settings)| Some((*guild_id, settings.always_on_channel?)))
+            .collect(),
+        None => return,
+    };
+
+    let manager = match songbird::get(ctx).await {
+        Some(m) => m,
+        None => return,
+    };
+    for (guild_id, channel_id) in guilds {
+        let res = join_channel(&manager, guild_id, channel_id).await;
+        log_error(guild_id, res.map(|_| ()));
+    }
+}
+
+/// Keep track of the bot's voice channel, set an idle timeout when the last other user leaves
+/// and clean up when the bot is disconnected
 pub async fn handle_voice_state_event(
     ctx: &serenity::Context,
     voice_state: &serenity::model::voice::VoiceState,
 ) {
     if let Some(guild_id) = voice_state.guild_id {
-        let songbird = songbird::serenity::get(ctx).await.unwrap();
-        let handler_lock = if let Some(h) = songbird.get(guild_id) {
-            h
-        } else {
-            return;
-        };
+        let change = track_voice_state(ctx, voice_state).await;
+        handle_voice_change(ctx, guild_id, change).await;
+    }
+}
 
-        let cache = ctx.cache.clone();
-        let guild = cache
-            .guild(guild_id)
-            .unwrap()
-            .channels(ctx.http())
-            .await
-            .unwrap();
-        for (_, channel) in guild {
-            if channel.kind != ChannelType::Voice {
-                continue;
-            }
+/// Clean up if the channel the bot is in was deleted
+pub async fn handle_channel_delete(ctx: &serenity::Context, channel: &GuildChannel) {
+    let change = track_channel_delete(ctx, channel.guild_id, channel.id).await;
+    handle_voice_change(ctx, channel.guild_id, change).await;
+}
 
-            // Find the voice channel bot is in
-            let mut bot_in_channel = false;
-            let bot_user_id = cache.clone().current_user_id();
-            let members = channel.members(cache.clone()).await.unwrap();
-            for member in &members {
-                if member.user.id == bot_user_id {
-                    bot_in_channel = true;
-                    break;
-                }
-            }
+async fn handle_voice_change(ctx: &serenity::Context, guild_id: GuildId, change: VoiceChange) {
+    if change == VoiceChange::Unchanged {
+        return;
+    }
 
-            if bot_in_channel {
-                if members.len() == 1 {
-                    // Only bot is in channel, add idle timeout
-                    set_leave_timer(handler_lock).await;
-                } else {
-                    // Others in channel as well, remove idle timeout if queue is not empty
-                    let mut handler = handler_lock.lock().await;
-                    if !handler.queue().is_empty() {
-                        handler.remove_all_global_events();
-                    }
-                }
-                return;
+    let songbird = match songbird::get(ctx).await {
+        Some(s) => s,
+        None => return,
+    };
+    let settings = get_settings(ctx, guild_id).await;
+
+    if change == VoiceChange::Disconnected {
+        publish(MusicEvent::VoiceLeft { guild_id });
+
+        // Nothing can be played anymore, stop the queue
+        if let Some(handler_lock) = songbird.get(guild_id) {
+            let mut handler = handler_lock.lock().await;
+            if let Some(track) = handler.queue().current() {
+                let _ = track.stop();
             }
+            handler.queue().stop();
+            handler.remove_all_global_events();
+            let _ = handler.leave().await;
+        }
+
+        // Rejoin if bot was disconnected in 24/7 mode
+        if let (true, Some(channel_id)) = (settings.always_on, settings.always_on_channel) {
+            let res = join_channel(&songbird, guild_id, channel_id).await;
+            log_error(guild_id, res.map(|_| ()));
+        }
+        return;
+    }
+
+    if let (VoiceChange::Moved { .. }, Some(channel_id)) =
+        (&change, get_bot_channel_id(ctx, guild_id).await)
+    {
+        publish(MusicEvent::VoiceJoined {
+            guild_id,
+            channel_id,
+        });
+    }
+
+    let handler_lock = match songbird.get(guild_id) {
+        Some(h) => h,
+        None => return,
+    };
+    // Don't play to an empty channel
+    match change {
+        VoiceChange::Moved { alone: true } | VoiceChange::Alone => {
+            auto_pause(guild_id, &handler_lock).await
+        }
+        VoiceChange::Moved { alone: false } | VoiceChange::NotAlone => {
+            auto_resume(&handler_lock).await
         }
+        _ => (),
+    }
+
+    if let VoiceChange::Alone = change {
+        // Only bot is in channel, add alone timeout
+        if !settings.always_on {
+            let timeout = settings.alone_timeout;
+            set_leave_timer(guild_id, handler_lock, timeout, LeaveReason::Alone).await;
+        }
+        return;
+    }
+
+    let mut handler = handler_lock.lock().await;
+    let reasons = match replace_leave_timers(&change, handler.queue().is_empty()) {
+        Some(r) => r,
+        None => return,
+    };
+    handler.remove_all_global_events();
+    drop(handler);
+
+    if !settings.always_on {
+        for reason in reasons {
+            let timeout = match reason {
+                LeaveReason::Idle => settings.idle_timeout,
+                LeaveReason::Alone => settings.alone_timeout,
+            };
+            set_leave_timer(guild_id, handler_lock.clone(), timeout, reason).await;
+        }
+    }
+}
+
+/// Leave timers that replace all current ones after a voice change, `None` keeps them
+fn replace_leave_timers(change: &VoiceChange, idle: bool) -> Option<Vec<LeaveReason>> {
+    let alone = match change {
+        // Timers set for the old channel no longer apply
+        VoiceChange::Moved { alone } => *alone,
+        // Someone is listening again, but the bot still leaves if nothing is queued
+        VoiceChange::NotAlone => false,
+        _ => return None,
+    };
+
+    let mut reasons = vec![];
+    if idle {
+        reasons.push(LeaveReason::Idle);
+    }
+    if alone {
+        reasons.push(LeaveReason::Alone);
+    }
+    Some(reasons)
+}
+
+/// Pause the playing track until someone is listening again
+async fn auto_pause(guild_id: GuildId, handler_lock: &Arc<Mutex<songbird::Call>>) {
+    let track = match handler_lock.lock().await.queue().current() {
+        Some(t) => t,
+        None => return,
+    };
+    match track.get_info().await {
+        Ok(info) if info.playing == PlayMode::Play => (),
+        _ => return,
+    }
+    if track.pause().is_err() {
+        return;
+    }
+    pause_crossfade(&track).await;
+
+    track.typemap().write().await.insert::<AutoPaused>(());
+    publish(MusicEvent::TrackPaused {
+        guild_id,
+        track,
+        auto: true,
+    });
+}
+
+/// Resume the current track if it was paused by `auto_pause`, `TrackStartNotifier` announces it
+async fn auto_resume(handler_lock: &Arc<Mutex<songbird::Call>>) {
+    let track = match handler_lock.lock().await.queue().current() {
+        Some(t) => t,
+        None => return,
+    };
+    if track.typemap().read().await.contains_key::<AutoPaused>() {
+        let _ = track.play();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use songbird::input::{Input, Reader};
+    use songbird::tracks::TrackState;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_skip_dropped_track() {
+        let (track, handle) =
+            songbird::create_player(Input::float_pcm(true, Reader::from_memory(vec![])));
+        drop(track);
+        handle
+            .typemap()
+            .write()
+            .await
+            .insert::<SkipperGeneration>(0);
+
+        let skipper = TrackSegmentSkipper {
+            segments: vec![(Duration::ZERO, Duration::from_secs(10))],
+            idx: 0.into(),
+            generation: 0,
+        };
+        let state = TrackState::default();
+        let event = skipper
+            .act(&EventContext::Track(&[(&state, &handle)]))
+            .await;
+        assert!(matches!(event, Some(Event::Cancel)));
+    }
+
+    #[tokio::test]
+    async fn test_leave_without_gateway() {
+        // Standalone calls can't send voice state updates, so leaving fails
+        let call = songbird::Call::standalone(GuildId(1), UserId(2));
+        let call = Arc::new(Mutex::new(call));
+        assert!(matches!(
+            stop_and_leave(&mut *call.lock().await).await,
+            Err(InternalError::LeaveVoice(_))
+        ));
+
+        let leaver = ChannelIdleLeaver {
+            guild_id: GuildId(1),
+            call,
+            reason: LeaveReason::Alone,
+        };
+        assert!(leaver.act(&EventContext::Track(&[])).await.is_none());
+    }
+
+    #[test]
+    fn test_replace_leave_timers() {
+        use LeaveReason::*;
+
+        // Someone rejoining cancels the alone timer, but not leaving an empty queue
+        assert_eq!(
+            Some(vec![Idle]),
+            replace_leave_timers(&VoiceChange::NotAlone, true)
+        );
+        assert_eq!(
+            Some(vec![]),
+            replace_leave_timers(&VoiceChange::NotAlone, false)
+        );
+        assert_eq!(
+            Some(vec![Idle, Alone]),
+            replace_leave_timers(&VoiceChange::Moved { alone: true }, true)
+        );
+        assert_eq!(
+            Some(vec![]),
+            replace_leave_timers(&VoiceChange::Moved { alone: false }, false)
+        );
+        assert_eq!(None, replace_leave_timers(&VoiceChange::Alone, true));
+        assert_eq!(None, replace_leave_timers(&VoiceChange::Unchanged, true));
     }
 }
@@ -0,0 +1,308 @@
+use std::fmt::Write as _;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use songbird::input::Metadata;
+use songbird::tracks::TrackHandle;
+use url::Url;
+
+use super::error::MusicError;
+
+/// Imported files larger than this are rejected
+pub const MAX_IMPORT_SIZE: u64 = 1024 * 1024;
+/// Tracks after this many in an imported file are dropped
+pub const MAX_IMPORT_TRACKS: usize = 500;
+
+#[derive(Clone, Copy, Debug, poise::ChoiceParameter)]
+pub enum ExportFormat {
+    #[name = "json"]
+    Json,
+    #[name = "m3u"]
+    M3u,
+    #[name = "xspf"]
+    Xspf,
+}
+
+impl ExportFormat {
+    pub fn file_name(&self) -> &'static str {
+        match self {
+            Self::Json => "queue.json",
+            Self::M3u => "queue.m3u",
+            Self::Xspf => "queue.xspf",
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ExportedQueue {
+    pub tracks: Vec<ExportedTrack>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExportedTrack {
+    pub url: String,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    /// Duration in seconds
+    pub duration: Option<f64>,
+}
+
+impl ExportedTrack {
+    pub fn metadata(&self) -> Metadata {
+        Metadata {
+            title: self.title.clone(),
+            artist: self.artist.clone(),
+            duration: self
+                .duration
+                .filter(|d| d.is_finite() && *d >= 0.0)
+                .map(Duration::from_secs_f64),
+            source_url: Some(self.url.clone()),
+            channels: Some(2),
+            ..Default::default()
+        }
+    }
+}
+
+impl ExportedQueue {
+    /// Tracks of a queue that have a source URL
+    pub fn from_tracks(tracks: &[TrackHandle]) -> Self {
+        let tracks = tracks
+            .iter()
+            .filter_map(|t| {
+                let metadata = t.metadata();
+                Some(ExportedTrack {
+                    url: metadata.source_url.clone()?,
+                    title: metadata.title.clone(),
+                    artist: metadata.artist.clone(),
+                    duration: metadata.duration.map(|d| d.as_secs_f64()),
+                })
+            })
+            .collect();
+        Self { tracks }
+    }
+
+    pub fn export(&self, format: ExportFormat) -> String {
+        match format {
+            ExportFormat::Json => serde_json::to_string_pretty(self).unwrap_or_default(),
+            ExportFormat::M3u => self.to_m3u(),
+            ExportFormat::Xspf => self.to_xspf(),
+        }
+    }
+
+    /// Parse a file in any of the export formats
+    pub fn import(data: &str) -> Result<Self, MusicError> {
+        let trimmed = data.trim_start_matches('\u{feff}').trim_start();
+        let queue = if trimmed.starts_with('{') {
+            serde_json::from_str(trimmed).map_err(|_| MusicError::BadImport)?
+        } else if trimmed.starts_with("<?xml") || trimmed.starts_with("<playlist") {
+            Self::from_xspf(trimmed)
+        } else {
+            Self::from_m3u(trimmed)
+        };
+
+        // URLs are passed to yt-dlp, anything else could be read as an option
+        if queue.tracks.is_empty() || !queue.tracks.iter().all(|t| is_web_url(&t.url)) {
+            return Err(MusicError::BadImport);
+        }
+        Ok(queue)
+    }
+
+    /// Drop tracks after `MAX_IMPORT_TRACKS`, returns the number of dropped tracks
+    pub fn limit_tracks(&mut self) -> usize {
+        let dropped = self.tracks.len().saturating_sub(MAX_IMPORT_TRACKS);
+        self.tracks.truncate(MAX_IMPORT_TRACKS);
+        dropped
+    }
+
+    fn to_m3u(&self) -> String {
+        let mut out = "#EXTM3U\n".to_owned();
+        for track in &self.tracks {
+            let duration = track.duration.map_or(-1, |d| d.round() as i64);
+            let name = match (&track.artist, &track.title) {
+                (Some(artist), Some(title)) => format!("{} - {}", artist, title),
+                (None, Some(title)) => title.clone(),
+                _ => String::new(),
+            };
+            let _ = writeln!(out, "#EXTINF:{},{}", duration, name.replace('\n', " "));
+            let _ = writeln!(out, "{}", track.url);
+        }
+        out
+    }
+
+    fn from_m3u(data: &str) -> Self {
+        let mut tracks = vec![];
+        let mut info: Option<(Option<f64>, String)> = None;
+        for line in data.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+                let (duration, name) = extinf.split_once(',').unwrap_or((extinf, ""));
+                let duration = duration.trim().parse::<f64>().ok().filter(|d| *d >= 0.0);
+                info = Some((duration, name.trim().to_owned()));
+            } else if !line.starts_with('#') {
+                let (duration, name) = info.take().unwrap_or_default();
+                let (artist, title) = match name.split_once(" - ") {
+                    Some((artist, title)) => (Some(artist.to_owned()), Some(title.to_owned())),
+                    None if !name.is_empty() => (None, Some(name)),
+                    None => (None, None),
+                };
+                tracks.push(ExportedTrack {
+                    url: line.to_owned(),
+                    title,
+                    artist,
+                    duration,
+                });
+            }
+        }
+        Self { tracks }
+    }
+
+    fn to_xspf(&self) -> String {
+        let mut out = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n  <trackList>\n",
+        );
+        for track in &self.tracks {
+            out.push_str("    <track>\n");
+            let _ = writeln!(out, "      <location>{}</location>", escape_xml(&track.url));
+            if let Some(title) = &track.title {
+                let _ = writeln!(out, "      <title>{}</title>", escape_xml(title));
+            }
+            if let Some(artist) = &track.artist {
+                let _ = writeln!(out, "      <creator>{}</creator>", escape_xml(artist));
+            }
+            if let Some(duration) = track.duration {
+                // XSPF durations are in milliseconds
+                let _ = writeln!(
+                    out,
+                    "      <duration>{}</duration>",
+                    (duration * 1000.0).round() as u64
+                );
+            }
+            out.push_str("    </track>\n");
+        }
+        out.push_str("  </trackList>\n</playlist>\n");
+        out
+    }
+
+    fn from_xspf(data: &str) -> Self {
+        let tracks = data
+            .split("<track>")
+            .skip(1)
+            .filter_map(|block| {
+                let block = block.split("</track>").next()?;
+                Some(ExportedTrack {
+                    url: xml_element(block, "location")?,
+                    title: xml_element(block, "title"),
+                    artist: xml_element(block, "creator"),
+                    duration: xml_element(block, "duration")
+                        .and_then(|d| d.parse::<f64>().ok())
+                        .map(|ms| ms / 1000.0),
+                })
+            })
+            .collect();
+        Self { tracks }
+    }
+}
+
+fn is_web_url(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|u| matches!(u.scheme(), "http" | "https"))
+}
+
+/// Text content of the first element with the given name
+fn xml_element(block: &str, name: &str) -> Option<String> {
+    let start = block.find(&format!("<{}>", name))? + name.len() + 2;
+    let len = block[start..].find(&format!("</{}>", name))?;
+    Some(unescape_xml(block[start..start + len].trim()))
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn unescape_xml(s: &str) -> String {
+    s.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn queue() -> ExportedQueue {
+        ExportedQueue {
+            tracks: vec![
+                ExportedTrack {
+                    url: "https://www.youtube.com/watch?v=5gvfp-haKXc&t=1".to_owned(),
+                    title: Some("Song <live> & more".to_owned()),
+                    artist: Some("Artist".to_owned()),
+                    duration: Some(61.0),
+                },
+                ExportedTrack {
+                    url: "https://soundcloud.com/artist/track".to_owned(),
+                    title: None,
+                    artist: None,
+                    duration: None,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for format in [ExportFormat::Json, ExportFormat::M3u, ExportFormat::Xspf] {
+            let imported = ExportedQueue::import(&queue().export(format)).unwrap();
+            assert_eq!(queue().tracks, imported.tracks, "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn test_plain_m3u() {
+        let imported = ExportedQueue::import("# comment\nhttps://a\n\nhttps://b\n").unwrap();
+        assert_eq!(2, imported.tracks.len());
+        assert_eq!("https://b", imported.tracks[1].url);
+        assert_eq!(None, imported.tracks[1].title);
+    }
+
+    #[test]
+    fn test_limit_tracks() {
+        let data = "https://a\n".repeat(MAX_IMPORT_TRACKS + 3);
+        let mut imported = ExportedQueue::import(&data).unwrap();
+        assert_eq!(3, imported.limit_tracks());
+        assert_eq!(MAX_IMPORT_TRACKS, imported.tracks.len());
+        assert_eq!(0, imported.limit_tracks());
+    }
+
+    #[test]
+    fn test_empty_import() {
+        assert!(matches!(
+            ExportedQueue::import("#EXTM3U\n"),
+            Err(MusicError::BadImport)
+        ));
+        assert!(matches!(
+            ExportedQueue::import("{\"tracks\": 1}"),
+            Err(MusicError::BadImport)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_url_import() {
+        for data in [
+            "https://a\n--exec=touch /tmp/x\n",
+            "#EXTINF:1,a\nfile:///etc/passwd\n",
+            "{\"tracks\": [{\"url\": \"-o /tmp/x\", \"title\": null, \"artist\": null, \"duration\": null}]}",
+            "<playlist><track><location>--version</location></track></playlist>",
+        ] {
+            assert!(
+                matches!(ExportedQueue::import(data), Err(MusicError::BadImport)),
+                "{}",
+                data
+            );
+        }
+    }
+}
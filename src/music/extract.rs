@@ -0,0 +1,286 @@
+use std::fmt::Display;
+use std::process::Stdio;
+
+use serde::Deserialize;
+use songbird::input::error::{Error, Result};
+use songbird::input::Metadata;
+use tokio::process::Command;
+
+use super::cache::youtube_id;
+use super::youtube::music::yt_music_song_search;
+use crate::network::network;
+use crate::CLIENT;
+
+/// yt-dlp options tried in order until one of them works
+#[derive(Debug)]
+pub struct Strategy {
+    pub format: &'static str,
+    /// YouTube client to extract with, the default clients if `None`
+    pub player_client: Option<&'static str>,
+}
+
+/// Other clients are served different formats and signatures, and some of them skip age checks
+pub const STRATEGIES: [Strategy; 4] = [
+    Strategy {
+        format: "webm[abr>0]/bestaudio/best",
+        player_client: None,
+    },
+    Strategy {
+        format: "bestaudio/best",
+        player_client: Some("tv_embedded"),
+    },
+    Strategy {
+        format: "bestaudio/best",
+        player_client: Some("ios"),
+    },
+    Strategy {
+        format: "best",
+        player_client: Some("android"),
+    },
+];
+
+impl Strategy {
+    /// yt-dlp arguments selecting this strategy
+    pub fn args(&self) -> Vec<String> {
+        let mut args = vec!["-f".to_owned(), self.format.to_owned()];
+        if let Some(client) = self.player_client {
+            args.push("--extractor-args".to_owned());
+            args.push(format!("youtube:player_client={}", client));
+        }
+        args
+    }
+}
+
+/// Why yt-dlp could not extract a source
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ExtractError {
+    AgeRestricted,
+    NoResults,
+    /// Extraction is broken until yt-dlp is updated, usually after a YouTube change
+    Outdated,
+    RateLimited,
+    RegionLocked,
+    Unavailable,
+    Other(String),
+}
+
+impl Display for ExtractError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::AgeRestricted => write!(f, "the video is age restricted"),
+            Self::NoResults => write!(f, "no results found"),
+            Self::Outdated => write!(
+                f,
+                "the site changed and yt-dlp can't extract it until it is updated"
+            ),
+            Self::RateLimited => write!(f, "the site is rate limiting the bot, try again later"),
+            Self::RegionLocked => write!(f, "the video is not available in the bot's country"),
+            Self::Unavailable => write!(f, "the video is private or was removed"),
+            Self::Other(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl ExtractError {
+    /// Classify the error output of yt-dlp
+    pub fn classify(output: &str) -> Self {
+        let lower = output.to_lowercase();
+        let any = |patterns: &[&str]| patterns.iter().any(|p| lower.contains(p));
+
+        if any(&["confirm your age", "age-restricted", "age restricted"]) {
+            Self::AgeRestricted
+        } else if any(&["in your country", "geo restrict", "geo-restrict"]) {
+            Self::RegionLocked
+        } else if any(&["http error 429", "too many requests", "not a bot"]) {
+            Self::RateLimited
+        } else if any(&[
+            "private video",
+            "video unavailable",
+            "has been removed",
+            "no longer available",
+            "has been terminated",
+        ]) {
+            Self::Unavailable
+        } else if any(&[
+            "signature extraction failed",
+            "nsig extraction failed",
+            "unable to extract",
+            "requested format is not available",
+        ]) {
+            Self::Outdated
+        } else {
+            Self::Other(output.trim().to_owned())
+        }
+    }
+
+    /// Classify a failed yt-dlp source, `None` if yt-dlp did not run
+    pub fn from_source(e: &Error) -> Option<Self> {
+        match e {
+            Error::Json { parsed_text, error } => {
+                if parsed_text.trim().is_empty() && error.is_eof() {
+                    Some(Self::NoResults)
+                } else {
+                    Some(Self::classify(parsed_text))
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether another strategy could succeed
+    pub fn is_retryable(&self) -> bool {
+        !matches!(
+            self,
+            Self::NoResults | Self::RegionLocked | Self::Unavailable
+        )
+    }
+
+    /// Whether another upload of the same song could be played instead
+    pub fn has_alternate(&self) -> bool {
+        matches!(
+            self,
+            Self::AgeRestricted | Self::RegionLocked | Self::Unavailable
+        )
+    }
+}
+
+/// Get the metadata of a source without streaming it
+pub async fn probe(uri: &str, strategy: &Strategy) -> Result<Metadata> {
+    let output = Command::new("yt-dlp")
+        .arg("-j")
+        .args(strategy.args())
+        .args(network().ytdl_args())
+        .args([
+            "--no-playlist",
+            "--ignore-config",
+            "--no-warnings",
+            "--",
+            uri,
+        ])
+        .stdin(Stdio::null())
+        .output()
+        .await?;
+
+    let end = output
+        .stdout
+        .iter()
+        .position(|b| *b == b'\n')
+        .unwrap_or(output.stdout.len());
+    let value = serde_json::from_slice(&output.stdout[..end]).map_err(|error| Error::Json {
+        error,
+        parsed_text: String::from_utf8_lossy(&output.stderr).into_owned(),
+    })?;
+    Ok(Metadata::from_ytdl_output(value))
+}
+
+/// Search YouTube Music for another upload of a song that can't be played
+pub async fn find_alternate(uri: &str, metadata: Option<&Metadata>) -> Option<String> {
+    let query = match metadata {
+        Some(m) => {
+            let title = m.track.as_ref().or(m.title.as_ref())?;
+            match &m.artist {
+                Some(artist) => format!("{} {}", artist, title),
+                None => title.clone(),
+            }
+        }
+        None => match uri.strip_prefix("ytsearch1:") {
+            Some(query) => query.to_owned(),
+            None => youtube_title(uri).await?,
+        },
+    };
+
+    let url = yt_music_song_search(query).await.ok()?;
+    match (youtube_id(&url), youtube_id(uri)) {
+        (Some(a), Some(b)) if a == b => None,
+        _ => Some(url),
+    }
+}
+
+/// Title and channel of a YouTube video, which are available even if the video can't be played
+async fn youtube_title(url: &str) -> Option<String> {
+    #[derive(Deserialize)]
+    struct OEmbed {
+        title: String,
+        author_name: String,
+    }
+
+    youtube_id(url)?;
+    let text = CLIENT
+        .next()
+        .get("https://www.youtube.com/oembed")
+        .query(&[("url", url), ("format", "json")])
+        .send()
+        .await
+        .ok()?
+        .text()
+        .await
+        .ok()?;
+    let oembed: OEmbed = serde_json::from_str(&text).ok()?;
+    let author = oembed.author_name.trim_end_matches(" - Topic");
+    Some(format!("{} {}", author, oembed.title))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let classify = ExtractError::classify;
+        assert_eq!(
+            classify("ERROR: [youtube] abc: Sign in to confirm your age. This video may be inappropriate for some users."),
+            ExtractError::AgeRestricted
+        );
+        assert_eq!(
+            classify("ERROR: [youtube] abc: The uploader has not made this video available in your country"),
+            ExtractError::RegionLocked
+        );
+        assert_eq!(
+            classify("ERROR: [youtube] abc: Private video. Sign in if you've been granted access to this video"),
+            ExtractError::Unavailable
+        );
+        assert_eq!(
+            classify("ERROR: [youtube] abc: Sign in to confirm you’re not a bot"),
+            ExtractError::RateLimited
+        );
+        assert_eq!(
+            classify("ERROR: [youtube] abc: Requested format is not available"),
+            ExtractError::Outdated
+        );
+        assert_eq!(
+            classify("ERROR: something else\n"),
+            ExtractError::Other("ERROR: something else".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_from_source() {
+        let json_error = |text: &str| Error::Json {
+            error: serde_json::from_str::<serde_json::Value>(text).unwrap_err(),
+            parsed_text: text.to_owned(),
+        };
+        assert_eq!(
+            ExtractError::from_source(&json_error("")),
+            Some(ExtractError::NoResults)
+        );
+        assert_eq!(
+            ExtractError::from_source(&json_error("ERROR: Video unavailable")),
+            Some(ExtractError::Unavailable)
+        );
+        assert_eq!(ExtractError::from_source(&Error::Metadata), None);
+    }
+
+    #[test]
+    fn test_strategy_args() {
+        assert_eq!(STRATEGIES[0].args(), ["-f", "webm[abr>0]/bestaudio/best"]);
+        assert_eq!(
+            STRATEGIES[1].args(),
+            [
+                "-f",
+                "bestaudio/best",
+                "--extractor-args",
+                "youtube:player_client=tv_embedded"
+            ]
+        );
+    }
+}
@@ -0,0 +1,42 @@
+use std::collections::{HashMap, VecDeque};
+
+use poise::serenity_prelude as serenity;
+use serenity::{GuildId, TypeMapKey};
+use songbird::input::Metadata;
+
+/// Number of finished tracks remembered per guild
+const HISTORY_LEN: usize = 20;
+
+/// Recently finished tracks of each guild, most recent last
+pub struct TrackHistoryMap;
+
+impl TypeMapKey for TrackHistoryMap {
+    type Value = HashMap<GuildId, VecDeque<Metadata>>;
+}
+
+pub async fn push_history(ctx: &serenity::Context, guild_id: GuildId, metadata: Metadata) {
+    let mut data = ctx.data.write().await;
+    if let Some(map) = data.get_mut::<TrackHistoryMap>() {
+        let history = map.entry(guild_id).or_default();
+        if history.len() >= HISTORY_LEN {
+            history.pop_front();
+        }
+        history.push_back(metadata);
+    }
+}
+
+/// Recently finished tracks of a guild, most recent last
+pub async fn guild_history(ctx: &serenity::Context, guild_id: GuildId) -> Vec<Metadata> {
+    let data = ctx.data.read().await;
+    data.get::<TrackHistoryMap>()
+        .and_then(|map| map.get(&guild_id))
+        .map(|history| history.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+pub async fn pop_history(ctx: &serenity::Context, guild_id: GuildId) -> Option<Metadata> {
+    let mut data = ctx.data.write().await;
+    data.get_mut::<TrackHistoryMap>()?
+        .get_mut(&guild_id)?
+        .pop_back()
+}
@@ -0,0 +1,319 @@
+use std::time::Duration;
+
+use poise::serenity_prelude as serenity;
+use serenity::{CollectComponentInteraction, CreateComponents, InteractionResponseType, UserId};
+use songbird::tracks::{TrackHandle, TrackQueue};
+
+use super::error::MusicError;
+use super::message::format_queue_page;
+use super::queue::Requester;
+use super::youtube::sponsorblock::SBDuration;
+use crate::PoiseContext;
+
+pub const TRACKS_PER_PAGE: usize = 10;
+/// Buttons are removed after no one has used them for this long
+const INACTIVITY_TIMEOUT: Duration = Duration::from_secs(120);
+/// Discord limits select menus to 25 options
+const MAX_PAGE_OPTIONS: usize = 25;
+
+/// Number of a track in the list for a queue index, the current track is 1
+pub fn track_number(idx: usize) -> usize {
+    idx + 1
+}
+
+/// Queue index of a track number shown in the list
+pub fn track_index(number: usize) -> Option<usize> {
+    number.checked_sub(1)
+}
+
+/// Track information needed to display the queue
+pub struct QueuedTrack {
+    pub handle: TrackHandle,
+    pub requester: Option<UserId>,
+    pub sb_duration: Option<Duration>,
+}
+
+impl QueuedTrack {
+    /// Duration of the track with SponsorBlock segments removed
+    pub fn play_duration(&self) -> Option<Duration> {
+        let duration = self.handle.metadata().duration?;
+        Some(duration.saturating_sub(self.sb_duration.unwrap_or_default()))
+    }
+}
+
+/// State of the queue at the time it was listed
+pub struct QueueSnapshot {
+    pub tracks: Vec<QueuedTrack>,
+    pub elapsed: Duration,
+}
+
+impl QueueSnapshot {
+    pub async fn new(queue: &TrackQueue) -> Self {
+        let mut tracks = vec![];
+        for handle in queue.current_queue() {
+            let typemap = handle.typemap().read().await;
+            let requester = typemap.get::<Requester>().copied();
+            let sb_duration = typemap.get::<SBDuration>().copied().flatten();
+            drop(typemap);
+            tracks.push(QueuedTrack {
+                handle,
+                requester,
+                sb_duration,
+            });
+        }
+
+        let elapsed = match tracks.first() {
+            Some(t) => t
+                .handle
+                .get_info()
+                .await
+                .map(|info| info.position)
+                .unwrap_or_default(),
+            None => Duration::ZERO,
+        };
+
+        Self { tracks, elapsed }
+    }
+
+    pub fn num_pages(&self) -> usize {
+        std::cmp::max(1, self.tracks.len().div_ceil(TRACKS_PER_PAGE))
+    }
+
+    /// Time until the queue finishes playing, and whether the duration of some tracks is unknown
+    pub fn remaining(&self) -> (Duration, bool) {
+        let mut unknown = false;
+        let total: Duration = self
+            .tracks
+            .iter()
+            .filter_map(|t| {
+                let d = t.play_duration();
+                unknown |= d.is_none();
+                d
+            })
+            .sum();
+        (total.saturating_sub(self.elapsed), unknown)
+    }
+}
+
+/// Show the queue as an embed with buttons to change pages
+pub async fn list_queue(
+    ctx: PoiseContext<'_>,
+    queue: TrackQueue,
+    page: usize,
+) -> Result<(), MusicError> {
+    let ids = ComponentIds::new(ctx.id());
+
+    let mut snapshot = QueueSnapshot::new(&queue).await;
+    let mut page = page.min(snapshot.num_pages() - 1);
+
+    let reply = ctx
+        .send(|m| {
+            m.embed(|e| {
+                format_queue_page(&snapshot, page)(e);
+                e
+            })
+            .components(|c| ids.build(c, page, snapshot.num_pages()))
+        })
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?;
+    let message_id = reply
+        .message()
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?
+        .id;
+
+    loop {
+        let prefix = ids.prefix.clone();
+        let interaction = CollectComponentInteraction::new(ctx)
+            .message_id(message_id)
+            .filter(move |ci| ci.data.custom_id.starts_with(&prefix))
+            .timeout(INACTIVITY_TIMEOUT)
+            .await;
+        let interaction = match interaction {
+            Some(i) => i,
+            None => break,
+        };
+
+        // Refresh the queue so the page is up to date
+        snapshot = QueueSnapshot::new(&queue).await;
+        let num_pages = snapshot.num_pages();
+        page = ids
+            .target_page(
+                &interaction.data.custom_id,
+                &interaction.data.values,
+                page,
+                num_pages,
+            )
+            .min(num_pages - 1);
+
+        let _ = interaction
+            .create_interaction_response(ctx, |r| {
+                r.kind(InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|d| {
+                        d.embed(|e| {
+                            format_queue_page(&snapshot, page)(e);
+                            e
+                        })
+                        .components(|c| ids.build(c, page, num_pages))
+                    })
+            })
+            .await;
+    }
+
+    // Remove components once they expire
+    let _ = reply.edit(ctx, |m| m.components(|c| c)).await;
+
+    Ok(())
+}
+
+/// Custom IDs of the components attached to a single list message
+struct ComponentIds {
+    prefix: String,
+    first: String,
+    prev: String,
+    next: String,
+    last: String,
+    select: String,
+}
+
+impl ComponentIds {
+    fn new(id: u64) -> Self {
+        let prefix = format!("list-{}-", id);
+        Self {
+            first: format!("{}first", prefix),
+            prev: format!("{}prev", prefix),
+            next: format!("{}next", prefix),
+            last: format!("{}last", prefix),
+            select: format!("{}select", prefix),
+            prefix,
+        }
+    }
+
+    fn target_page(&self, id: &str, values: &[String], page: usize, num_pages: usize) -> usize {
+        if id == self.first {
+            0
+        } else if id == self.prev {
+            page.saturating_sub(1)
+        } else if id == self.next {
+            page + 1
+        } else if id == self.last {
+            num_pages - 1
+        } else if id == self.select {
+            values.first().and_then(|v| v.parse().ok()).unwrap_or(page)
+        } else {
+            page
+        }
+    }
+
+    fn build<'a>(
+        &self,
+        c: &'a mut CreateComponents,
+        page: usize,
+        num_pages: usize,
+    ) -> &'a mut CreateComponents {
+        if num_pages <= 1 {
+            return c;
+        }
+
+        c.create_action_row(|ar| {
+            for (id, label, disabled) in [
+                (&self.first, "First", page == 0),
+                (&self.prev, "Prev", page == 0),
+                (&self.next, "Next", page + 1 >= num_pages),
+                (&self.last, "Last", page + 1 >= num_pages),
+            ] {
+                ar.create_button(|b| {
+                    b.style(serenity::ButtonStyle::Secondary)
+                        .label(label)
+                        .custom_id(id)
+                        .disabled(disabled)
+                });
+            }
+            ar
+        });
+
+        // Show the pages around the current page if there are too many to select from
+        let start = page
+            .saturating_sub(MAX_PAGE_OPTIONS / 2)
+            .min(num_pages.saturating_sub(MAX_PAGE_OPTIONS));
+        let end = (start + MAX_PAGE_OPTIONS).min(num_pages);
+        c.create_action_row(|ar| {
+            ar.create_select_menu(|m| {
+                m.custom_id(&self.select)
+                    .placeholder("Jump to page")
+                    .options(|o| {
+                        for p in start..end {
+                            o.create_option(|opt| {
+                                opt.label(format!("Page {}", p + 1))
+                                    .value(p)
+                                    .default_selection(p == page)
+                            });
+                        }
+                        o
+                    })
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serenity::CreateEmbed;
+    use songbird::input::{Codec, Container, Input, Metadata, Reader};
+
+    use super::*;
+
+    fn queued_track(title: &str) -> QueuedTrack {
+        let metadata = Metadata {
+            title: Some(title.to_owned()),
+            ..Default::default()
+        };
+        let input = Input::new(
+            true,
+            Reader::from_memory(vec![]),
+            Codec::FloatPcm,
+            Container::Raw,
+            Some(metadata),
+        );
+        QueuedTrack {
+            handle: songbird::create_player(input).1,
+            requester: None,
+            sb_duration: None,
+        }
+    }
+
+    #[test]
+    fn test_list_numbers_match_indices() {
+        let snapshot = QueueSnapshot {
+            tracks: ["a", "b", "c"].into_iter().map(queued_track).collect(),
+            elapsed: Duration::ZERO,
+        };
+        let mut embed = CreateEmbed::default();
+        format_queue_page(&snapshot, 0)(&mut embed);
+        let description = embed.0["description"].as_str().unwrap().to_owned();
+
+        // The number shown for each track refers to that track in remove, skipto and the web API
+        for (idx, title) in ["a", "b", "c"].into_iter().enumerate() {
+            let line = description
+                .lines()
+                .find(|l| l.contains(&format!(" {} [", title)))
+                .unwrap();
+            let number: usize = line.split_once(". ").unwrap().0.parse().unwrap();
+            assert_eq!(track_number(idx), number);
+            assert_eq!(Some(idx), track_index(number));
+        }
+        assert_eq!(None, track_index(0));
+    }
+
+    #[test]
+    fn test_target_page() {
+        let ids = ComponentIds::new(1);
+        assert_eq!(0, ids.target_page("list-1-first", &[], 3, 5));
+        assert_eq!(2, ids.target_page("list-1-prev", &[], 3, 5));
+        assert_eq!(0, ids.target_page("list-1-prev", &[], 0, 5));
+        assert_eq!(4, ids.target_page("list-1-next", &[], 3, 5));
+        assert_eq!(4, ids.target_page("list-1-last", &[], 0, 5));
+        assert_eq!(2, ids.target_page("list-1-select", &["2".to_owned()], 0, 5));
+        assert_eq!(3, ids.target_page("list-2-first", &[], 3, 5));
+    }
+}
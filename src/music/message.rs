@@ -8,6 +8,10 @@ use serenity::Color;
 use songbird::tracks::TrackHandle;
 use std::sync::LazyLock;
 
+use super::list::{track_number, QueueSnapshot, TRACKS_PER_PAGE};
+use super::source::AlternateUpload;
+use super::stats::PlayStats;
+use super::youtube::music::{artist_names, Album, AlbumResult, Artist};
 use super::youtube::sponsorblock::SBDuration;
 use crate::message::{EMBED_COLOR, EMBED_PLAYING_COLOR};
 
@@ -17,6 +21,10 @@ pub enum PlayUpdate {
     Play(TrackHandle, usize),
     Pause(TrackHandle),
     Resume(TrackHandle),
+    AutoPause(TrackHandle),
+    AutoResume(TrackHandle),
+    Previous(TrackHandle),
+    Replay(TrackHandle),
     Skip(TrackHandle),
     Remove(TrackHandle),
     Stop,
@@ -28,6 +36,7 @@ impl PlayUpdate {
         // Extract the sponsorblock duration first because we can't call async functions in the
         // returned closure
         let sb_duration = self.sb_duration().await;
+        let alternate = self.alternate().await;
 
         Box::new(move |e| {
             // Embed color
@@ -64,6 +73,16 @@ impl PlayUpdate {
                 if let Some(url) = &track.metadata().thumbnail {
                     e.thumbnail(url);
                 }
+
+                // Explain why the link may be different from what was requested
+                if let Some(alternate) = alternate {
+                    e.footer(|f| {
+                        f.text(format!(
+                            "Playing another upload, {}: {}",
+                            alternate.reason, alternate.url
+                        ))
+                    });
+                }
             }
         })
     }
@@ -75,6 +94,10 @@ impl PlayUpdate {
             Self::Play(_, _) => "Playing",
             Self::Pause(_) => "Paused",
             Self::Resume(_) => "Resumed",
+            Self::AutoPause(_) => "Paused, no one is listening",
+            Self::AutoResume(_) => "Resumed, welcome back",
+            Self::Previous(_) => "Going back to",
+            Self::Replay(_) => "Replaying",
             Self::Skip(_) => "Skipped",
             Self::Remove(_) => "Removed",
             Self::Stop => "Stopped",
@@ -160,6 +183,10 @@ impl PlayUpdate {
             Self::Play(t, _) => Some(t.clone()),
             Self::Pause(t) => Some(t.clone()),
             Self::Resume(t) => Some(t.clone()),
+            Self::AutoPause(t) => Some(t.clone()),
+            Self::AutoResume(t) => Some(t.clone()),
+            Self::Previous(t) => Some(t.clone()),
+            Self::Replay(t) => Some(t.clone()),
             Self::Skip(t) => Some(t.clone()),
             Self::Remove(t) => Some(t.clone()),
             _ => None,
@@ -178,6 +205,13 @@ impl PlayUpdate {
         None
     }
 
+    /// Another upload that is played instead of the requested one
+    async fn alternate(&self) -> Option<AlternateUpload> {
+        let track_handle = self.track_handle()?;
+        let typemap = track_handle.typemap().read().await;
+        typemap.get::<AlternateUpload>()?.get().cloned()
+    }
+
     /// Color of Discord message embed
     fn color(&self) -> Color {
         match self {
@@ -193,16 +227,25 @@ pub fn format_add_playlist<'a>(
     num_queued_tracks: usize,
     total_tracks: usize,
     finished: bool,
+) -> Box<dyn FnOnce(&mut CreateEmbed) + Send + Sync + 'a> {
+    let title = if finished {
+        format!(
+            "Finished Queuing {}/{} tracks",
+            num_queued_tracks, total_tracks
+        )
+    } else {
+        format!("Queuing {}/{} tracks", num_queued_tracks, total_tracks)
+    };
+    format_track_summary(title, tracks, num_queued_tracks)
+}
+
+/// Formats a Discord message embed listing some of the tracks affected by a queue operation
+pub fn format_track_summary<'a>(
+    title: String,
+    tracks: impl ExactSizeIterator<Item = TrackHandle> + Send + Sync + 'a,
+    num_tracks: usize,
 ) -> Box<dyn FnOnce(&mut CreateEmbed) + Send + Sync + 'a> {
     Box::new(move |e| {
-        let title = if finished {
-            format!(
-                "Finished Queuing {}/{} tracks",
-                num_queued_tracks, total_tracks
-            )
-        } else {
-            format!("Queuing {}/{} tracks", num_queued_tracks, total_tracks)
-        };
         let title = mdast::Node::Text(mdast::Text {
             value: title,
             position: None,
@@ -215,11 +258,11 @@ pub fn format_add_playlist<'a>(
         e.title(title);
 
         let mut description = Vec::with_capacity(tracks.len() + 1);
-        if num_queued_tracks > tracks.len() {
+        if num_tracks > tracks.len() {
             description.push(mdast::Node::Paragraph(mdast::Paragraph {
                 children: vec![mdast::Node::Emphasis(mdast::Emphasis {
                     children: vec![mdast::Node::Text(mdast::Text {
-                        value: format!("{} tracks omitted", num_queued_tracks - tracks.len()),
+                        value: format!("{} tracks omitted", num_tracks - tracks.len()),
                         position: None,
                     })],
                     position: None,
@@ -236,6 +279,346 @@ pub fn format_add_playlist<'a>(
     })
 }
 
+/// Formats a Discord message embed showing one page of the queue
+pub fn format_queue_page<'a>(
+    snapshot: &'a QueueSnapshot,
+    page: usize,
+) -> Box<dyn FnOnce(&mut CreateEmbed) + Send + Sync + 'a> {
+    Box::new(move |e| {
+        e.color(*EMBED_COLOR);
+        e.title(match snapshot.tracks.len() {
+            0 => "Queue is empty".to_owned(),
+            1 => "1 track in queue".to_owned(),
+            n => format!("{} tracks in queue", n),
+        });
+
+        let mut lines = vec![];
+
+        // Currently playing track with elapsed time
+        if let Some(current) = snapshot.tracks.first() {
+            if page == 0 {
+                let elapsed = format_duration(snapshot.elapsed);
+                let duration = match current.play_duration() {
+                    Some(d) => format_duration(d),
+                    None => "?".to_owned(),
+                };
+                let mut children = vec![
+                    mdast::Node::Text(mdast::Text {
+                        value: format!("{}. ", track_number(0)),
+                        position: None,
+                    }),
+                    mdast::Node::Strong(mdast::Strong {
+                        children: vec![mdast::Node::Text(mdast::Text {
+                            value: "Now playing: ".to_owned(),
+                            position: None,
+                        })],
+                        position: None,
+                    }),
+                ];
+                children.push(format_track_link(&current.handle));
+                children.push(mdast::Node::Text(mdast::Text {
+                    value: format!(" [{}/{}]", elapsed, duration),
+                    position: None,
+                }));
+                if let Some(user) = current.requester {
+                    children.push(mdast::Node::Text(mdast::Text {
+                        value: format!(" <@{}>", user),
+                        position: None,
+                    }));
+                }
+                lines.push(mdast::Node::Paragraph(mdast::Paragraph {
+                    children,
+                    position: None,
+                }));
+            }
+        }
+
+        // Upcoming tracks
+        let start = std::cmp::max(1, page * TRACKS_PER_PAGE);
+        let end = (page + 1) * TRACKS_PER_PAGE;
+        for (i, track) in snapshot.tracks.iter().enumerate().take(end).skip(start) {
+            let duration = match track.play_duration() {
+                Some(d) => format_duration(d),
+                None => "?".to_owned(),
+            };
+            let mut children = vec![mdast::Node::Text(mdast::Text {
+                value: format!("{}. ", track_number(i)),
+                position: None,
+            })];
+            children.push(format_track_link(&track.handle));
+            children.push(mdast::Node::Text(mdast::Text {
+                value: format!(" [{}]", duration),
+                position: None,
+            }));
+            if let Some(user) = track.requester {
+                children.push(mdast::Node::Text(mdast::Text {
+                    value: format!(" <@{}>", user),
+                    position: None,
+                }));
+            }
+            lines.push(mdast::Node::Paragraph(mdast::Paragraph {
+                children,
+                position: None,
+            }));
+        }
+
+        let description = lines
+            .iter()
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        e.description(description);
+
+        // Page and remaining time
+        let (remaining, unknown) = snapshot.remaining();
+        let mut footer = format!(
+            "Page {}/{} • {} remaining",
+            page + 1,
+            snapshot.num_pages(),
+            format_duration(remaining)
+        );
+        if unknown {
+            footer.push_str(" (some durations unknown)");
+        }
+        e.footer(|f| f.text(footer));
+    })
+}
+
+/// Formats a Discord message embed listing albums to choose from
+pub fn format_album_results<'a>(
+    albums: &'a [(AlbumResult, Album)],
+) -> Box<dyn FnOnce(&mut CreateEmbed) + Send + Sync + 'a> {
+    Box::new(move |e| {
+        e.color(*EMBED_COLOR);
+        e.title("Choose an album");
+        let lines: Vec<_> = albums
+            .iter()
+            .enumerate()
+            .map(|(i, (result, album))| {
+                format!(
+                    "{}. {} — {}",
+                    i + 1,
+                    album.title,
+                    format_album_details(result, album)
+                )
+            })
+            .collect();
+        e.description(lines.join("\n"));
+    })
+}
+
+/// Formats a Discord message embed listing the tracks of an album
+pub fn format_album<'a>(
+    result: &'a AlbumResult,
+    album: &'a Album,
+) -> Box<dyn FnOnce(&mut CreateEmbed) + Send + Sync + 'a> {
+    const MAX_TRACKS: usize = 50;
+
+    Box::new(move |e| {
+        e.color(*EMBED_COLOR);
+        e.title(&album.title);
+        if let Some(thumbnail) = album.thumbnails.last() {
+            e.thumbnail(&thumbnail.url);
+        }
+
+        let mut lines = vec![format_album_details(result, album), String::new()];
+        for (i, track) in album.tracks.iter().enumerate().take(MAX_TRACKS) {
+            let mut line = format!("{}. {}", i + 1, track.title);
+            if let Some(secs) = track.duration_seconds {
+                let _ = write!(line, " [{}]", format_duration(Duration::from_secs(secs)));
+            }
+            if track.video_id.is_none() {
+                line.push_str(" (unavailable)");
+            }
+            lines.push(line);
+        }
+        if album.tracks.len() > MAX_TRACKS {
+            lines.push(format!(
+                "{} tracks omitted",
+                album.tracks.len() - MAX_TRACKS
+            ));
+        }
+        e.description(lines.join("\n"));
+    })
+}
+
+/// Returns "artist • type • year • n tracks", leaving out anything unknown
+pub fn format_album_details(result: &AlbumResult, album: &Album) -> String {
+    let artists = if album.artists.is_empty() {
+        &result.artists
+    } else {
+        &album.artists
+    };
+    let track_count = album.track_count.unwrap_or(album.tracks.len());
+    let track_count = match track_count {
+        1 => "1 track".to_owned(),
+        n => format!("{} tracks", n),
+    };
+    [
+        artist_names(artists),
+        result.album_type.clone(),
+        album.year.clone().or_else(|| result.year.clone()),
+        Some(track_count),
+    ]
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>()
+    .join(" • ")
+}
+
+/// Formats a Discord message embed showing an artist's top songs, albums and singles
+pub fn format_artist_page<'a>(
+    artist: &'a Artist,
+    max_items: usize,
+) -> Box<dyn FnOnce(&mut CreateEmbed) + Send + Sync + 'a> {
+    Box::new(move |e| {
+        e.color(*EMBED_COLOR);
+        e.title(&artist.name);
+        if let Some(thumbnail) = artist.thumbnails.last() {
+            e.thumbnail(&thumbnail.url);
+        }
+
+        let numbered = |lines: Vec<String>| {
+            lines
+                .into_iter()
+                .enumerate()
+                .map(|(i, line)| format!("{}. {}", i + 1, line))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let albums = |albums: &[AlbumResult]| {
+            albums
+                .iter()
+                .take(max_items)
+                .map(|a| match &a.year {
+                    Some(year) => format!("{} ({})", a.title, year),
+                    None => a.title.clone(),
+                })
+                .collect()
+        };
+        let songs: Vec<_> = artist
+            .songs()
+            .iter()
+            .take(max_items)
+            .map(|s| s.title.clone())
+            .collect();
+
+        for (name, lines) in [
+            ("Top songs", songs),
+            ("Albums", albums(artist.albums())),
+            ("Singles", albums(artist.singles())),
+        ] {
+            if !lines.is_empty() {
+                e.field(name, numbered(lines), false);
+            }
+        }
+    })
+}
+
+/// Formats a Discord message embed showing the leaderboards and totals of played tracks
+pub fn format_stats<'a>(
+    title: String,
+    stats: &'a PlayStats,
+) -> Box<dyn FnOnce(&mut CreateEmbed) + Send + Sync + 'a> {
+    Box::new(move |e| {
+        e.color(*EMBED_COLOR);
+        e.title(title);
+
+        if stats.totals.plays == 0 {
+            e.description("No tracks played yet");
+            return;
+        }
+
+        let tracks = stats.top_tracks.iter().map(|t| {
+            let artist = t.artist.clone().unwrap_or_else(|| "Unknown".into());
+            vec![
+                mdast::Node::Text(mdast::Text {
+                    value: format!("{} — ", artist),
+                    position: None,
+                }),
+                mdast::Node::Strong(mdast::Strong {
+                    children: vec![mdast::Node::Text(mdast::Text {
+                        value: t.title.clone(),
+                        position: None,
+                    })],
+                    position: None,
+                }),
+                format_play_count(t.plays),
+            ]
+        });
+        let artists = stats.top_artists.iter().map(|a| {
+            vec![
+                mdast::Node::Text(mdast::Text {
+                    value: a.name.clone(),
+                    position: None,
+                }),
+                format_play_count(a.plays),
+            ]
+        });
+        let requesters = stats.top_requesters.iter().map(|r| {
+            vec![
+                mdast::Node::Text(mdast::Text {
+                    value: format!("<@{}>", r.name),
+                    position: None,
+                }),
+                format_play_count(r.plays),
+            ]
+        });
+
+        for (name, lines) in [
+            ("Top tracks", format_leaderboard(tracks)),
+            ("Top artists", format_leaderboard(artists)),
+            ("Top requesters", format_leaderboard(requesters)),
+        ] {
+            if !lines.is_empty() {
+                e.field(name, lines, false);
+            }
+        }
+
+        let totals = &stats.totals;
+        e.field(
+            "Listening time",
+            format!("{:.1} hours", totals.listening_hours()),
+            true,
+        );
+        e.field("Plays", totals.plays, true);
+        e.field("Skip rate", format!("{:.0}%", totals.skip_rate()), true);
+    })
+}
+
+/// Numbers each line of a leaderboard
+fn format_leaderboard(entries: impl Iterator<Item = Vec<mdast::Node>>) -> String {
+    entries
+        .enumerate()
+        .map(|(i, mut children)| {
+            children.insert(
+                0,
+                mdast::Node::Text(mdast::Text {
+                    value: format!("{}. ", i + 1),
+                    position: None,
+                }),
+            );
+            mdast::Node::Paragraph(mdast::Paragraph {
+                children,
+                position: None,
+            })
+            .to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn format_play_count(plays: i64) -> mdast::Node {
+    let value = match plays {
+        1 => " (1 play)".to_owned(),
+        n => format!(" ({} plays)", n),
+    };
+    mdast::Node::Text(mdast::Text {
+        value,
+        position: None,
+    })
+}
+
 /// Returns "artist — title"
 fn format_track_link(track: &TrackHandle) -> mdast::Node {
     let title = track
@@ -349,7 +732,7 @@ fn format_track_duration(track: &TrackHandle, sb_time: Option<Duration>) -> mdas
     })
 }
 
-fn format_duration(t: Duration) -> String {
+pub fn format_duration(t: Duration) -> String {
     // compute hours mins secs
     let total_secs = t.as_secs();
     let secs = total_secs % 60;
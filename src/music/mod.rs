@@ -1,11 +1,50 @@
+mod announce;
+mod browse;
+mod bus;
+mod cache;
 pub mod commands;
+mod database;
 mod error;
 mod events;
+mod export;
+mod extract;
+mod history;
+mod list;
 mod message;
+mod playlist;
+mod prefetch;
 mod queue;
+mod resolve;
+mod schedule;
+mod scrobble;
+mod settings;
+mod sleep;
+mod source;
+mod stats;
+mod tracker;
 mod voice;
+mod web;
 mod youtube;
 
+pub use announce::{start_announcer, NotificationMap};
 pub use error::MusicError;
-pub use events::handle_voice_state_event;
+pub use events::{handle_channel_delete, handle_voice_state_event, join_always_on_channels};
+pub use history::TrackHistoryMap;
 pub use queue::QueueMutexMap;
+pub use schedule::{start_schedules, ScheduleTaskMap};
+pub use scrobble::{start_scrobbler, ScrobbleClient, LASTFM_API_URL, LISTENBRAINZ_API_URL};
+pub use settings::{check_music_channel, load_settings, GuildSettingsMap};
+pub use sleep::SleepTimerMap;
+pub use stats::start_stats_recorder;
+pub use tracker::VoiceTrackerMap;
+pub use web::start_web_server;
+
+/// Create the database tables used by the music commands
+pub async fn create_tables(db_uri: &str) -> anyhow::Result<()> {
+    let db = database::connect(db_uri).await?;
+    settings::create_table(db).await?;
+    schedule::create_table(db).await?;
+    scrobble::create_table(db).await?;
+    stats::create_table(db).await?;
+    cache::init(db).await
+}
@@ -0,0 +1,439 @@
+use std::time::Duration;
+
+use poise::serenity_prelude::{ButtonStyle, CollectComponentInteraction};
+use serde::Deserialize;
+use songbird::input::Metadata;
+use tokio::process::Command;
+use url::Url;
+
+use super::error::MusicError;
+use super::queue::{add_tracks_at, Placement, Query};
+use super::youtube::music::yt_music_song_search;
+use crate::network::network;
+use crate::PoiseContext;
+
+/// Auto-generated mixes go on practically forever, so only their start is queued
+pub const MAX_MIX_LENGTH: usize = 25;
+/// How long to wait for a choice of what to queue from a playlist link
+const LIST_CHOICE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Output of `yt-dlp --flat-playlist --dump-single-json`
+#[derive(Debug, Deserialize)]
+struct FlatPlaylist {
+    #[serde(rename = "_type")]
+    kind: Option<String>,
+    #[serde(default)]
+    entries: Vec<PlaylistEntry>,
+}
+
+/// A single playlist entry, only containing the information yt-dlp provides without resolving
+/// the entry itself
+#[derive(Clone, Debug, Deserialize)]
+pub struct PlaylistEntry {
+    pub id: String,
+    pub url: Option<String>,
+    pub webpage_url: Option<String>,
+    pub ie_key: Option<String>,
+    pub title: Option<String>,
+    pub duration: Option<f64>,
+    pub uploader: Option<String>,
+    pub channel: Option<String>,
+    #[serde(default)]
+    pub thumbnails: Vec<Thumbnail>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Thumbnail {
+    pub url: String,
+}
+
+impl PlaylistEntry {
+    /// URL that can be passed back to yt-dlp to resolve this entry
+    pub fn source_url(&self) -> String {
+        if let Some(url) = self.webpage_url.as_ref().or(self.url.as_ref()) {
+            return url.clone();
+        }
+        match self.ie_key.as_deref() {
+            Some("Youtube") | None => format!("https://www.youtube.com/watch?v={}", self.id),
+            Some(_) => self.id.clone(),
+        }
+    }
+
+    /// Track metadata known before the entry is resolved
+    pub fn metadata(&self) -> Metadata {
+        Metadata {
+            title: self.title.clone(),
+            artist: self.uploader.clone().or_else(|| self.channel.clone()),
+            channel: self.channel.clone(),
+            duration: self.duration.map(Duration::from_secs_f64),
+            source_url: Some(self.source_url()),
+            thumbnail: self.thumbnails.last().map(|t| t.url.clone()),
+            channels: Some(2),
+            ..Default::default()
+        }
+    }
+}
+
+/// A YouTube video link that also names the playlist or mix it was opened from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ListLink {
+    pub url: String,
+    pub video_id: String,
+    pub list_id: String,
+}
+
+/// What to queue from a video link with a playlist
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListChoice {
+    Track,
+    Playlist,
+    FromTrack,
+}
+
+impl ListLink {
+    /// Parse `watch?v=...&list=...` links, including `youtu.be` and YouTube Music links
+    pub fn parse(url: &Url) -> Option<Self> {
+        let video_id = match url.host_str()? {
+            "youtu.be" => url.path_segments()?.next()?.to_owned(),
+            "youtube.com" | "www.youtube.com" | "m.youtube.com" | "music.youtube.com"
+                if url.path() == "/watch" =>
+            {
+                query_param(url, "v")?
+            }
+            _ => return None,
+        };
+        let list_id = query_param(url, "list")?;
+        if video_id.is_empty() || list_id.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            url: url.to_string(),
+            video_id,
+            list_id,
+        })
+    }
+
+    /// Whether the list is a mix generated by YouTube rather than a playlist someone made
+    pub fn is_mix(&self) -> bool {
+        // RDCLAK lists are curated YouTube Music playlists despite the prefix
+        self.list_id.starts_with("RD") && !self.list_id.starts_with("RDCLAK")
+    }
+
+    /// Link to the video on its own
+    pub fn video_url(&self) -> String {
+        format!("https://www.youtube.com/watch?v={}", self.video_id)
+    }
+
+    /// The entries to queue for a choice, capping mixes to `MAX_MIX_LENGTH`
+    fn select(&self, entries: Vec<PlaylistEntry>, choice: ListChoice) -> Vec<PlaylistEntry> {
+        let mut entries = match choice {
+            ListChoice::Track => vec![],
+            ListChoice::Playlist => entries,
+            ListChoice::FromTrack => match entries.iter().position(|e| e.id == self.video_id) {
+                Some(start) => entries.into_iter().skip(start).collect(),
+                None => entries,
+            },
+        };
+        if self.is_mix() {
+            entries.truncate(MAX_MIX_LENGTH);
+        }
+        entries
+    }
+
+    /// Queries for a choice, expanding the playlist unless only the track is wanted
+    pub async fn queries(&self, choice: ListChoice) -> Result<Vec<Query>, MusicError> {
+        if choice == ListChoice::Track {
+            return Ok(vec![Query::Url(self.video_url())]);
+        }
+        let entries = get_playlist_entries(&self.url).await?;
+        Ok(self
+            .select(entries, choice)
+            .iter()
+            .map(|e| Query::Known(e.metadata()))
+            .collect())
+    }
+}
+
+fn query_param(url: &Url, key: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.into_owned())
+}
+
+/// Add all entries of a playlist, album or set from any site supported by yt-dlp
+///
+/// Returns `MusicError::BadPlaylist` if the URL does not point to a playlist
+pub async fn add_playlist(
+    ctx: PoiseContext<'_>,
+    url: &str,
+    placement: Placement,
+) -> Result<(), MusicError> {
+    let entries = get_playlist_entries(url).await?;
+    let num_tracks = entries.len();
+
+    let stream = futures::stream::iter(entries.into_iter().map(|e| Query::Known(e.metadata())));
+    add_tracks_at(ctx, stream, num_tracks, placement).await
+}
+
+/// Ask whether to queue the track, the playlist, or the playlist from the track, then add them
+///
+/// Only the track is queued if no one answers
+pub async fn add_list_link(
+    ctx: PoiseContext<'_>,
+    link: &ListLink,
+    placement: Placement,
+) -> Result<(), MusicError> {
+    let prefix = format!("list-link-{}-", ctx.id());
+    let playlist_label = if link.is_mix() {
+        format!("Mix (first {})", MAX_MIX_LENGTH)
+    } else {
+        "Whole playlist".to_owned()
+    };
+    let choices = [
+        (ListChoice::Track, "Just this track".to_owned()),
+        (ListChoice::Playlist, playlist_label),
+        (ListChoice::FromTrack, "Playlist from this track".to_owned()),
+    ];
+
+    let reply = ctx
+        .send(|m| {
+            m.content("This link is part of a playlist, what should be queued?")
+                .components(|c| {
+                    c.create_action_row(|ar| {
+                        for (i, (_, label)) in choices.iter().enumerate() {
+                            ar.create_button(|b| {
+                                b.style(ButtonStyle::Primary)
+                                    .label(label)
+                                    .custom_id(format!("{}{}", prefix, i))
+                            });
+                        }
+                        ar
+                    })
+                })
+        })
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?;
+    let message_id = reply
+        .message()
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?
+        .id;
+
+    let filter_prefix = prefix.clone();
+    let interaction = CollectComponentInteraction::new(ctx)
+        .message_id(message_id)
+        .author_id(ctx.author().id)
+        .filter(move |ci| ci.data.custom_id.starts_with(&filter_prefix))
+        .timeout(LIST_CHOICE_TIMEOUT)
+        .await;
+    // Fall back to the first choice, the track alone
+    let (choice, label) = interaction
+        .as_ref()
+        .and_then(|i| i.data.custom_id.strip_prefix(&prefix))
+        .and_then(|i| i.parse::<usize>().ok())
+        .and_then(|i| choices.get(i))
+        .unwrap_or(&choices[0]);
+    if let Some(interaction) = interaction {
+        let _ = interaction.defer(ctx).await;
+    }
+    let _ = reply
+        .edit(ctx, |m| {
+            m.content(format!("Queueing: {}", label.to_lowercase()))
+                .components(|c| c)
+        })
+        .await;
+
+    let queries = link.queries(*choice).await?;
+    let num_tracks = queries.len();
+    add_tracks_at(ctx, futures::stream::iter(queries), num_tracks, placement).await
+}
+
+/// Queries for a URL or YouTube Music search outside of a command, expanding playlists
+pub async fn resolve_queries(query: &str) -> Result<Vec<Query>, MusicError> {
+    if let Ok(url) = Url::parse(query) {
+        // Without anyone to ask, queue the whole list, capping mixes
+        if let Some(link) = ListLink::parse(&url) {
+            return link.queries(ListChoice::Playlist).await;
+        }
+        match get_playlist_entries(query).await {
+            Ok(entries) => Ok(entries.iter().map(|e| Query::Known(e.metadata())).collect()),
+            Err(MusicError::BadPlaylist) => Ok(vec![Query::Url(query.to_owned())]),
+            Err(e) => Err(e),
+        }
+    } else {
+        let url = yt_music_song_search(query.to_owned()).await?;
+        Ok(vec![Query::Url(url)])
+    }
+}
+
+/// Expand a playlist URL into its entries
+pub async fn get_playlist_entries(url: &str) -> Result<Vec<PlaylistEntry>, MusicError> {
+    // Avoid running yt-dlp for URLs that can never be a playlist
+    if let Ok(parsed) = Url::parse(url) {
+        if is_single_youtube_video(&parsed) {
+            return Err(MusicError::BadPlaylist);
+        }
+    }
+
+    // Get playlist items via yt-dlp
+    let output = Command::new("yt-dlp")
+        .arg("--ignore-config")
+        .arg("--no-warnings")
+        .arg("--dump-single-json")
+        .arg("--flat-playlist")
+        .args(network().ytdl_args())
+        .arg("--")
+        .arg(url)
+        .output()
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?;
+    if !output.status.success() {
+        return Err(MusicError::BadPlaylist);
+    }
+
+    parse_flat_playlist(&output.stdout)
+}
+
+fn parse_flat_playlist(output: &[u8]) -> Result<Vec<PlaylistEntry>, MusicError> {
+    let playlist: FlatPlaylist =
+        serde_json::from_slice(output).map_err(|_| MusicError::BadPlaylist)?;
+
+    // Single tracks are reported as videos, not playlists
+    if playlist.kind.as_deref() != Some("playlist") || playlist.entries.is_empty() {
+        return Err(MusicError::BadPlaylist);
+    }
+
+    Ok(playlist.entries)
+}
+
+/// Plain YouTube video links without a list parameter
+fn is_single_youtube_video(url: &Url) -> bool {
+    let has_list = url.query_pairs().any(|(k, _)| k == "list");
+    match url.host_str() {
+        Some("youtu.be") => !has_list,
+        Some("youtube.com" | "www.youtube.com" | "m.youtube.com" | "music.youtube.com") => {
+            url.path() == "/watch" && !has_list
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_soundcloud_set() {
+        let output = br#"{
+            "_type": "playlist",
+            "title": "set",
+            "entries": [
+                {"_type": "url", "ie_key": "Soundcloud", "id": "1", "url": "https://api.soundcloud.com/tracks/1", "title": "one", "duration": 61.5, "uploader": "artist"},
+                {"_type": "url", "ie_key": "Soundcloud", "id": "2", "url": "https://api.soundcloud.com/tracks/2"}
+            ]
+        }"#;
+        let entries = parse_flat_playlist(output).unwrap();
+        assert_eq!(2, entries.len());
+
+        let metadata = entries[0].metadata();
+        assert_eq!(Some("one"), metadata.title.as_deref());
+        assert_eq!(Some("artist"), metadata.artist.as_deref());
+        assert_eq!(Some(Duration::from_secs_f64(61.5)), metadata.duration);
+        assert_eq!(
+            Some("https://api.soundcloud.com/tracks/1"),
+            metadata.source_url.as_deref()
+        );
+        assert_eq!(None, entries[1].metadata().title);
+    }
+
+    #[test]
+    fn test_parse_single_video() {
+        let output = br#"{"_type": "video", "id": "abc", "title": "video"}"#;
+        assert!(matches!(
+            parse_flat_playlist(output),
+            Err(MusicError::BadPlaylist)
+        ));
+    }
+
+    #[test]
+    fn test_youtube_entry_url() {
+        let entry: PlaylistEntry =
+            serde_json::from_str(r#"{"id": "5gvfp-haKXc", "ie_key": "Youtube"}"#).unwrap();
+        assert_eq!(
+            "https://www.youtube.com/watch?v=5gvfp-haKXc",
+            entry.source_url()
+        );
+    }
+
+    #[test]
+    fn test_parse_list_link() {
+        let parse = |s| ListLink::parse(&Url::parse(s).unwrap());
+        let link = parse("https://www.youtube.com/watch?v=5gvfp-haKXc&list=RD5gvfp-haKXc").unwrap();
+        assert_eq!("5gvfp-haKXc", link.video_id);
+        assert_eq!("RD5gvfp-haKXc", link.list_id);
+        assert!(link.is_mix());
+        assert_eq!(
+            "https://www.youtube.com/watch?v=5gvfp-haKXc",
+            link.video_url()
+        );
+
+        let link = parse("https://music.youtube.com/watch?v=abc&list=OLAK5uy_x").unwrap();
+        assert!(!link.is_mix());
+        assert!(!parse("https://youtu.be/abc?list=RDCLAK5uy_x")
+            .unwrap()
+            .is_mix());
+
+        assert_eq!(None, parse("https://www.youtube.com/watch?v=abc"));
+        assert_eq!(None, parse("https://www.youtube.com/playlist?list=PL1"));
+        assert_eq!(None, parse("https://soundcloud.com/a/sets/b?list=x"));
+    }
+
+    #[test]
+    fn test_select_list_entries() {
+        let entries: Vec<PlaylistEntry> = (0..40)
+            .map(|i| serde_json::from_value(serde_json::json!({ "id": i.to_string() })).unwrap())
+            .collect();
+        let ids = |entries: Vec<PlaylistEntry>| -> Vec<String> {
+            entries.into_iter().map(|e| e.id).collect()
+        };
+        let playlist =
+            ListLink::parse(&Url::parse("https://www.youtube.com/watch?v=30&list=PL1").unwrap())
+                .unwrap();
+        assert_eq!(
+            40,
+            playlist.select(entries.clone(), ListChoice::Playlist).len()
+        );
+        assert_eq!(
+            ["30", "31"],
+            ids(playlist.select(entries.clone(), ListChoice::FromTrack))[..2]
+        );
+        assert_eq!(
+            10,
+            playlist
+                .select(entries.clone(), ListChoice::FromTrack)
+                .len()
+        );
+
+        let mix =
+            ListLink::parse(&Url::parse("https://www.youtube.com/watch?v=5&list=RD5").unwrap())
+                .unwrap();
+        assert_eq!(
+            MAX_MIX_LENGTH,
+            mix.select(entries.clone(), ListChoice::Playlist).len()
+        );
+        assert_eq!("5", ids(mix.select(entries, ListChoice::FromTrack))[0]);
+    }
+
+    #[test]
+    fn test_single_youtube_video() {
+        let single = |s| is_single_youtube_video(&Url::parse(s).unwrap());
+        assert!(single("https://www.youtube.com/watch?v=5gvfp-haKXc"));
+        assert!(single("https://youtu.be/5gvfp-haKXc"));
+        assert!(!single(
+            "https://www.youtube.com/watch?v=5gvfp-haKXc&list=PL1"
+        ));
+        assert!(!single("https://www.youtube.com/playlist?list=PL1"));
+        assert!(!single("https://soundcloud.com/artist/sets/set"));
+    }
+}
@@ -0,0 +1,208 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use poise::serenity_prelude::{async_trait, TypeMapKey};
+use songbird::tracks::{TrackHandle, TrackQueue};
+use songbird::{Event, EventContext, EventHandler as VoiceEventHandler, TrackEvent};
+
+use super::resolve::{resolve_track, NormalizedVolume, PlaybackEnd};
+
+/// How often to check the position of the playing track
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How often to adjust volumes while crossfading
+const FADE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Next track, once it has been started early to crossfade into it from this track
+pub struct CrossfadeNext;
+
+impl TypeMapKey for CrossfadeNext {
+    type Value = TrackHandle;
+}
+
+/// Prefetch the next track in the queue when this track is close to its end, and optionally
+/// crossfade into it
+pub fn add_prefetcher(
+    track: &TrackHandle,
+    queue: TrackQueue,
+    prefetch_time: Duration,
+    crossfade_time: Duration,
+) {
+    let _ = track.add_event(
+        Event::Periodic(CHECK_INTERVAL, None),
+        TrackPrefetcher {
+            queue,
+            prefetch_time: prefetch_time.max(crossfade_time),
+            crossfade_time,
+            prefetched: AtomicBool::new(false),
+        },
+    );
+    if !crossfade_time.is_zero() {
+        let _ = track.add_event(Event::Track(TrackEvent::End), CrossfadeFinisher);
+    }
+}
+
+struct TrackPrefetcher {
+    queue: TrackQueue,
+    prefetch_time: Duration,
+    crossfade_time: Duration,
+    prefetched: AtomicBool,
+}
+
+#[async_trait]
+impl VoiceEventHandler for TrackPrefetcher {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track(&[(state, track)]) = ctx {
+            let end = match track.typemap().read().await.get::<PlaybackEnd>() {
+                Some(end) => *end,
+                None => track.metadata().duration?,
+            };
+            let remaining = end.saturating_sub(state.position);
+
+            // Start loading the next track
+            if remaining <= self.prefetch_time && !self.prefetched.swap(true, Ordering::SeqCst) {
+                if let Some(next) = next_track(&self.queue.current_queue(), track) {
+                    let _ = next.make_playable();
+                    tokio::spawn(resolve_track(next));
+                }
+            }
+
+            if !self.crossfade_time.is_zero() && remaining <= self.crossfade_time {
+                self.crossfade(track, remaining).await;
+                return Some(Event::Periodic(FADE_INTERVAL, None));
+            }
+
+            // Seeking back out of a crossfade, e.g. by replaying the track, cancels it
+            let next = track.typemap().write().await.remove::<CrossfadeNext>();
+            if let Some(next) = next {
+                let _ = next.pause();
+                let _ = next.set_volume(normalized_volume(&next).await);
+                return Some(Event::Periodic(CHECK_INTERVAL, None));
+            }
+        }
+
+        None
+    }
+}
+
+impl TrackPrefetcher {
+    /// Fade this track out and the next track in, proportional to the time remaining
+    async fn crossfade(&self, track: &TrackHandle, remaining: Duration) {
+        let mut typemap = track.typemap().write().await;
+        let next = match typemap.get::<CrossfadeNext>() {
+            Some(next) => Some(next.clone()),
+            None => {
+                // Start the next track silently
+                let next = next_track(&self.queue.current_queue(), track);
+                if let Some(next) = next.as_ref() {
+                    let _ = next.set_volume(0.0);
+                    typemap.insert::<CrossfadeNext>(next.clone());
+                }
+                next
+            }
+        };
+        drop(typemap);
+
+        let next_volume = match next.as_ref() {
+            Some(next) => normalized_volume(next).await,
+            None => 0.0,
+        };
+        let (volume, next_volume) = crossfade_volumes(
+            remaining,
+            self.crossfade_time,
+            normalized_volume(track).await,
+            next_volume,
+        );
+        let _ = track.set_volume(volume);
+        if let Some(next) = next {
+            // Also resumes the next track if the crossfade was paused
+            let _ = next.play();
+            let _ = next.set_volume(next_volume);
+        }
+    }
+}
+
+/// Makes sure the next track plays at full volume, even if the crossfade was interrupted
+struct CrossfadeFinisher;
+
+#[async_trait]
+impl VoiceEventHandler for CrossfadeFinisher {
+    async fn act(&self, ctx: &EventContext<'_>) -> Option<Event> {
+        if let EventContext::Track(&[(_, track)]) = ctx {
+            let next = track.typemap().write().await.remove::<CrossfadeNext>();
+            if let Some(next) = next {
+                let _ = next.set_volume(normalized_volume(&next).await);
+            }
+        }
+
+        None
+    }
+}
+
+/// Pause the next track if it was started early to crossfade into it, so it does not keep playing
+/// while `track` is paused or replaced. It resumes along with the crossfade.
+pub async fn pause_crossfade(track: &TrackHandle) {
+    if let Some(next) = track.typemap().read().await.get::<CrossfadeNext>() {
+        let _ = next.pause();
+    }
+}
+
+/// Track after `track` in the queue
+fn next_track(tracks: &[TrackHandle], track: &TrackHandle) -> Option<TrackHandle> {
+    let idx = tracks.iter().position(|t| t.uuid() == track.uuid())?;
+    tracks.get(idx + 1).cloned()
+}
+
+/// Volumes of the track fading out and the track fading in, proportional to the time remaining
+fn crossfade_volumes(
+    remaining: Duration,
+    crossfade_time: Duration,
+    volume: f32,
+    next_volume: f32,
+) -> (f32, f32) {
+    let progress = 1.0 - remaining.as_secs_f32() / crossfade_time.as_secs_f32();
+    let progress = progress.clamp(0.0, 1.0);
+    (volume * (1.0 - progress), next_volume * progress)
+}
+
+async fn normalized_volume(track: &TrackHandle) -> f32 {
+    track
+        .typemap()
+        .read()
+        .await
+        .get::<NormalizedVolume>()
+        .copied()
+        .unwrap_or(1.0)
+}
+
+#[cfg(test)]
+mod test {
+    use songbird::input::{Input, Reader};
+
+    use super::*;
+
+    fn handle() -> TrackHandle {
+        songbird::create_player(Input::float_pcm(true, Reader::from_memory(vec![]))).1
+    }
+
+    #[test]
+    fn test_next_track() {
+        let tracks = vec![handle(), handle(), handle()];
+        let next = |i: usize| next_track(&tracks, &tracks[i]).map(|t| t.uuid());
+        assert_eq!(Some(tracks[1].uuid()), next(0));
+        assert_eq!(Some(tracks[2].uuid()), next(1));
+        assert_eq!(None, next(2));
+        assert!(next_track(&tracks, &handle()).is_none());
+    }
+
+    #[test]
+    fn test_crossfade_volumes() {
+        let crossfade = Duration::from_secs(4);
+        let volumes = |remaining| crossfade_volumes(remaining, crossfade, 0.8, 0.5);
+        assert_eq!((0.8, 0.0), volumes(crossfade));
+        assert_eq!((0.4, 0.25), volumes(Duration::from_secs(2)));
+        assert_eq!((0.0, 0.5), volumes(Duration::ZERO));
+
+        // Prefetching starts before the crossfade
+        assert_eq!((0.8, 0.0), volumes(Duration::from_secs(10)));
+    }
+}
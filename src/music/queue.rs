@@ -1,40 +1,100 @@
-use std::collections::{HashMap, VecDeque};
-use std::sync::Arc;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use futures::stream::StreamExt;
 use futures::Stream;
+use if_chain::if_chain;
 use poise::serenity_prelude as serenity;
 use serenity::model::id::GuildId;
 use serenity::*;
-use songbird::input::{self, Restartable};
-use songbird::tracks::{Track, TrackHandle};
-use songbird::{Event, TrackEvent};
-
-use super::error::{InternalError, MusicError};
-use super::events::{TrackEndNotifier, TrackSegmentSkipper, TrackStartNotifier};
+use songbird::input::{self, Metadata};
+use songbird::tracks::{PlayMode, Queued, Track, TrackHandle, TrackQueue};
+use songbird::{Call, Event, EventHandler as VoiceEventHandler, TrackEvent};
+
+use super::bus::{publish, MusicEvent};
+use super::cache::{self, search_key, video_key, CachedMetadata, METADATA_TTL};
+use super::error::{log_error, InternalError, MusicError};
+use super::events::{set_segment_skipper, TrackEndNotifier, TrackStartNotifier};
+use super::history::{pop_history, push_history};
 use super::message::{format_add_playlist, PlayUpdate};
-use super::voice::{CanGetVoice, CanJoinVoice};
-use super::youtube::loudness::get_loudness;
-use super::youtube::sponsorblock::{get_skips, SBDuration};
+use super::prefetch::{add_prefetcher, pause_crossfade};
+use super::resolve::{
+    apply_loudness_and_skips, get_loudness_and_skips, resolve_upcoming, PendingResolve,
+};
+use super::settings::get_settings;
+use super::source::{AlternateUpload, YtdlSource};
+use super::voice::{busy_in_other_channel, join_channel, CanGetVoice, CanJoinVoice};
 use crate::message::{CustomSendMessage, SendableMessage, CANCEL_INTERACTION_ID};
 use crate::PoiseContext;
 
 pub enum Query {
     Search(String),
     Url(String),
+    /// Track with metadata known ahead of time, such as a playlist entry
+    Known(Metadata),
+}
+
+/// Who requested tracks and where, needed to create tracks outside of a command
+#[derive(Clone)]
+pub struct TrackRequest {
+    pub ctx: serenity::Context,
+    pub guild_id: GuildId,
+    /// Text channel to send updates to
+    pub channel_id: ChannelId,
+    pub author_id: UserId,
+    pub prefetch_time: Duration,
+    pub crossfade_time: Duration,
 }
 
-/// Add the given tracks to the queue
+impl TrackRequest {
+    pub async fn new(ctx: PoiseContext<'_>) -> Result<Self, MusicError> {
+        let guild_id = ctx.guild_id().ok_or(MusicError::GetVoice)?;
+        let settings = get_settings(ctx.serenity_context(), guild_id).await;
+        Ok(Self {
+            ctx: ctx.serenity_context().clone(),
+            guild_id,
+            channel_id: settings.announcement_channel(ctx.channel_id()),
+            author_id: ctx.author().id,
+            prefetch_time: ctx.data().prefetch_time,
+            crossfade_time: ctx.data().crossfade_time,
+        })
+    }
+}
+
+/// Where added tracks are placed in the queue
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Placement {
+    /// After all other tracks
+    End,
+    /// Right after the current track
+    Next,
+    /// Before the current track, which is paused and resumes once the added tracks end
+    Now,
+}
+
+/// Add the given tracks to the end of the queue
 pub async fn add_tracks(
     ctx: PoiseContext<'_>,
     queries: impl Stream<Item = Query>,
     num_queries: usize,
+) -> Result<(), MusicError> {
+    add_tracks_at(ctx, queries, num_queries, Placement::End).await
+}
+
+/// Add the given tracks to the queue, keeping them together in order
+pub async fn add_tracks_at(
+    ctx: PoiseContext<'_>,
+    queries: impl Stream<Item = Query>,
+    num_queries: usize,
+    placement: Placement,
 ) -> Result<(), MusicError> {
     let mutex = get_lock(ctx).await?;
     let _lock = mutex.lock().await;
 
     let handler_lock = ctx.get_voice().await?;
+    let request = TrackRequest::new(ctx).await?;
 
     let lazy = {
         // Workaround for https://github.com/serenity-rs/songbird/issues/97
@@ -46,8 +106,9 @@ pub async fn add_tracks(
     let mut tracks = queries
         .enumerate()
         .map(|(i, q)| {
-            let lazy = lazy || (i != 0);
-            create_track(ctx, q, lazy)
+            // A track played now starts right away
+            let lazy = (lazy && placement != Placement::Now) || (i != 0);
+            create_track(&request, q, lazy)
         })
         .buffered(20);
 
@@ -57,6 +118,15 @@ pub async fn add_tracks(
     let mut pushed_tracks = VecDeque::with_capacity(MAX_NUM_DISPLAYED_TRACKS);
     let mut num_queued_tracks = 0;
 
+    // Editing the reply for every track would hit rate limits on large playlists
+    const REPLY_EDIT_INTERVAL: Duration = Duration::from_secs(1);
+    let mut last_edit = Instant::now();
+
+    // Only join the voice channel once the first track is created
+    let mut joined_handler_lock = None;
+    // Later tracks are placed after the previous one
+    let mut previous_track: Option<TrackHandle> = None;
+
     // Spawn a new task to check if:
     // _tx is dropped when all tracks are added
     let (_tx, rx) = futures::channel::oneshot::channel::<()>();
@@ -73,7 +143,7 @@ pub async fn add_tracks(
                 _ = rx => (),
                 Some(ci) = button_interaction_fut => {
                     drop(tx_cancel);
-                    ci.defer(serenity_ctx.http()).await.unwrap();
+                    let _ = ci.defer(serenity_ctx.http()).await;
                 },
             }
         });
@@ -92,12 +162,17 @@ pub async fn add_tracks(
                     break;
                 }
 
-                let handler_lock = ctx.join_voice().await?;
+                let handler_lock = match joined_handler_lock {
+                    Some(ref h) => h,
+                    None => joined_handler_lock.insert(ctx.join_voice().await?),
+                };
                 let mut handler = handler_lock.lock().await;
-                handler.remove_all_global_events();
 
                 // Queue track
-                handler.enqueue(track);
+                enqueue(&request, &mut handler, track, &track_handle);
+                place_track(handler.queue(), placement, previous_track.as_ref()).await;
+                previous_track = Some(track_handle.clone());
+                resolve_upcoming(handler.queue());
 
                 // Make the next song in queue playable to reduce delay
                 let queue = handler.queue().current_queue();
@@ -120,18 +195,24 @@ pub async fn add_tracks(
                 };
                 if let Some(ref reply_handle) = reply_handle {
                     // If a previous reply has been sent, edit the reply
-                    CustomSendMessage::Cancelable(fmt())
-                        .edit_reply(ctx, reply_handle.clone())
-                        .await;
+                    if last_edit.elapsed() >= REPLY_EDIT_INTERVAL {
+                        CustomSendMessage::Cancelable(fmt())
+                            .edit_reply(ctx, reply_handle.clone())
+                            .await;
+                        last_edit = Instant::now();
+                    }
                 } else {
-                    let update = match handler.queue().current_queue().len() {
-                        1 => PlayUpdate::Play(track_handle.clone(), queue.len()),
-                        _ => PlayUpdate::Add(track_handle.clone(), queue.len()),
+                    let playing = handler.queue().current().map(|t| t.uuid());
+                    let update = if playing == Some(track_handle.uuid()) {
+                        PlayUpdate::Play(track_handle.clone(), queue.len())
+                    } else {
+                        PlayUpdate::Add(track_handle.clone(), queue.len())
                     };
                     if num_queries != 1 {
                         // If first of many queued tracks, send an initial reply
                         reply_handle =
                             Some(CustomSendMessage::Cancelable(fmt()).send_msg(ctx).await);
+                        last_edit = Instant::now();
                     }
                     if !(num_queries != 1 && matches!(update, PlayUpdate::Add(_, _))) {
                         CustomSendMessage::Custom(update.format().await)
@@ -168,19 +249,94 @@ pub async fn add_tracks(
     }
 }
 
+/// Join a voice channel and add tracks without a command, announcing them in the request channel
+///
+/// Fails instead of moving the bot if it is playing to listeners in another voice channel
+pub async fn add_tracks_to_channel(
+    request: &TrackRequest,
+    voice_channel_id: ChannelId,
+    queries: Vec<Query>,
+) -> Result<(), MusicError> {
+    let mutex = get_guild_lock(&request.ctx, Some(request.guild_id)).await?;
+    let _lock = mutex.lock().await;
+
+    let manager = songbird::get(&request.ctx)
+        .await
+        .ok_or(MusicError::GetVoice)?;
+    if busy_in_other_channel(&request.ctx, &manager, request.guild_id, voice_channel_id).await {
+        return Err(MusicError::VoiceChannelBusy);
+    }
+    let handler_lock = join_channel(&manager, request.guild_id, voice_channel_id).await?;
+
+    let num_queries = queries.len();
+    let mut added = vec![];
+    for query in queries {
+        let lazy = !handler_lock.lock().await.queue().is_empty();
+        let (track, track_handle) = match create_track(request, query, lazy).await {
+            Ok(t) => t,
+            Err(e) if num_queries == 1 => return Err(e),
+            Err(_) => continue,
+        };
+        let mut handler = handler_lock.lock().await;
+        enqueue(request, &mut handler, track, &track_handle);
+        resolve_upcoming(handler.queue());
+        added.push(track_handle);
+    }
+
+    let http = &request.ctx.http;
+    if let [track_handle] = &added[..] {
+        let queue_len = handler_lock.lock().await.queue().len();
+        // Playing tracks are announced by TrackStartNotifier
+        if queue_len > 1 {
+            let update = PlayUpdate::Add(track_handle.clone(), queue_len);
+            let result = CustomSendMessage::Custom(update.format().await)
+                .send_msg_http(http, request.channel_id)
+                .await;
+            log_error(request.guild_id, result.map_err(InternalError::SendMessage));
+        }
+    } else {
+        let num_added = added.len();
+        let fmt = format_add_playlist(added.into_iter(), num_added, num_queries, true);
+        let result = CustomSendMessage::Custom(fmt)
+            .send_msg_http(http, request.channel_id)
+            .await;
+        log_error(request.guild_id, result.map_err(InternalError::SendMessage));
+    }
+
+    Ok(())
+}
+
 pub async fn remove_track(
     ctx: PoiseContext<'_>,
     start_idx: usize,
     end_idx: usize,
 ) -> Result<Vec<TrackHandle>, MusicError> {
-    let mutex = match get_lock(ctx).await {
+    let guild_id = ctx.guild_id().ok_or(MusicError::RemoveTrack)?;
+    let handler_lock = ctx.get_voice().await.map_err(|_| MusicError::RemoveTrack)?;
+    remove_guild_tracks(
+        ctx.serenity_context(),
+        guild_id,
+        &handler_lock,
+        start_idx,
+        end_idx,
+    )
+    .await
+}
+
+/// Remove the tracks between the given queue indices (inclusive) of a guild
+pub async fn remove_guild_tracks(
+    ctx: &serenity::Context,
+    guild_id: GuildId,
+    handler_lock: &Mutex<Call>,
+    start_idx: usize,
+    end_idx: usize,
+) -> Result<Vec<TrackHandle>, MusicError> {
+    let mutex = match get_guild_lock(ctx, Some(guild_id)).await {
         Err(_) => return Err(MusicError::RemoveTrack),
         Ok(m) => m,
     };
     let _lock = mutex.lock().await;
 
-    let handler_lock = ctx.get_voice().await.map_err(|_| MusicError::RemoveTrack)?;
-
     let handler = handler_lock.lock().await;
     let queue = handler.queue();
 
@@ -194,96 +350,436 @@ pub async fn remove_track(
             };
         }
     });
+    resolve_upcoming(queue);
+    for track in &removed_tracks {
+        publish(MusicEvent::TrackRemoved {
+            guild_id,
+            track: track.clone(),
+        });
+    }
 
     Ok(removed_tracks)
 }
 
-async fn create_track(
+/// Move an upcoming track to another position in the queue
+pub async fn move_guild_track(
+    ctx: &serenity::Context,
+    guild_id: GuildId,
+    handler_lock: &Mutex<Call>,
+    from_idx: usize,
+    to_idx: usize,
+) -> Result<TrackHandle, MusicError> {
+    let mutex = get_guild_lock(ctx, Some(guild_id)).await?;
+    let _lock = mutex.lock().await;
+
+    let handler = handler_lock.lock().await;
+    let queue = handler.queue();
+    let track = queue.modify_queue(|q| {
+        // The current track keeps playing, so only upcoming tracks can be moved
+        if from_idx == 0 || to_idx == 0 || from_idx >= q.len() || to_idx >= q.len() {
+            return Err(MusicError::BadIndex);
+        }
+        let track = q.remove(from_idx).ok_or(MusicError::BadIndex)?;
+        let handle = track.handle();
+        q.insert(to_idx, track);
+        Ok(handle)
+    })?;
+    resolve_upcoming(queue);
+    publish(MusicEvent::TrackMoved {
+        guild_id,
+        track: track.clone(),
+        from: from_idx,
+        to: to_idx,
+    });
+
+    Ok(track)
+}
+
+/// Pause the current track, returns it if it was playing
+pub async fn pause_track(
+    guild_id: GuildId,
+    handler: &Call,
+) -> Result<Option<TrackHandle>, MusicError> {
+    let track = handler
+        .queue()
+        .current()
+        .ok_or(MusicError::NoPlayingTrack)?;
+    let info = track
+        .get_info()
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?;
+    if info.playing != PlayMode::Play {
+        return Ok(None);
+    }
+    track.pause().map_err(|e| MusicError::Internal(e.into()))?;
+    pause_crossfade(&track).await;
+    publish(MusicEvent::TrackPaused {
+        guild_id,
+        track: track.clone(),
+        auto: false,
+    });
+
+    Ok(Some(track))
+}
+
+/// Resume the current track if it is paused
+pub async fn resume_track(handler: &mut Call) -> Result<TrackHandle, MusicError> {
+    if_chain! {
+        if let Some(track) = handler.queue().current();
+        if let Ok(info) = track.get_info().await;
+        if info.playing == PlayMode::Pause;
+        then {
+            let _ = track.play();
+            handler.remove_all_global_events();
+            Ok(track)
+        } else {
+            Err(MusicError::NoPausedTrack)
+        }
+    }
+}
+
+/// Stop the current track and start playing the next one
+pub fn skip_track(guild_id: GuildId, handler: &Call) -> Result<TrackHandle, MusicError> {
+    let track = handler
+        .queue()
+        .dequeue(0)
+        .ok_or(MusicError::NoPlayingTrack)?;
+    let _ = track.stop();
+    let track = track.handle();
+    publish(MusicEvent::TrackSkipped {
+        guild_id,
+        track: track.clone(),
+    });
+    if let Some(next) = handler.queue().current() {
+        let _ = next.play();
+    }
+
+    Ok(track)
+}
+
+/// Discard all tracks before the given queue index and start playing it
+pub async fn skip_to(ctx: PoiseContext<'_>, idx: usize) -> Result<Vec<TrackHandle>, MusicError> {
+    remove_from_queue(ctx, |q| {
+        if idx == 0 || idx >= q.len() {
+            return Err(MusicError::BadIndex);
+        }
+        let removed: Vec<_> = q.drain(..idx).collect();
+        if let Some(next) = q.front() {
+            let _ = next.play();
+        }
+        Ok(removed)
+    })
+    .await
+}
+
+/// Remove all upcoming tracks, leaving the current track playing
+pub async fn clear_queue(ctx: PoiseContext<'_>) -> Result<Vec<TrackHandle>, MusicError> {
+    remove_from_queue(ctx, |q| Ok(q.drain(1.min(q.len())..).collect())).await
+}
+
+/// Remove upcoming tracks with the same source as an earlier track in the queue
+pub async fn dedupe_queue(ctx: PoiseContext<'_>) -> Result<Vec<TrackHandle>, MusicError> {
+    remove_from_queue(ctx, |q| {
+        let mut seen = HashSet::new();
+        let mut removed = vec![];
+        let mut idx = 0;
+        while idx < q.len() {
+            let duplicate = match q[idx].metadata().source_url.clone() {
+                Some(url) => !seen.insert(url),
+                None => false,
+            };
+            if duplicate && idx != 0 {
+                removed.extend(q.remove(idx));
+            } else {
+                idx += 1;
+            }
+        }
+        Ok(removed)
+    })
+    .await
+}
+
+/// Modify the queue under the per-guild lock and stop all tracks removed from it
+async fn remove_from_queue(
     ctx: PoiseContext<'_>,
+    f: impl FnOnce(&mut VecDeque<Queued>) -> Result<Vec<Queued>, MusicError>,
+) -> Result<Vec<TrackHandle>, MusicError> {
+    let mutex = get_lock(ctx).await?;
+    let _lock = mutex.lock().await;
+
+    let handler_lock = ctx.get_voice().await?;
+    let handler = handler_lock.lock().await;
+    let queue = handler.queue();
+
+    let guild_id = ctx.guild_id().ok_or(MusicError::GetVoice)?;
+    let removed = queue.modify_queue(f)?;
+    let removed_tracks = removed
+        .into_iter()
+        .map(|t| {
+            let _ = t.stop();
+            let track = t.handle();
+            publish(MusicEvent::TrackRemoved {
+                guild_id,
+                track: track.clone(),
+            });
+            track
+        })
+        .collect();
+    resolve_upcoming(queue);
+
+    Ok(removed_tracks)
+}
+
+/// Re-add the most recently finished track to the front of the queue, pausing the current track
+/// so it resumes once the previous track ends
+pub async fn play_previous(ctx: PoiseContext<'_>) -> Result<TrackHandle, MusicError> {
+    let mutex = get_lock(ctx).await?;
+    let _lock = mutex.lock().await;
+
+    let guild_id = ctx.guild_id().ok_or(MusicError::GetVoice)?;
+    let metadata = pop_history(ctx.serenity_context(), guild_id)
+        .await
+        .ok_or(MusicError::NoPreviousTrack)?;
+
+    let handler_lock = ctx.join_voice().await?;
+    let request = TrackRequest::new(ctx).await?;
+    let lazy = !handler_lock.lock().await.queue().is_empty();
+    let (track, track_handle) =
+        match create_track(&request, Query::Known(metadata.clone()), lazy).await {
+            Ok(t) => t,
+            Err(e) => {
+                push_history(ctx.serenity_context(), guild_id, metadata).await;
+                return Err(e);
+            }
+        };
+
+    let mut handler = handler_lock.lock().await;
+    enqueue(&request, &mut handler, track, &track_handle);
+    place_track(handler.queue(), Placement::Now, None).await;
+    resolve_upcoming(handler.queue());
+
+    Ok(track_handle)
+}
+
+/// Restart the current track from the beginning
+pub async fn replay_track(ctx: PoiseContext<'_>) -> Result<TrackHandle, MusicError> {
+    let mutex = get_lock(ctx).await?;
+    let _lock = mutex.lock().await;
+
+    let handler_lock = ctx.get_voice().await?;
+    let handler = handler_lock.lock().await;
+    let track = handler
+        .queue()
+        .current()
+        .ok_or(MusicError::NoPlayingTrack)?;
+    track
+        .seek_time(Duration::ZERO)
+        .map_err(|_| MusicError::Seek)?;
+
+    // Skipped segments need to be skipped again
+    set_segment_skipper(&track, Duration::ZERO).await;
+
+    Ok(track)
+}
+
+/// Add a track to the end of the queue
+fn enqueue(request: &TrackRequest, handler: &mut Call, track: Track, track_handle: &TrackHandle) {
+    handler.remove_all_global_events();
+    handler.enqueue(track);
+    publish(MusicEvent::TrackQueued {
+        guild_id: request.guild_id,
+        track: track_handle.clone(),
+        queue_len: handler.queue().len(),
+    });
+    add_prefetcher(
+        track_handle,
+        handler.queue().clone(),
+        request.prefetch_time,
+        request.crossfade_time,
+    );
+}
+
+/// Move a track just added to the end of the queue to its placement, after `previous` so tracks
+/// added together stay in order
+async fn place_track(queue: &TrackQueue, placement: Placement, previous: Option<&TrackHandle>) {
+    if placement == Placement::End {
+        return;
+    }
+    let play_now = placement == Placement::Now && previous.is_none();
+    let replaced = queue.modify_queue(|q| {
+        // A track added to an empty queue is already playing
+        if q.len() < 2 {
+            return None;
+        }
+        let idx = match previous.and_then(|p| q.iter().position(|t| t.uuid() == p.uuid())) {
+            Some(i) => i + 1,
+            None if play_now => 0,
+            // The previous track already finished
+            None => 1,
+        };
+        let track = q.pop_back()?;
+        let replaced = if idx == 0 {
+            q.front().map(|t| t.handle())
+        } else {
+            None
+        };
+        q.insert(idx.min(q.len()), track);
+        replaced
+    });
+
+    // Pause the current track so it resumes from the same position later
+    if let Some(replaced) = replaced {
+        let _ = replaced.pause();
+        pause_crossfade(&replaced).await;
+        if let Some(track) = queue.current() {
+            let _ = track.play();
+        }
+    }
+}
+
+async fn create_track(
+    request: &TrackRequest,
     query: Query,
     lazy: bool,
 ) -> Result<(Track, TrackHandle), MusicError> {
-    // Create source
-    let source_res = match query {
-        Query::Search(x) => Restartable::ytdl_search(x, lazy).await,
-        Query::Url(x) => Restartable::ytdl(x.to_owned(), lazy).await,
+    // Reuse metadata resolved for an earlier request of the same search or video
+    let cache_key = match &query {
+        Query::Search(x) => Some(search_key("ytdl", x)),
+        Query::Url(x) => Some(video_key("metadata", x)),
+        Query::Known(_) => None,
+    };
+    let query = match &cache_key {
+        Some(key) => match cache::get::<CachedMetadata>(key).await {
+            Some(m) if m.source_url.is_some() => Query::Known(m.into()),
+            _ => query,
+        },
+        None => query,
     };
-    let source = match source_res {
-        Ok(s) => s,
-        Err(songbird::input::error::Error::Json { parsed_text, error }) => {
-            if error.to_string().starts_with("EOF") {
-                return Err(MusicError::NoResults);
+
+    // Tracks with known metadata are created without yt-dlp and resolved shortly before playing
+    let deferred = lazy && matches!(query, Query::Known(_));
+
+    // Create source
+    let mut known = None;
+    let alternate = Arc::<OnceLock<AlternateUpload>>::default();
+    let source = match query {
+        Query::Search(x) => {
+            let uri = format!("ytsearch1:{}", x);
+            YtdlSource::restartable(uri, None, lazy, alternate.clone()).await?
+        }
+        Query::Url(x) => YtdlSource::restartable(x, None, lazy, alternate.clone()).await?,
+        Query::Known(m) => {
+            let url = m
+                .source_url
+                .clone()
+                .ok_or_else(|| MusicError::BadSource("missing source URL".to_owned()))?;
+            if deferred {
+                YtdlSource::restartable(url, Some(m), true, alternate.clone()).await?
             } else {
-                return Err(MusicError::BadSource(parsed_text));
+                known = Some(m.clone());
+                YtdlSource::restartable(url, Some(m), lazy, alternate.clone()).await?
             }
         }
-        Err(e) => return Err(MusicError::Internal(e.into())),
     };
 
-    let input: input::Input = source.into();
+    let mut input: input::Input = source.into();
 
-    // Get volume and skips
-    let (volume, skips) = if let Some(url) = &input.metadata.source_url {
-        tokio::join!(get_loudness(url), get_skips(url))
-    } else {
-        (1.0, vec![])
-    };
+    // Fill in any metadata the extractor did not return
+    if let Some(known) = known {
+        let metadata = &mut input.metadata;
+        metadata.title = metadata.title.take().or(known.title);
+        metadata.artist = metadata.artist.take().or(known.artist);
+        metadata.duration = metadata.duration.take().or(known.duration);
+        metadata.source_url = metadata.source_url.take().or(known.source_url);
+        metadata.thumbnail = metadata.thumbnail.take().or(known.thumbnail);
+    }
+    if let Some(key) = cache_key.filter(|_| !deferred) {
+        if input.metadata.source_url.is_some() {
+            let metadata = CachedMetadata::from(&*input.metadata);
+            cache::put(&key, &metadata, METADATA_TTL).await;
+        }
+    }
 
     // Create track
     let (track, track_handle) = songbird::tracks::create_player(input);
-    let _ = track_handle.set_volume(volume);
 
-    // Set TrackSegmentSkipper if skips exist
-    let sb_time = if let Some(segment) = skips.first().cloned() {
-        let sb_time = skips.iter().map(|(a, b)| *b - *a).sum();
+    // Remember who requested the track and where
+    {
+        let mut typemap = track_handle.typemap().write().await;
+        typemap.insert::<Requester>(request.author_id);
+        typemap.insert::<RequestChannel>(request.channel_id);
+        typemap.insert::<RequestGuild>(request.guild_id);
+        typemap.insert::<AlternateUpload>(alternate);
+    }
+
+    // Set volume and skips
+    if deferred {
         track_handle
-            .add_event(
-                Event::Delayed(segment.0),
-                TrackSegmentSkipper {
-                    segments: skips,
-                    idx: 0.into(),
-                },
-            )
-            .unwrap();
-        Some(sb_time)
+            .typemap()
+            .write()
+            .await
+            .insert::<PendingResolve>(());
     } else {
-        None
-    };
-    track_handle
-        .typemap()
-        .write()
-        .await
-        .insert::<SBDuration>(sb_time);
+        let (volume, skips) =
+            get_loudness_and_skips(track_handle.metadata().source_url.as_deref()).await;
+        apply_loudness_and_skips(&track_handle, volume, skips, Duration::ZERO).await;
+    }
 
     // Set TrackEndNotifier
-    track_handle
-        .add_event(
-            Event::Track(TrackEvent::End),
-            TrackEndNotifier {
-                ctx: Arc::new(Mutex::new(ctx.serenity_context().clone())),
-                chan_id: ctx.channel_id(),
-                guild_id: ctx.guild_id().unwrap(),
-                http: ctx.serenity_context().http.clone(),
-            },
-        )
-        .expect("Error adding TrackEndNotifier");
+    add_track_event(
+        &track_handle,
+        Event::Track(TrackEvent::End),
+        TrackEndNotifier {
+            ctx: Arc::new(Mutex::new(request.ctx.clone())),
+            guild_id: request.guild_id,
+        },
+    )?;
 
     // Set TrackStartNotifier
-    track_handle
-        .add_event(
-            Event::Track(TrackEvent::Play),
-            TrackStartNotifier {
-                ctx: Arc::new(Mutex::new(ctx.serenity_context().clone())),
-                chan_id: ctx.channel_id(),
-                guild_id: ctx.guild_id().unwrap(),
-                http: ctx.serenity_context().http.clone(),
-            },
-        )
-        .expect("Error adding TrackStartNotifier");
+    add_track_event(
+        &track_handle,
+        Event::Track(TrackEvent::Play),
+        TrackStartNotifier {
+            ctx: Arc::new(Mutex::new(request.ctx.clone())),
+            guild_id: request.guild_id,
+        },
+    )?;
 
     Ok((track, track_handle))
 }
 
+/// Add an event to a track, which fails if the track was already dropped
+fn add_track_event<F: VoiceEventHandler + 'static>(
+    track: &TrackHandle,
+    event: Event,
+    action: F,
+) -> Result<(), MusicError> {
+    track
+        .add_event(event, action)
+        .map_err(|e| InternalError::AddEvent(e).into())
+}
+
+/// User who added the track to the queue
+pub struct Requester;
+
+impl TypeMapKey for Requester {
+    type Value = UserId;
+}
+
+/// Text channel the track was requested from, where updates about it are sent
+pub struct RequestChannel;
+
+impl TypeMapKey for RequestChannel {
+    type Value = ChannelId;
+}
+
+/// Guild the track was queued in
+pub struct RequestGuild;
+
+impl TypeMapKey for RequestGuild {
+    type Value = GuildId;
+}
+
 pub struct QueueMutexMap;
 
 impl TypeMapKey for QueueMutexMap {
@@ -291,23 +787,107 @@ impl TypeMapKey for QueueMutexMap {
 }
 
 async fn get_lock(ctx: PoiseContext<'_>) -> Result<Arc<Mutex<()>>, MusicError> {
-    let data = ctx.serenity_context().data.read().await;
+    get_guild_lock(ctx.serenity_context(), ctx.guild_id()).await
+}
+
+async fn get_guild_lock(
+    ctx: &serenity::Context,
+    guild_id: Option<GuildId>,
+) -> Result<Arc<Mutex<()>>, MusicError> {
+    let data = ctx.data.read().await;
     let map = data
         .get::<QueueMutexMap>()
         .ok_or_else(|| MusicError::Internal(InternalError::QueueLock.into()))?;
-    let m = match map.get(&ctx.guild_id()) {
+    let m = match map.get(&guild_id) {
         Some(m) => m.clone(),
         None => {
             let m = Arc::new(Mutex::new(()));
             drop(data);
-            let mut data = ctx.serenity_context().data.write().await;
+            let mut data = ctx.data.write().await;
             let map = data
                 .get_mut::<QueueMutexMap>()
                 .ok_or_else(|| MusicError::Internal(InternalError::QueueLock.into()))?;
-            map.insert(ctx.guild_id(), m.clone());
+            map.insert(guild_id, m.clone());
             m
         }
     };
 
     Ok(m)
 }
+
+#[cfg(test)]
+mod test {
+    use songbird::input::{Input, Reader};
+    use songbird::{EventContext, TrackEvent};
+
+    use super::*;
+
+    struct NoopHandler;
+
+    #[async_trait]
+    impl VoiceEventHandler for NoopHandler {
+        async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_add_event_to_dropped_track() {
+        let (track, handle) =
+            songbird::create_player(Input::float_pcm(true, Reader::from_memory(vec![])));
+        assert!(add_track_event(&handle, Event::Track(TrackEvent::End), NoopHandler).is_ok());
+
+        drop(track);
+        assert!(matches!(
+            add_track_event(&handle, Event::Track(TrackEvent::End), NoopHandler),
+            Err(MusicError::Internal(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_place_track() {
+        async fn add(
+            queue: &TrackQueue,
+            driver: &mut songbird::Driver,
+            placement: Placement,
+            previous: Option<&TrackHandle>,
+        ) -> TrackHandle {
+            let (track, handle) =
+                songbird::create_player(Input::float_pcm(true, Reader::from_memory(vec![])));
+            queue.add(track, driver);
+            place_track(queue, placement, previous).await;
+            handle
+        }
+
+        let mut driver = songbird::Driver::default();
+        let queue = TrackQueue::new();
+        let order = |queue: &TrackQueue| -> Vec<_> {
+            queue.current_queue().iter().map(|t| t.uuid()).collect()
+        };
+
+        // A track added to an empty queue plays regardless of placement
+        let current = add(&queue, &mut driver, Placement::Now, None).await;
+        let last = add(&queue, &mut driver, Placement::End, None).await;
+        let next = add(&queue, &mut driver, Placement::Next, None).await;
+        let next_2 = add(&queue, &mut driver, Placement::Next, Some(&next)).await;
+        assert_eq!(
+            order(&queue),
+            [current.uuid(), next.uuid(), next_2.uuid(), last.uuid()]
+        );
+
+        // Tracks played now go before the current track, which is kept after them
+        let now = add(&queue, &mut driver, Placement::Now, None).await;
+        let now_2 = add(&queue, &mut driver, Placement::Now, Some(&now)).await;
+        assert_eq!(
+            order(&queue),
+            [
+                now.uuid(),
+                now_2.uuid(),
+                current.uuid(),
+                next.uuid(),
+                next_2.uuid(),
+                last.uuid()
+            ]
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use std::time::Duration;
+
+use poise::serenity_prelude::TypeMapKey;
+use songbird::tracks::{TrackHandle, TrackQueue};
+
+use super::events::set_segment_skipper;
+use super::youtube::loudness::get_loudness;
+use super::youtube::sponsorblock::{get_skips, SBDuration, SBSegments};
+
+/// Number of tracks at the front of the queue that are resolved ahead of time
+const RESOLVE_AHEAD: usize = 3;
+
+/// Marks a queued track whose source, loudness and skips have not been resolved yet
+pub struct PendingResolve;
+
+impl TypeMapKey for PendingResolve {
+    type Value = ();
+}
+
+/// Volume of a track after loudness normalization
+pub struct NormalizedVolume;
+
+impl TypeMapKey for NormalizedVolume {
+    type Value = f32;
+}
+
+/// Position where playback of a track ends, excluding a trailing SponsorBlock segment
+pub struct PlaybackEnd;
+
+impl TypeMapKey for PlaybackEnd {
+    type Value = Duration;
+}
+
+/// Resolve any placeholder tracks that are about to play
+pub fn resolve_upcoming(queue: &TrackQueue) {
+    for track in queue.current_queue().into_iter().take(RESOLVE_AHEAD) {
+        tokio::spawn(resolve_track(track));
+    }
+}
+
+pub async fn resolve_track(track: TrackHandle) {
+    // Only resolve each track once
+    if !claim_pending(&track).await {
+        return;
+    }
+
+    // Start the source so it is buffered by the time it plays
+    let _ = track.make_playable();
+
+    let (volume, skips) = get_loudness_and_skips(track.metadata().source_url.as_deref()).await;
+    let position = match track.get_info().await {
+        Ok(info) => info.position,
+        // Track already ended
+        Err(_) => return,
+    };
+    apply_loudness_and_skips(&track, volume, skips, position).await;
+}
+
+/// Remove the placeholder marker of a track, returns whether the caller should resolve it
+async fn claim_pending(track: &TrackHandle) -> bool {
+    track
+        .typemap()
+        .write()
+        .await
+        .remove::<PendingResolve>()
+        .is_some()
+}
+
+/// Look up the normalized volume and SponsorBlock segments of a track
+pub async fn get_loudness_and_skips(url: Option<&str>) -> (f32, Vec<(Duration, Duration)>) {
+    if let Some(url) = url {
+        tokio::join!(get_loudness(url), get_skips(url))
+    } else {
+        (1.0, vec![])
+    }
+}
+
+/// Set the volume of a track and skip its SponsorBlock segments that have not been played yet
+pub async fn apply_loudness_and_skips(
+    track: &TrackHandle,
+    volume: f32,
+    skips: Vec<(Duration, Duration)>,
+    position: Duration,
+) {
+    let _ = track.set_volume(volume);
+
+    let sb_time = if skips.is_empty() {
+        None
+    } else {
+        Some(skips.iter().map(|(a, b)| *b - *a).sum())
+    };
+    let end = track
+        .metadata()
+        .duration
+        .map(|duration| match skips.last() {
+            Some((a, b)) if *b + Duration::from_secs(1) >= duration => *a,
+            _ => duration,
+        });
+
+    let mut typemap = track.typemap().write().await;
+    typemap.insert::<SBDuration>(sb_time);
+    typemap.insert::<SBSegments>(skips);
+    typemap.insert::<NormalizedVolume>(volume);
+    if let Some(end) = end {
+        typemap.insert::<PlaybackEnd>(end);
+    }
+    drop(typemap);
+
+    // Set TrackSegmentSkipper if skips exist
+    set_segment_skipper(track, position).await;
+}
+
+#[cfg(test)]
+mod test {
+    use poise::serenity_prelude::{GuildId, UserId};
+    use songbird::input::{Input, Reader};
+    use songbird::Call;
+
+    use super::*;
+
+    fn placeholder() -> (songbird::tracks::Track, TrackHandle) {
+        songbird::create_player(Input::float_pcm(true, Reader::from_memory(vec![])))
+    }
+
+    async fn is_pending(track: &TrackHandle) -> bool {
+        track
+            .typemap()
+            .read()
+            .await
+            .contains_key::<PendingResolve>()
+    }
+
+    #[tokio::test]
+    async fn test_claim_pending_once() {
+        let (_track, handle) = placeholder();
+        handle.typemap().write().await.insert::<PendingResolve>(());
+
+        assert!(claim_pending(&handle).await);
+        assert!(!claim_pending(&handle).await);
+
+        // Already resolved tracks are left alone
+        resolve_track(handle.clone()).await;
+        assert!(!is_pending(&handle).await);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_upcoming_only_first() {
+        let mut call = Call::standalone(GuildId(1), UserId(2));
+        let mut handles = vec![];
+        for _ in 0..RESOLVE_AHEAD + 2 {
+            let (track, handle) = placeholder();
+            handle.typemap().write().await.insert::<PendingResolve>(());
+            call.enqueue(track);
+            handles.push(handle);
+        }
+
+        resolve_upcoming(call.queue());
+
+        // Resolving happens in spawned tasks
+        tokio::time::timeout(Duration::from_secs(5), async {
+            for handle in &handles[..RESOLVE_AHEAD] {
+                while is_pending(handle).await {
+                    tokio::task::yield_now().await;
+                }
+            }
+        })
+        .await
+        .unwrap();
+        for handle in &handles[RESOLVE_AHEAD..] {
+            assert!(is_pending(handle).await);
+        }
+    }
+}
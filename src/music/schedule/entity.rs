@@ -0,0 +1,20 @@
+use sea_orm::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "music_schedule")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub guild_id: String,
+    pub voice_channel_id: String,
+    pub text_channel_id: String,
+    pub user_id: String,
+    pub query: String,
+    /// Unix timestamp in seconds
+    pub run_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
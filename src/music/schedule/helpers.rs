@@ -0,0 +1,67 @@
+use poise::serenity_prelude::{ChannelId, GuildId, UserId};
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+};
+
+use super::entity;
+use crate::music::database::{self, stringify};
+use crate::music::MusicError;
+
+pub async fn create_table(db: &DatabaseConnection) -> anyhow::Result<()> {
+    database::create_table(db, entity::Entity).await?;
+    Ok(())
+}
+
+pub async fn insert_schedule(
+    guild_id: GuildId,
+    voice_channel_id: ChannelId,
+    text_channel_id: ChannelId,
+    user_id: UserId,
+    query: &str,
+    run_at: i64,
+) -> Result<entity::Model, MusicError> {
+    let db = database::connection()?;
+
+    let model = entity::ActiveModel {
+        guild_id: Set(stringify(guild_id.0)),
+        voice_channel_id: Set(stringify(voice_channel_id.0)),
+        text_channel_id: Set(stringify(text_channel_id.0)),
+        user_id: Set(stringify(user_id.0)),
+        query: Set(query.to_owned()),
+        run_at: Set(run_at),
+        ..Default::default()
+    };
+    model
+        .insert(db)
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))
+}
+
+/// Delete a schedule, returns the number of deleted rows
+pub async fn delete_schedule(guild_id: GuildId, id: i64) -> Result<u64, MusicError> {
+    let db = database::connection()?;
+
+    let res = entity::Entity::delete_by_id(id)
+        .filter(entity::Column::GuildId.eq(stringify(guild_id.0)))
+        .exec(db)
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?;
+    Ok(res.rows_affected)
+}
+
+pub async fn guild_schedules(guild_id: GuildId) -> Result<Vec<entity::Model>, MusicError> {
+    let db = database::connection()?;
+
+    entity::Entity::find()
+        .filter(entity::Column::GuildId.eq(stringify(guild_id.0)))
+        .order_by_asc(entity::Column::RunAt)
+        .all(db)
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))
+}
+
+pub async fn all_schedules() -> anyhow::Result<Vec<entity::Model>> {
+    let db = database::connection()?;
+    Ok(entity::Entity::find().all(db).await?)
+}
@@ -0,0 +1,244 @@
+mod entity;
+mod helpers;
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use poise::serenity_prelude as serenity;
+use serenity::{ChannelId, GuildId, TypeMapKey, UserId};
+use time::{OffsetDateTime, Time};
+use tokio::task::JoinHandle;
+
+pub use entity::Model as Schedule;
+pub use helpers::{create_table, guild_schedules};
+
+use super::database::parse_id;
+use super::error::{log_error, InternalError, MusicError};
+use super::playlist::resolve_queries;
+use super::queue::{add_tracks_to_channel, TrackRequest};
+use super::settings::get_settings;
+use crate::message::{SendMessage, SendableMessage};
+use crate::{Data, PoiseContext};
+
+/// Schedules missed by more than this while the bot was offline are dropped
+const MAX_MISSED: Duration = Duration::from_secs(600);
+
+/// Waiting tasks of pending schedules by schedule id
+pub struct ScheduleTaskMap;
+
+impl TypeMapKey for ScheduleTaskMap {
+    type Value = HashMap<i64, JoinHandle<()>>;
+}
+
+impl Schedule {
+    pub fn voice_channel_id(&self) -> Option<ChannelId> {
+        parse_id(&self.voice_channel_id).map(ChannelId)
+    }
+
+    pub fn run_at(&self) -> Option<OffsetDateTime> {
+        OffsetDateTime::from_unix_timestamp(self.run_at).ok()
+    }
+}
+
+/// Queue the query in the author's voice channel at the next occurrence of the given time of day
+/// in the guild's timezone
+pub async fn add_schedule(
+    ctx: PoiseContext<'_>,
+    time: Time,
+    voice_channel_id: ChannelId,
+    query: &str,
+) -> Result<Schedule, MusicError> {
+    let guild_id = ctx.guild_id().ok_or(MusicError::GetVoice)?;
+    let settings = get_settings(ctx.serenity_context(), guild_id).await;
+    let now = OffsetDateTime::now_utc().to_offset(settings.utc_offset);
+    let run_at = next_occurrence(now, time);
+
+    let schedule = helpers::insert_schedule(
+        guild_id,
+        voice_channel_id,
+        ctx.channel_id(),
+        ctx.author().id,
+        query,
+        run_at.unix_timestamp(),
+    )
+    .await?;
+    spawn_schedule(ctx.serenity_context(), ctx.data(), schedule.clone()).await;
+
+    Ok(schedule)
+}
+
+/// Remove a pending schedule, returns whether it existed
+pub async fn cancel_schedule(ctx: PoiseContext<'_>, id: i64) -> Result<bool, MusicError> {
+    let guild_id = ctx.guild_id().ok_or(MusicError::GetVoice)?;
+    let deleted = helpers::delete_schedule(guild_id, id).await?;
+    if deleted == 0 {
+        return Ok(false);
+    }
+
+    let mut data = ctx.serenity_context().data.write().await;
+    if let Some(task) = data
+        .get_mut::<ScheduleTaskMap>()
+        .and_then(|tasks| tasks.remove(&id))
+    {
+        task.abort();
+    }
+
+    Ok(true)
+}
+
+/// Start waiting for all schedules stored in the database
+pub async fn start_schedules(ctx: &serenity::Context, data: &Data) {
+    let schedules = match helpers::all_schedules().await {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Error loading schedules: {e}");
+            return;
+        }
+    };
+    for schedule in schedules {
+        spawn_schedule(ctx, data, schedule).await;
+    }
+}
+
+async fn spawn_schedule(ctx: &serenity::Context, data: &Data, schedule: Schedule) {
+    let mut type_data = ctx.data.write().await;
+    let tasks = match type_data.get_mut::<ScheduleTaskMap>() {
+        Some(t) => t,
+        None => return,
+    };
+    // Already waiting, e.g. after reconnecting to the gateway
+    if tasks.contains_key(&schedule.id) {
+        return;
+    }
+
+    let id = schedule.id;
+    let ctx = ctx.clone();
+    let (prefetch_time, crossfade_time) = (data.prefetch_time, data.crossfade_time);
+    let task = tokio::spawn(async move {
+        let wait = schedule.run_at - OffsetDateTime::now_utc().unix_timestamp();
+        if wait > 0 {
+            tokio::time::sleep(Duration::from_secs(wait as u64)).await;
+        }
+
+        // Schedules only run once
+        if let Some(tasks) = ctx.data.write().await.get_mut::<ScheduleTaskMap>() {
+            tasks.remove(&schedule.id);
+        }
+        let guild_id = parse_id(&schedule.guild_id).map(GuildId);
+        if let Some(guild_id) = guild_id {
+            let _ = helpers::delete_schedule(guild_id, schedule.id).await;
+        }
+        if -wait > MAX_MISSED.as_secs() as i64 {
+            return;
+        }
+
+        let (guild_id, text_channel_id, voice_channel_id, user_id) = match (
+            guild_id,
+            parse_id(&schedule.text_channel_id).map(ChannelId),
+            schedule.voice_channel_id(),
+            parse_id(&schedule.user_id).map(UserId),
+        ) {
+            (Some(g), Some(t), Some(v), Some(u)) => (g, t, v, u),
+            _ => return,
+        };
+        let text_channel_id = get_settings(&ctx, guild_id)
+            .await
+            .announcement_channel(text_channel_id);
+        let request = TrackRequest {
+            ctx,
+            guild_id,
+            channel_id: text_channel_id,
+            author_id: user_id,
+            prefetch_time,
+            crossfade_time,
+        };
+        if let Err(e) = run_schedule(&request, voice_channel_id, &schedule.query).await {
+            let result = SendMessage::Error(format!("scheduled playback failed: {}", e))
+                .send_msg_http(&request.ctx.http, text_channel_id)
+                .await;
+            log_error(guild_id, result.map_err(InternalError::SendMessage));
+        }
+    });
+    tasks.insert(id, task);
+}
+
+async fn run_schedule(
+    request: &TrackRequest,
+    voice_channel_id: ChannelId,
+    query: &str,
+) -> Result<(), MusicError> {
+    let queries = resolve_queries(query).await?;
+    add_tracks_to_channel(request, voice_channel_id, queries).await
+}
+
+/// Next time the given time of day occurs after `now`, in the same offset as `now`
+fn next_occurrence(now: OffsetDateTime, time: Time) -> OffsetDateTime {
+    let today = now.replace_time(time);
+    if today > now {
+        today
+    } else {
+        today + time::Duration::days(1)
+    }
+}
+
+/// Parse a time of day such as "19:30", "7:30pm" or "7pm"
+pub fn parse_time_of_day(s: &str) -> Option<Time> {
+    let s = s.trim().to_lowercase();
+    let (s, pm) = if let Some(s) = s.strip_suffix("pm") {
+        (s.trim(), Some(true))
+    } else if let Some(s) = s.strip_suffix("am") {
+        (s.trim(), Some(false))
+    } else {
+        (s.as_str(), None)
+    };
+
+    let (hour, minute) = match (s.split_once(':'), pm) {
+        (Some((h, m)), _) => (h.parse::<u8>().ok()?, m.parse::<u8>().ok()?),
+        (None, Some(_)) => (s.parse::<u8>().ok()?, 0),
+        (None, None) => return None,
+    };
+    let hour = match pm {
+        Some(_) if !(1..=12).contains(&hour) => return None,
+        Some(true) => hour % 12 + 12,
+        Some(false) => hour % 12,
+        None => hour,
+    };
+
+    Time::from_hms(hour, minute, 0).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use time::macros::{datetime, time};
+
+    use super::*;
+
+    #[test]
+    fn test_parse_time_of_day() {
+        assert_eq!(Some(time!(19:30)), parse_time_of_day("19:30"));
+        assert_eq!(Some(time!(7:05)), parse_time_of_day("7:05"));
+        assert_eq!(Some(time!(19:30)), parse_time_of_day("7:30 PM"));
+        assert_eq!(Some(time!(0:00)), parse_time_of_day("12am"));
+        assert_eq!(Some(time!(12:00)), parse_time_of_day("12pm"));
+        assert_eq!(None, parse_time_of_day("19"));
+        assert_eq!(None, parse_time_of_day("13pm"));
+        assert_eq!(None, parse_time_of_day("24:00"));
+    }
+
+    #[test]
+    fn test_next_occurrence() {
+        let now = datetime!(2024-03-01 18:00 +09:00);
+        assert_eq!(
+            datetime!(2024-03-01 19:30 +09:00),
+            next_occurrence(now, time!(19:30))
+        );
+        assert_eq!(
+            datetime!(2024-03-02 07:00 +09:00),
+            next_occurrence(now, time!(7:00))
+        );
+        assert_eq!(
+            datetime!(2024-03-02 18:00 +09:00),
+            next_occurrence(now, time!(18:00))
+        );
+    }
+}
@@ -0,0 +1,481 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use md5::{Digest, Md5};
+use serde::Deserialize;
+use serde_json::json;
+use songbird::input::Metadata;
+
+use super::ScrobbleService;
+use crate::music::MusicError;
+use crate::CLIENT;
+
+pub const LASTFM_API_URL: &str = "https://ws.audioscrobbler.com/2.0/";
+pub const LISTENBRAINZ_API_URL: &str = "https://api.listenbrainz.org";
+const LASTFM_AUTH_URL: &str = "https://www.last.fm/api/auth/";
+
+/// Track information sent to scrobbling services
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScrobbleTrack {
+    pub artist: String,
+    pub title: String,
+    pub duration: Option<Duration>,
+    pub url: Option<String>,
+}
+
+impl ScrobbleTrack {
+    /// Tracks without a known artist can't be scrobbled
+    pub fn from_metadata(metadata: &Metadata) -> Option<Self> {
+        let artist = metadata.artist.clone().or_else(|| {
+            // Auto-generated YouTube Music channels are named after the artist
+            metadata
+                .channel
+                .as_ref()
+                .map(|c| c.trim_end_matches(" - Topic").to_owned())
+        })?;
+        let title = metadata.track.clone().or_else(|| metadata.title.clone())?;
+        Some(Self {
+            artist,
+            title,
+            duration: metadata.duration,
+            url: metadata.source_url.clone(),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+struct LastfmKey {
+    api_key: String,
+    secret: String,
+}
+
+/// Client of the Last.fm and ListenBrainz APIs
+#[derive(Clone, Debug)]
+pub struct ScrobbleClient {
+    lastfm_url: String,
+    lastfm_key: Option<LastfmKey>,
+    listenbrainz_url: String,
+}
+
+impl ScrobbleClient {
+    pub fn new(
+        lastfm_url: &str,
+        lastfm_api_key: Option<String>,
+        lastfm_api_secret: Option<String>,
+        listenbrainz_url: &str,
+    ) -> Self {
+        let lastfm_key = match (lastfm_api_key, lastfm_api_secret) {
+            (Some(api_key), Some(secret)) => Some(LastfmKey { api_key, secret }),
+            _ => None,
+        };
+        Self {
+            lastfm_url: lastfm_url.to_owned(),
+            lastfm_key,
+            listenbrainz_url: listenbrainz_url.trim_end_matches('/').to_owned(),
+        }
+    }
+
+    pub fn lastfm_enabled(&self) -> bool {
+        self.lastfm_key.is_some()
+    }
+
+    /// Start linking a Last.fm account, returns a request token and the URL where the user
+    /// authorizes it
+    pub async fn lastfm_auth_url(&self) -> Result<(String, String), MusicError> {
+        #[derive(Deserialize)]
+        struct TokenResponse {
+            token: String,
+        }
+
+        let key = self.lastfm_key()?;
+        let resp: TokenResponse = self.lastfm_call("auth.getToken", BTreeMap::new()).await?;
+        let url = format!(
+            "{}?api_key={}&token={}",
+            LASTFM_AUTH_URL, key.api_key, resp.token
+        );
+        Ok((resp.token, url))
+    }
+
+    /// Finish linking a Last.fm account, returns the session key and username
+    pub async fn lastfm_session(&self, token: &str) -> Result<(String, String), MusicError> {
+        #[derive(Deserialize)]
+        struct Session {
+            name: String,
+            key: String,
+        }
+        #[derive(Deserialize)]
+        struct SessionResponse {
+            session: Session,
+        }
+
+        let params = BTreeMap::from([("token", token.to_owned())]);
+        let resp: SessionResponse = self
+            .lastfm_call("auth.getSession", params)
+            .await
+            .map_err(|_| MusicError::BadToken)?;
+        Ok((resp.session.key, resp.session.name))
+    }
+
+    /// Check a ListenBrainz user token, returns the username
+    pub async fn listenbrainz_validate(&self, token: &str) -> Result<String, MusicError> {
+        #[derive(Deserialize)]
+        struct ValidateResponse {
+            valid: bool,
+            user_name: Option<String>,
+        }
+
+        let text = CLIENT
+            .next()
+            .get(format!("{}/1/validate-token", self.listenbrainz_url))
+            .header("Authorization", format!("Token {}", token))
+            .send()
+            .await
+            .map_err(|e| MusicError::Internal(e.into()))?
+            .text()
+            .await
+            .map_err(|e| MusicError::Internal(e.into()))?;
+        match serde_json::from_str::<ValidateResponse>(&text) {
+            Ok(ValidateResponse {
+                valid: true,
+                user_name: Some(name),
+            }) => Ok(name),
+            _ => Err(MusicError::BadToken),
+        }
+    }
+
+    pub async fn now_playing(
+        &self,
+        service: ScrobbleService,
+        token: &str,
+        track: &ScrobbleTrack,
+    ) -> Result<(), MusicError> {
+        match service {
+            ScrobbleService::Lastfm => {
+                let params = lastfm_track_params(token, track);
+                self.lastfm_call::<serde_json::Value>("track.updateNowPlaying", params)
+                    .await?;
+            }
+            ScrobbleService::ListenBrainz => {
+                let payload = json!({
+                    "listen_type": "playing_now",
+                    "payload": [{ "track_metadata": listenbrainz_metadata(track) }],
+                });
+                self.listenbrainz_submit(token, payload).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Scrobble a track that started playing at the given Unix timestamp
+    pub async fn scrobble(
+        &self,
+        service: ScrobbleService,
+        token: &str,
+        track: &ScrobbleTrack,
+        timestamp: i64,
+    ) -> Result<(), MusicError> {
+        match service {
+            ScrobbleService::Lastfm => {
+                let mut params = lastfm_track_params(token, track);
+                params.insert("timestamp", timestamp.to_string());
+                self.lastfm_call::<serde_json::Value>("track.scrobble", params)
+                    .await?;
+            }
+            ScrobbleService::ListenBrainz => {
+                let payload = json!({
+                    "listen_type": "single",
+                    "payload": [{
+                        "listened_at": timestamp,
+                        "track_metadata": listenbrainz_metadata(track),
+                    }],
+                });
+                self.listenbrainz_submit(token, payload).await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn lastfm_key(&self) -> Result<&LastfmKey, MusicError> {
+        self.lastfm_key
+            .as_ref()
+            .ok_or(MusicError::ScrobblingDisabled)
+    }
+
+    /// Call a signed Last.fm API method
+    async fn lastfm_call<T: for<'de> Deserialize<'de>>(
+        &self,
+        method: &str,
+        mut params: BTreeMap<&str, String>,
+    ) -> Result<T, MusicError> {
+        let key = self.lastfm_key()?;
+        params.insert("method", method.to_owned());
+        params.insert("api_key", key.api_key.clone());
+        let signature = lastfm_signature(&params, &key.secret);
+        params.insert("api_sig", signature);
+        params.insert("format", "json".to_owned());
+
+        let text = CLIENT
+            .next()
+            .post(&self.lastfm_url)
+            .form(&params)
+            .send()
+            .await
+            .map_err(|e| MusicError::Internal(e.into()))?
+            .text()
+            .await
+            .map_err(|e| MusicError::Internal(e.into()))?;
+
+        // Errors are returned as {"error": code, "message": "..."}
+        let value: serde_json::Value =
+            serde_json::from_str(&text).map_err(|e| MusicError::Internal(e.into()))?;
+        if let Some(message) = value
+            .get("message")
+            .filter(|_| value.get("error").is_some())
+        {
+            return Err(MusicError::Internal(anyhow::anyhow!(
+                "Last.fm {} failed: {}",
+                method,
+                message
+            )));
+        }
+        serde_json::from_value(value).map_err(|e| MusicError::Internal(e.into()))
+    }
+
+    async fn listenbrainz_submit(
+        &self,
+        token: &str,
+        payload: serde_json::Value,
+    ) -> Result<(), MusicError> {
+        let resp = CLIENT
+            .next()
+            .post(format!("{}/1/submit-listens", self.listenbrainz_url))
+            .header("Authorization", format!("Token {}", token))
+            .header("Content-Type", "application/json")
+            .body(payload.to_string())
+            .send()
+            .await
+            .map_err(|e| MusicError::Internal(e.into()))?;
+        if !resp.status().is_success() {
+            let status = resp.status();
+            let text = resp.text().await.unwrap_or_default();
+            return Err(MusicError::Internal(anyhow::anyhow!(
+                "ListenBrainz submission failed with {}: {}",
+                status,
+                text
+            )));
+        }
+        Ok(())
+    }
+}
+
+fn lastfm_track_params<'a>(session_key: &str, track: &ScrobbleTrack) -> BTreeMap<&'a str, String> {
+    let mut params = BTreeMap::from([
+        ("sk", session_key.to_owned()),
+        ("artist", track.artist.clone()),
+        ("track", track.title.clone()),
+    ]);
+    if let Some(duration) = track.duration {
+        params.insert("duration", duration.as_secs().to_string());
+    }
+    params
+}
+
+/// Signature of Last.fm API parameters, the MD5 of all parameters sorted by name followed by
+/// the API secret
+fn lastfm_signature(params: &BTreeMap<&str, String>, secret: &str) -> String {
+    let mut hasher = Md5::new();
+    for (name, value) in params {
+        hasher.update(name.as_bytes());
+        hasher.update(value.as_bytes());
+    }
+    hasher.update(secret.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+fn listenbrainz_metadata(track: &ScrobbleTrack) -> serde_json::Value {
+    let mut info = json!({
+        "media_player": "Discord",
+        "submission_client": "insomnia-bot",
+    });
+    if let Some(duration) = track.duration {
+        info["duration_ms"] = json!(duration.as_millis() as u64);
+    }
+    if let Some(url) = &track.url {
+        info["origin_url"] = json!(url);
+    }
+    json!({
+        "artist_name": track.artist,
+        "track_name": track.title,
+        "additional_info": info,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    use super::*;
+
+    /// Serve a single request with the given JSON response, returns the API URL and the raw
+    /// request once it was received
+    async fn mock_api(response: &'static str) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let task = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = vec![];
+            let mut buf = [0; 4096];
+            loop {
+                let n = socket.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let len = head
+                        .lines()
+                        .find_map(|l| {
+                            l.to_lowercase()
+                                .strip_prefix("content-length:")?
+                                .trim()
+                                .parse()
+                                .ok()
+                        })
+                        .unwrap_or(0);
+                    if body.len() >= len {
+                        break;
+                    }
+                }
+                if n == 0 {
+                    break;
+                }
+            }
+            let reply = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                response.len(),
+                response
+            );
+            socket.write_all(reply.as_bytes()).await.unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        (url, task)
+    }
+
+    fn track() -> ScrobbleTrack {
+        ScrobbleTrack {
+            artist: "Artist".to_owned(),
+            title: "Song".to_owned(),
+            duration: Some(Duration::from_secs(200)),
+            url: None,
+        }
+    }
+
+    #[test]
+    fn test_from_metadata() {
+        let metadata = Metadata {
+            title: Some("Song (Official Video)".to_owned()),
+            track: Some("Song".to_owned()),
+            channel: Some("Artist - Topic".to_owned()),
+            ..Default::default()
+        };
+        let track = ScrobbleTrack::from_metadata(&metadata).unwrap();
+        assert_eq!("Artist", track.artist);
+        assert_eq!("Song", track.title);
+
+        assert_eq!(None, ScrobbleTrack::from_metadata(&Metadata::default()));
+    }
+
+    #[test]
+    fn test_lastfm_signature() {
+        let params = BTreeMap::from([
+            ("method", "auth.getSession".to_owned()),
+            ("api_key", "key".to_owned()),
+            ("token", "token".to_owned()),
+        ]);
+        // md5("api_keykeymethodauth.getSessiontokentokensecret")
+        assert_eq!(
+            "9ac306496295a8866c4a8673395540eb",
+            lastfm_signature(&params, "secret")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lastfm_scrobble() {
+        let (url, request) = mock_api(r#"{"scrobbles": {}}"#).await;
+        let client = ScrobbleClient::new(
+            &url,
+            Some("key".to_owned()),
+            Some("secret".to_owned()),
+            LISTENBRAINZ_API_URL,
+        );
+        client
+            .scrobble(ScrobbleService::Lastfm, "session", &track(), 1700000000)
+            .await
+            .unwrap();
+
+        let request = request.await.unwrap();
+        assert!(request.starts_with("POST /"));
+        for param in [
+            "method=track.scrobble",
+            "sk=session",
+            "artist=Artist",
+            "timestamp=1700000000",
+            "api_sig=",
+        ] {
+            assert!(request.contains(param), "{} not in {}", param, request);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_lastfm_error() {
+        let (url, _) = mock_api(r#"{"error": 9, "message": "Invalid session key"}"#).await;
+        let client = ScrobbleClient::new(
+            &url,
+            Some("key".to_owned()),
+            Some("secret".to_owned()),
+            LISTENBRAINZ_API_URL,
+        );
+        let result = client
+            .now_playing(ScrobbleService::Lastfm, "session", &track())
+            .await;
+        assert!(matches!(result, Err(MusicError::Internal(_))));
+    }
+
+    #[tokio::test]
+    async fn test_listenbrainz_now_playing() {
+        let (url, request) = mock_api(r#"{"status": "ok"}"#).await;
+        let client = ScrobbleClient::new(LASTFM_API_URL, None, None, &url);
+        client
+            .now_playing(ScrobbleService::ListenBrainz, "token", &track())
+            .await
+            .unwrap();
+
+        let request = request.await.unwrap();
+        assert!(request.starts_with("POST /1/submit-listens"));
+        assert!(request
+            .to_lowercase()
+            .contains("authorization: token token"));
+        let body = request.split_once("\r\n\r\n").unwrap().1;
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!("playing_now", body["listen_type"]);
+        assert_eq!("Song", body["payload"][0]["track_metadata"]["track_name"]);
+        assert_eq!(
+            200000,
+            body["payload"][0]["track_metadata"]["additional_info"]["duration_ms"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_listenbrainz_validate() {
+        let (url, _) = mock_api(r#"{"code": 200, "valid": true, "user_name": "user"}"#).await;
+        let client = ScrobbleClient::new(LASTFM_API_URL, None, None, &url);
+        assert_eq!("user", client.listenbrainz_validate("token").await.unwrap());
+
+        let (url, _) = mock_api(r#"{"code": 200, "valid": false}"#).await;
+        let client = ScrobbleClient::new(LASTFM_API_URL, None, None, &url);
+        assert!(matches!(
+            client.listenbrainz_validate("token").await,
+            Err(MusicError::BadToken)
+        ));
+    }
+}
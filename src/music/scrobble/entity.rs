@@ -0,0 +1,19 @@
+use sea_orm::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "music_scrobble_account")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub user_id: String,
+    /// "lastfm" or "listenbrainz"
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub service: String,
+    /// Last.fm session key or ListenBrainz user token
+    pub token: String,
+    pub username: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,70 @@
+use poise::serenity_prelude::UserId;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+
+use super::entity;
+use super::ScrobbleService;
+use crate::music::database::{self, stringify};
+use crate::music::MusicError;
+
+pub async fn create_table(db: &DatabaseConnection) -> anyhow::Result<()> {
+    database::create_table(db, entity::Entity).await?;
+    Ok(())
+}
+
+/// Link an account of a user, replacing a previously linked account of the same service
+pub async fn link_account(
+    user_id: UserId,
+    service: ScrobbleService,
+    token: &str,
+    username: &str,
+) -> Result<(), MusicError> {
+    let db = database::connection()?;
+
+    let model = entity::ActiveModel {
+        user_id: Set(stringify(user_id.0)),
+        service: Set(service.as_str().to_owned()),
+        token: Set(token.to_owned()),
+        username: Set(username.to_owned()),
+    };
+    entity::Entity::insert(model)
+        .on_conflict(
+            OnConflict::columns([entity::Column::UserId, entity::Column::Service])
+                .update_columns([entity::Column::Token, entity::Column::Username])
+                .to_owned(),
+        )
+        .exec(db)
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?;
+
+    Ok(())
+}
+
+/// Unlink an account, returns the number of deleted rows
+pub async fn unlink_account(user_id: UserId, service: ScrobbleService) -> Result<u64, MusicError> {
+    let db = database::connection()?;
+
+    let res = entity::Entity::delete_many()
+        .filter(entity::Column::UserId.eq(stringify(user_id.0)))
+        .filter(entity::Column::Service.eq(service.as_str()))
+        .exec(db)
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?;
+    Ok(res.rows_affected)
+}
+
+/// Linked accounts of all given users
+pub async fn user_accounts(user_ids: &[UserId]) -> Result<Vec<entity::Model>, MusicError> {
+    if user_ids.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let db = database::connection()?;
+
+    entity::Entity::find()
+        .filter(entity::Column::UserId.is_in(user_ids.iter().map(|u| stringify(u.0))))
+        .all(db)
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))
+}
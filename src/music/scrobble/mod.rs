@@ -0,0 +1,140 @@
+mod api;
+mod entity;
+mod helpers;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use poise::serenity_prelude as serenity;
+use serenity::GuildId;
+use time::OffsetDateTime;
+
+pub use api::{ScrobbleClient, ScrobbleTrack, LASTFM_API_URL, LISTENBRAINZ_API_URL};
+pub use helpers::{create_table, link_account, unlink_account, user_accounts};
+
+use super::bus::{spawn_subscriber, MusicEvent};
+use super::error::log_error;
+use super::voice::listeners;
+use crate::Data;
+
+/// Tracks shorter than this are never scrobbled
+const MIN_TRACK_DURATION: Duration = Duration::from_secs(30);
+/// Tracks are scrobbled once they played for half their duration or this long
+const MAX_SCROBBLE_TIME: Duration = Duration::from_secs(240);
+
+/// Set once the scrobbler is subscribed, since the bot can become ready more than once
+static SCROBBLER_STARTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum ScrobbleService {
+    #[name = "Last.fm"]
+    Lastfm,
+    #[name = "ListenBrainz"]
+    ListenBrainz,
+}
+
+impl ScrobbleService {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Lastfm => "lastfm",
+            Self::ListenBrainz => "listenbrainz",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "lastfm" => Some(Self::Lastfm),
+            "listenbrainz" => Some(Self::ListenBrainz),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a track was played long enough to be scrobbled
+pub fn should_scrobble(duration: Option<Duration>, play_time: Duration) -> bool {
+    match duration {
+        Some(d) if d >= MIN_TRACK_DURATION => play_time >= std::cmp::min(d / 2, MAX_SCROBBLE_TIME),
+        _ => false,
+    }
+}
+
+/// Send now playing updates and scrobbles for every listener with a linked account
+pub fn start_scrobbler(ctx: &serenity::Context, data: &Data) {
+    if SCROBBLER_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let ctx = ctx.clone();
+    let client = data.scrobble_client.clone();
+    spawn_subscriber("scrobbler", move |event| {
+        let ctx = ctx.clone();
+        let client = client.clone();
+        async move {
+            let (track, play_time) = match &event {
+                MusicEvent::TrackStarted { track, .. } => (track, None),
+                MusicEvent::TrackEnded {
+                    track, play_time, ..
+                } if should_scrobble(track.metadata().duration, *play_time) => {
+                    (track, Some(*play_time))
+                }
+                _ => return,
+            };
+            let track = match ScrobbleTrack::from_metadata(track.metadata()) {
+                Some(t) => t,
+                None => return,
+            };
+
+            // Scrobbles are sent in the background so other events are not delayed
+            let guild_id = event.guild_id();
+            let users = listeners(&ctx, guild_id).await;
+            tokio::spawn(async move {
+                submit(&client, guild_id, &users, &track, play_time).await;
+            });
+        }
+    });
+}
+
+/// Send a now playing update, or a scrobble if the track was played for `play_time`
+async fn submit(
+    client: &ScrobbleClient,
+    guild_id: GuildId,
+    users: &[serenity::UserId],
+    track: &ScrobbleTrack,
+    play_time: Option<Duration>,
+) {
+    let accounts = match user_accounts(users).await {
+        Ok(a) => a,
+        Err(e) => return log_error(guild_id, Err(e)),
+    };
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    for account in accounts {
+        let service = match ScrobbleService::parse(&account.service) {
+            Some(s) => s,
+            None => continue,
+        };
+        let result = match play_time {
+            Some(t) => {
+                let started = now - t.as_secs() as i64;
+                client
+                    .scrobble(service, &account.token, track, started)
+                    .await
+            }
+            None => client.now_playing(service, &account.token, track).await,
+        };
+        log_error(guild_id, result);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_should_scrobble() {
+        let secs = Duration::from_secs;
+        assert!(should_scrobble(Some(secs(200)), secs(100)));
+        assert!(!should_scrobble(Some(secs(200)), secs(99)));
+        assert!(should_scrobble(Some(secs(600)), secs(240)));
+        assert!(!should_scrobble(Some(secs(20)), secs(20)));
+        assert!(!should_scrobble(None, secs(600)));
+    }
+}
@@ -0,0 +1,22 @@
+use sea_orm::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "music_settings")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub guild_id: String,
+    pub idle_timeout_seconds: i64,
+    pub alone_timeout_seconds: i64,
+    pub always_on: bool,
+    pub always_on_channel_id: Option<String>,
+    pub utc_offset_seconds: i32,
+    pub music_channel_id: Option<String>,
+    pub reject_other_channels: bool,
+    pub quiet_mode: bool,
+    pub clean_now_playing: bool,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use poise::serenity_prelude::{ChannelId, GuildId};
+use sea_orm::sea_query::{ColumnDef, OnConflict, Table};
+use sea_orm::ActiveValue::Set;
+use sea_orm::{ConnectionTrait, DatabaseConnection, EntityTrait};
+use time::UtcOffset;
+
+use super::{entity, GuildSettings, GuildSettingsMap};
+use crate::music::database::{self, parse_id, stringify};
+use crate::music::MusicError;
+use crate::PoiseContext;
+
+pub async fn create_table(db: &DatabaseConnection) -> anyhow::Result<()> {
+    database::create_table(db, entity::Entity).await?;
+
+    // Columns added after the table was first created
+    let backend = db.get_database_backend();
+    let columns = [
+        ColumnDef::new(entity::Column::UtcOffsetSeconds)
+            .integer()
+            .not_null()
+            .default(0)
+            .to_owned(),
+        ColumnDef::new(entity::Column::MusicChannelId)
+            .string()
+            .null()
+            .to_owned(),
+        ColumnDef::new(entity::Column::RejectOtherChannels)
+            .boolean()
+            .not_null()
+            .default(false)
+            .to_owned(),
+        ColumnDef::new(entity::Column::QuietMode)
+            .boolean()
+            .not_null()
+            .default(false)
+            .to_owned(),
+        ColumnDef::new(entity::Column::CleanNowPlaying)
+            .boolean()
+            .not_null()
+            .default(false)
+            .to_owned(),
+    ];
+    for mut column in columns {
+        let stmt = Table::alter()
+            .table(entity::Entity)
+            .add_column_if_not_exists(&mut column)
+            .to_owned();
+        db.execute(backend.build(&stmt)).await?;
+    }
+
+    Ok(())
+}
+
+/// Read the settings of all guilds
+pub async fn load_settings() -> anyhow::Result<HashMap<GuildId, GuildSettings>> {
+    let db = database::connection()?;
+
+    let models = entity::Entity::find().all(db).await?;
+    Ok(models
+        .into_iter()
+        .filter_map(|m| {
+            let guild_id = GuildId(parse_id(&m.guild_id)?);
+            Some((guild_id, m.into()))
+        })
+        .collect())
+}
+
+/// Store the settings of a guild in the database and update the cached settings
+pub async fn save_settings(
+    ctx: &PoiseContext<'_>,
+    guild_id: GuildId,
+    settings: GuildSettings,
+) -> Result<(), MusicError> {
+    let db = database::connection()?;
+
+    let model = entity::ActiveModel {
+        guild_id: Set(stringify(guild_id.0)),
+        idle_timeout_seconds: Set(settings.idle_timeout.as_secs() as i64),
+        alone_timeout_seconds: Set(settings.alone_timeout.as_secs() as i64),
+        always_on: Set(settings.always_on),
+        always_on_channel_id: Set(settings.always_on_channel.map(|c| stringify(c.0))),
+        utc_offset_seconds: Set(settings.utc_offset.whole_seconds()),
+        music_channel_id: Set(settings.music_channel.map(|c| stringify(c.0))),
+        reject_other_channels: Set(settings.reject_other_channels),
+        quiet_mode: Set(settings.quiet_mode),
+        clean_now_playing: Set(settings.clean_now_playing),
+    };
+    entity::Entity::insert(model)
+        .on_conflict(
+            OnConflict::column(entity::Column::GuildId)
+                .update_columns([
+                    entity::Column::IdleTimeoutSeconds,
+                    entity::Column::AloneTimeoutSeconds,
+                    entity::Column::AlwaysOn,
+                    entity::Column::AlwaysOnChannelId,
+                    entity::Column::UtcOffsetSeconds,
+                    entity::Column::MusicChannelId,
+                    entity::Column::RejectOtherChannels,
+                    entity::Column::QuietMode,
+                    entity::Column::CleanNowPlaying,
+                ])
+                .to_owned(),
+        )
+        .exec(db)
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?;
+
+    let mut data = ctx.serenity_context().data.write().await;
+    if let Some(map) = data.get_mut::<GuildSettingsMap>() {
+        map.insert(guild_id, settings);
+    }
+
+    Ok(())
+}
+
+impl From<entity::Model> for GuildSettings {
+    fn from(model: entity::Model) -> Self {
+        Self {
+            idle_timeout: Duration::from_secs(model.idle_timeout_seconds.max(0) as u64),
+            alone_timeout: Duration::from_secs(model.alone_timeout_seconds.max(0) as u64),
+            always_on: model.always_on,
+            always_on_channel: model
+                .always_on_channel_id
+                .and_then(|c| parse_id(&c))
+                .map(ChannelId),
+            utc_offset: UtcOffset::from_whole_seconds(model.utc_offset_seconds)
+                .unwrap_or(UtcOffset::UTC),
+            music_channel: model
+                .music_channel_id
+                .and_then(|c| parse_id(&c))
+                .map(ChannelId),
+            reject_other_channels: model.reject_other_channels,
+            quiet_mode: model.quiet_mode,
+            clean_now_playing: model.clean_now_playing,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_settings_from_model() {
+        let model = entity::Model {
+            guild_id: stringify(1),
+            idle_timeout_seconds: 60,
+            alone_timeout_seconds: -1,
+            always_on: true,
+            always_on_channel_id: Some(stringify(0xabc)),
+            utc_offset_seconds: -3600,
+            music_channel_id: None,
+            reject_other_channels: false,
+            quiet_mode: true,
+            clean_now_playing: false,
+        };
+        let settings = GuildSettings::from(model);
+        assert_eq!(Duration::from_secs(60), settings.idle_timeout);
+        assert_eq!(Duration::ZERO, settings.alone_timeout);
+        assert!(settings.always_on);
+        assert_eq!(Some(ChannelId(0xabc)), settings.always_on_channel);
+        assert_eq!(UtcOffset::from_hms(-1, 0, 0).unwrap(), settings.utc_offset);
+        assert_eq!(None, settings.music_channel);
+        assert!(settings.quiet_mode);
+    }
+}
@@ -0,0 +1,224 @@
+mod entity;
+mod helpers;
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use poise::serenity_prelude as serenity;
+use serenity::{ChannelId, GuildId, TypeMapKey};
+use time::UtcOffset;
+
+use super::error::MusicError;
+use crate::{PoiseContext, PoiseError};
+
+pub use helpers::{create_table, load_settings, save_settings};
+
+/// Default time to stay in a voice channel after the queue ends
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(600);
+/// Default time to stay in a voice channel after everyone else leaves
+const DEFAULT_ALONE_TIMEOUT: Duration = Duration::from_secs(600);
+/// Longest timeout that can be set, in minutes
+const MAX_TIMEOUT_MINUTES: u64 = 7 * 24 * 60;
+
+/// Per-guild music settings
+#[derive(Clone, Debug, PartialEq)]
+pub struct GuildSettings {
+    pub idle_timeout: Duration,
+    pub alone_timeout: Duration,
+    /// Never leave the voice channel
+    pub always_on: bool,
+    /// Voice channel to rejoin in 24/7 mode
+    pub always_on_channel: Option<ChannelId>,
+    /// Timezone used for scheduled playback, a fixed offset that does not follow daylight saving
+    /// time
+    pub utc_offset: UtcOffset,
+    /// Text channel for music commands and announcements
+    pub music_channel: Option<ChannelId>,
+    /// Reject music commands outside of the music channel instead of only redirecting
+    /// announcements
+    pub reject_other_channels: bool,
+    /// Keep a single now playing message updated instead of announcing every track
+    pub quiet_mode: bool,
+    /// Delete the previous now playing message when the next track starts
+    pub clean_now_playing: bool,
+}
+
+impl Default for GuildSettings {
+    fn default() -> Self {
+        Self {
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            alone_timeout: DEFAULT_ALONE_TIMEOUT,
+            always_on: false,
+            always_on_channel: None,
+            utc_offset: UtcOffset::UTC,
+            music_channel: None,
+            reject_other_channels: false,
+            quiet_mode: false,
+            clean_now_playing: false,
+        }
+    }
+}
+
+impl GuildSettings {
+    /// Channel to send announcements to, given the channel a command was used in
+    pub fn announcement_channel(&self, channel_id: ChannelId) -> ChannelId {
+        self.music_channel.unwrap_or(channel_id)
+    }
+
+    /// Whether music commands may be used in a channel
+    pub fn allows_channel(&self, channel_id: ChannelId) -> bool {
+        !self.reject_other_channels || self.music_channel.is_none_or(|c| c == channel_id)
+    }
+}
+
+/// How music commands used outside of the music channel are handled
+#[derive(Clone, Copy, Debug, poise::ChoiceParameter)]
+pub enum MusicChannelMode {
+    /// No music channel
+    #[name = "off"]
+    Off,
+    /// Allow commands anywhere but send announcements to the music channel
+    #[name = "redirect"]
+    Redirect,
+    /// Only allow commands in the music channel
+    #[name = "reject"]
+    Reject,
+}
+
+/// Settings of every guild that changed them from the defaults
+pub struct GuildSettingsMap;
+
+impl TypeMapKey for GuildSettingsMap {
+    type Value = HashMap<GuildId, GuildSettings>;
+}
+
+pub async fn get_settings(ctx: &serenity::Context, guild_id: GuildId) -> GuildSettings {
+    ctx.data
+        .read()
+        .await
+        .get::<GuildSettingsMap>()
+        .and_then(|map| map.get(&guild_id))
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Command check rejecting music commands outside of the music channel
+pub async fn check_music_channel(ctx: PoiseContext<'_>) -> Result<bool, PoiseError> {
+    if ctx.command().category != Some("Music") {
+        return Ok(true);
+    }
+    let guild_id = match ctx.guild_id() {
+        Some(g) => g,
+        None => return Ok(true),
+    };
+
+    let settings = get_settings(ctx.serenity_context(), guild_id).await;
+    match settings.music_channel {
+        Some(c) if !settings.allows_channel(ctx.channel_id()) => {
+            Err(MusicError::WrongTextChannel(c).into())
+        }
+        _ => Ok(true),
+    }
+}
+
+/// Parse a timezone given as a UTC offset, e.g. "UTC+9", "-05:30" or "+0200"
+pub fn parse_utc_offset(s: &str) -> Option<UtcOffset> {
+    let s = s.trim();
+    let s = match s.get(..3) {
+        Some(prefix)
+            if prefix.eq_ignore_ascii_case("utc") || prefix.eq_ignore_ascii_case("gmt") =>
+        {
+            &s[3..]
+        }
+        _ => s,
+    };
+    if s.is_empty() {
+        return Some(UtcOffset::UTC);
+    }
+
+    let (sign, s) = if let Some(rest) = s.strip_prefix('+') {
+        (1, rest)
+    } else if let Some(rest) = s.strip_prefix('-') {
+        (-1, rest)
+    } else {
+        return None;
+    };
+    let (hours, minutes) = match s.split_once(':') {
+        Some((h, m)) => (h, m),
+        // Slicing fails instead of panicking if the split is inside a character
+        None if s.len() > 2 => (s.get(..s.len() - 2)?, s.get(s.len() - 2..)?),
+        None => (s, "0"),
+    };
+    let hours: i8 = hours.parse().ok()?;
+    let minutes: i8 = minutes.parse().ok()?;
+    if !(0..60).contains(&minutes) {
+        return None;
+    }
+
+    UtcOffset::from_hms(sign * hours, sign * minutes, 0).ok()
+}
+
+/// Timeout from a number of minutes, 0 leaves right away
+pub fn timeout_from_minutes(minutes: u64) -> Option<Duration> {
+    if minutes > MAX_TIMEOUT_MINUTES {
+        return None;
+    }
+    minutes.checked_mul(60).map(Duration::from_secs)
+}
+
+/// Format a UTC offset as e.g. "UTC+09:00"
+pub fn format_utc_offset(offset: UtcOffset) -> String {
+    let (hours, minutes, _) = offset.as_hms();
+    let sign = if offset.is_negative() { '-' } else { '+' };
+    format!("UTC{}{:02}:{:02}", sign, hours.abs(), minutes.abs())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_utc_offset() {
+        let offset = |h, m| UtcOffset::from_hms(h, m, 0).unwrap();
+        assert_eq!(Some(UtcOffset::UTC), parse_utc_offset("UTC"));
+        assert_eq!(Some(offset(9, 0)), parse_utc_offset("UTC+9"));
+        assert_eq!(Some(offset(-5, -30)), parse_utc_offset("-05:30"));
+        assert_eq!(Some(offset(2, 0)), parse_utc_offset("gmt+0200"));
+        assert_eq!(None, parse_utc_offset("9"));
+        assert_eq!(None, parse_utc_offset("+5:75"));
+        assert_eq!(None, parse_utc_offset("Europe/Berlin"));
+        assert_eq!(None, parse_utc_offset("UTC−5"));
+        assert_eq!(None, parse_utc_offset("+ü5"));
+        assert_eq!(None, parse_utc_offset("+1ü"));
+        assert_eq!("UTC-05:30", format_utc_offset(offset(-5, -30)));
+    }
+
+    #[test]
+    fn test_timeout_from_minutes() {
+        assert_eq!(Some(Duration::ZERO), timeout_from_minutes(0));
+        assert_eq!(Some(Duration::from_secs(600)), timeout_from_minutes(10));
+        assert_eq!(
+            Some(Duration::from_secs(7 * 24 * 3600)),
+            timeout_from_minutes(MAX_TIMEOUT_MINUTES)
+        );
+        assert_eq!(None, timeout_from_minutes(MAX_TIMEOUT_MINUTES + 1));
+        assert_eq!(None, timeout_from_minutes(u64::MAX));
+    }
+
+    #[test]
+    fn test_music_channel() {
+        let mut settings = GuildSettings {
+            music_channel: Some(ChannelId(1)),
+            ..Default::default()
+        };
+        assert_eq!(ChannelId(1), settings.announcement_channel(ChannelId(2)));
+        assert!(settings.allows_channel(ChannelId(2)));
+
+        settings.reject_other_channels = true;
+        assert!(settings.allows_channel(ChannelId(1)));
+        assert!(!settings.allows_channel(ChannelId(2)));
+
+        let settings = GuildSettings::default();
+        assert_eq!(ChannelId(2), settings.announcement_channel(ChannelId(2)));
+    }
+}
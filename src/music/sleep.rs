@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use poise::serenity_prelude as serenity;
+use serenity::{async_trait, ChannelId, GuildId, TypeMapKey};
+use songbird::{Event, EventContext, EventHandler as VoiceEventHandler, TrackEvent};
+use tokio::task::JoinHandle;
+
+use super::error::{log_error, InternalError};
+use super::events::stop_and_leave;
+use crate::message::{SendMessage, SendableMessage};
+
+/// Longest duration of a sleep timer
+const MAX_SLEEP_DURATION: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Pending sleep timer of each guild
+pub struct SleepTimerMap;
+
+impl TypeMapKey for SleepTimerMap {
+    type Value = HashMap<GuildId, JoinHandle<()>>;
+}
+
+/// Stop playing and leave the voice channel after the given duration, replacing any previous
+/// sleep timer
+///
+/// If `finish_track` is set, the track playing when the timer runs out is allowed to finish
+pub async fn set_sleep_timer(
+    ctx: &serenity::Context,
+    guild_id: GuildId,
+    chan_id: ChannelId,
+    duration: Duration,
+    finish_track: bool,
+) {
+    let task = {
+        let ctx = ctx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(duration).await;
+            if let Some(timers) = ctx.data.write().await.get_mut::<SleepTimerMap>() {
+                timers.remove(&guild_id);
+            }
+
+            let call = match songbird::get(&ctx).await.and_then(|m| m.get(guild_id)) {
+                Some(c) => c,
+                None => return,
+            };
+            let mut handler = call.lock().await;
+            if let (true, Some(track)) = (finish_track, handler.queue().current()) {
+                let _ = track.add_event(
+                    Event::Track(TrackEvent::End),
+                    SleepLeaver {
+                        ctx: ctx.clone(),
+                        guild_id,
+                        chan_id,
+                    },
+                );
+                return;
+            }
+
+            log_error(guild_id, stop_and_leave(&mut handler).await);
+            let result = SendMessage::Normal("Sleep timer ended")
+                .send_msg_http(&ctx.http, chan_id)
+                .await;
+            log_error(guild_id, result.map_err(InternalError::SendMessage));
+        })
+    };
+
+    let mut data = ctx.data.write().await;
+    if let Some(timers) = data.get_mut::<SleepTimerMap>() {
+        if let Some(old) = timers.insert(guild_id, task) {
+            old.abort();
+        }
+    }
+}
+
+/// Cancel the sleep timer, returns whether a timer was pending
+pub async fn cancel_sleep_timer(ctx: &serenity::Context, guild_id: GuildId) -> bool {
+    let mut data = ctx.data.write().await;
+    match data
+        .get_mut::<SleepTimerMap>()
+        .and_then(|timers| timers.remove(&guild_id))
+    {
+        Some(task) => {
+            task.abort();
+            true
+        }
+        None => false,
+    }
+}
+
+/// Leaves once the track playing when the sleep timer ran out has finished
+struct SleepLeaver {
+    ctx: serenity::Context,
+    guild_id: GuildId,
+    chan_id: ChannelId,
+}
+
+#[async_trait]
+impl VoiceEventHandler for SleepLeaver {
+    async fn act(&self, _ctx: &EventContext<'_>) -> Option<Event> {
+        if let Some(call) = songbird::get(&self.ctx)
+            .await
+            .and_then(|m| m.get(self.guild_id))
+        {
+            log_error(self.guild_id, stop_and_leave(&mut *call.lock().await).await);
+            let result = SendMessage::Normal("Sleep timer ended")
+                .send_msg_http(&self.ctx.http, self.chan_id)
+                .await;
+            log_error(self.guild_id, result.map_err(InternalError::SendMessage));
+        }
+
+        None
+    }
+}
+
+/// Parse a duration such as "1h30m", "45m" or "90s" of at most a day, plain numbers are minutes
+pub fn parse_duration(s: &str) -> Option<Duration> {
+    let s = s.trim().to_lowercase();
+    let total = if let Ok(minutes) = s.parse::<u64>() {
+        minutes.checked_mul(60)?
+    } else {
+        parse_units(&s)?
+    };
+
+    let duration = Duration::from_secs(total);
+    if duration > MAX_SLEEP_DURATION {
+        return None;
+    }
+    Some(duration)
+}
+
+/// Total seconds of a duration with units, e.g. "1h30m"
+fn parse_units(s: &str) -> Option<u64> {
+    let mut total: u64 = 0;
+    let mut num = String::new();
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            num.push(c);
+            continue;
+        }
+        let unit = match c {
+            'h' => 3600,
+            'm' => 60,
+            's' => 1,
+            _ => return None,
+        };
+        total = total.checked_add(num.parse::<u64>().ok()?.checked_mul(unit)?)?;
+        num.clear();
+    }
+    if !num.is_empty() || total == 0 {
+        return None;
+    }
+
+    Some(total)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(Some(Duration::from_secs(30 * 60)), parse_duration("30"));
+        assert_eq!(Some(Duration::from_secs(5400)), parse_duration("1h30m"));
+        assert_eq!(Some(Duration::from_secs(90)), parse_duration("90S"));
+        assert_eq!(None, parse_duration("1h30"));
+        assert_eq!(None, parse_duration("soon"));
+        assert_eq!(None, parse_duration("0m"));
+        assert_eq!(Some(MAX_SLEEP_DURATION), parse_duration("23h60m"));
+        assert_eq!(None, parse_duration("24h1s"));
+        assert_eq!(None, parse_duration("1441"));
+        assert_eq!(None, parse_duration("99999999999999999h"));
+        assert_eq!(None, parse_duration("307445734561825860"));
+        assert_eq!(None, parse_duration("18446744073709551615s1s"));
+        assert_eq!(None, parse_duration("99999999999999999999999m"));
+    }
+}
@@ -0,0 +1,376 @@
+use std::future::Future;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use poise::async_trait;
+use poise::serenity_prelude::TypeMapKey;
+use songbird::input::error::{Error, Result};
+use songbird::input::restartable::Restart;
+use songbird::input::{children_to_reader, Codec, Container, Input, Metadata, Restartable};
+
+use super::error::MusicError;
+use super::extract::{find_alternate, probe, ExtractError, Strategy, STRATEGIES};
+use crate::network::network;
+
+/// Restartable yt-dlp source that retries failed extractions with other strategies, and plays
+/// another upload of the same song if the video itself can't be played
+pub struct YtdlSource {
+    uri: String,
+    /// Known ahead of time, so creating the source lazily does not spawn any processes until the
+    /// track is close to playing
+    metadata: Option<Metadata>,
+    /// Index of the first strategy to try, the last one that worked
+    strategy: usize,
+    fell_back: bool,
+    /// Set once another upload is played instead
+    alternate: Arc<OnceLock<AlternateUpload>>,
+}
+
+/// Another upload of the same song that was played because the original could not be
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AlternateUpload {
+    pub url: String,
+    /// Why the original upload could not be played
+    pub reason: ExtractError,
+}
+
+impl TypeMapKey for AlternateUpload {
+    type Value = Arc<OnceLock<AlternateUpload>>;
+}
+
+impl YtdlSource {
+    /// `alternate` is set if another upload ends up being played, which can happen long after the
+    /// source was created
+    pub async fn restartable(
+        uri: String,
+        metadata: Option<Metadata>,
+        lazy: bool,
+        alternate: Arc<OnceLock<AlternateUpload>>,
+    ) -> std::result::Result<Restartable, MusicError> {
+        let mut source = Self {
+            uri,
+            metadata,
+            strategy: 0,
+            fell_back: false,
+            alternate,
+        };
+        if lazy && source.metadata.is_none() {
+            let metadata = source
+                .retry(|uri, strategy| async move { probe(&uri, strategy).await })
+                .await
+                .map_err(source_error)?;
+            source.metadata = Some(metadata);
+        }
+        Restartable::new(source, lazy).await.map_err(source_error)
+    }
+
+    /// Run a yt-dlp operation with each strategy until one works, then with another upload
+    async fn retry<T, F, Fut>(&mut self, op: F) -> Result<T>
+    where
+        F: Fn(String, &'static Strategy) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        self.retry_with(op, |uri, metadata| async move {
+            find_alternate(&uri, metadata.as_ref()).await
+        })
+        .await
+    }
+
+    /// Like `retry`, with `find` looking up another upload given the URI and metadata
+    async fn retry_with<T, F, Fut, A, AFut>(&mut self, op: F, find: A) -> Result<T>
+    where
+        F: Fn(String, &'static Strategy) -> Fut,
+        Fut: Future<Output = Result<T>>,
+        A: Fn(String, Option<Metadata>) -> AFut,
+        AFut: Future<Output = Option<String>>,
+    {
+        let mut first_error: Option<(Option<ExtractError>, Error)> = None;
+        loop {
+            for (i, strategy) in STRATEGIES.iter().enumerate().skip(self.strategy) {
+                let e = match op(self.uri.clone(), strategy).await {
+                    Ok(x) => {
+                        self.strategy = i;
+                        return Ok(x);
+                    }
+                    Err(e) => e,
+                };
+                let kind = ExtractError::from_source(&e);
+                let retry = kind.as_ref().is_some_and(|k| k.is_retryable());
+                first_error.get_or_insert((kind, e));
+                if !retry {
+                    break;
+                }
+            }
+
+            let (kind, e) = match first_error {
+                Some(e) => e,
+                None => return Err(Error::Metadata),
+            };
+            if !self.fell_back && kind.as_ref().is_some_and(|k| k.has_alternate()) {
+                self.fell_back = true;
+                if let Some(uri) = find(self.uri.clone(), self.metadata.clone()).await {
+                    eprintln!("Playing {} instead of {}: {}", uri, self.uri, e);
+                    let _ = self.alternate.set(AlternateUpload {
+                        url: uri.clone(),
+                        reason: kind.clone().unwrap_or(ExtractError::Unavailable),
+                    });
+                    self.uri = uri;
+                    self.strategy = 0;
+                    first_error = Some((kind, e));
+                    continue;
+                }
+            }
+            return Err(e);
+        }
+    }
+}
+
+#[async_trait]
+impl Restart for YtdlSource {
+    async fn call_restart(&mut self, time: Option<Duration>) -> Result<Input> {
+        self.retry(|uri, strategy| async move { ytdl(&uri, time, strategy).await })
+            .await
+    }
+
+    async fn lazy_init(&mut self) -> Result<(Option<Metadata>, Codec, Container)> {
+        Ok((self.metadata.clone(), Codec::FloatPcm, Container::Raw))
+    }
+}
+
+/// Explain why a source could not be created
+fn source_error(e: Error) -> MusicError {
+    match ExtractError::from_source(&e) {
+        Some(ExtractError::NoResults) => MusicError::NoResults,
+        Some(ExtractError::Other(s)) => MusicError::BadSource(s),
+        Some(kind) => MusicError::Extract(kind),
+        None => MusicError::Internal(e.into()),
+    }
+}
+
+/// Stream audio with yt-dlp piped into ffmpeg, optionally starting at a given time
+async fn ytdl(uri: &str, start: Option<Duration>, strategy: &Strategy) -> Result<Input> {
+    let ytdl_args = [
+        "--print-json",
+        "-R",
+        "infinite",
+        "--no-playlist",
+        "--ignore-config",
+        "--no-warnings",
+        "-o",
+        "-",
+        "--",
+        uri,
+    ];
+
+    let ffmpeg_args = [
+        "-f",
+        "s16le",
+        "-ac",
+        "2",
+        "-ar",
+        "48000",
+        "-acodec",
+        "pcm_f32le",
+        "-",
+    ];
+
+    let mut youtube_dl = Command::new("yt-dlp")
+        .args(strategy.args())
+        .args(network().ytdl_args())
+        .args(ytdl_args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    // yt-dlp prints the JSON metadata to stderr before streaming to stdout
+    let stderr = youtube_dl.stderr.take().ok_or(Error::Metadata)?;
+    let (stderr, value) = tokio::task::spawn_blocking(move || {
+        use std::io::{BufRead, BufReader, Read};
+
+        let mut stderr = stderr;
+        let mut line = vec![];
+        let value = match BufReader::new(stderr.by_ref()).read_until(b'\n', &mut line) {
+            Ok(_) => serde_json::from_slice(&line).map_err(|error| Error::Json {
+                error,
+                parsed_text: String::from_utf8_lossy(&line).into_owned(),
+            }),
+            Err(_) => Err(Error::Metadata),
+        };
+        (stderr, value)
+    })
+    .await
+    .map_err(|_| Error::Metadata)?;
+    youtube_dl.stderr = Some(stderr);
+
+    let stdout = youtube_dl.stdout.take().ok_or(Error::Stdout)?;
+    let start = start.map(|t| format!("{:.3}", t.as_secs_f64()));
+    let ffmpeg = Command::new("ffmpeg")
+        .args(start.iter().flat_map(|t| ["-ss", t.as_str()]))
+        .arg("-i")
+        .arg("-")
+        .args(ffmpeg_args)
+        .stdin(stdout)
+        .stderr(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    Ok(Input::new(
+        true,
+        children_to_reader::<f32>(vec![youtube_dl, ffmpeg]),
+        Codec::FloatPcm,
+        Container::Raw,
+        Some(Metadata::from_ytdl_output(value?)),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use super::*;
+
+    fn json_error(text: &str) -> Error {
+        Error::Json {
+            error: serde_json::from_str::<serde_json::Value>(text).unwrap_err(),
+            parsed_text: text.to_owned(),
+        }
+    }
+
+    fn source(uri: &str) -> YtdlSource {
+        YtdlSource {
+            uri: uri.to_owned(),
+            metadata: None,
+            strategy: 0,
+            fell_back: false,
+            alternate: Default::default(),
+        }
+    }
+
+    /// Runs `retry_with` with canned results, returns the result and the URIs and strategies tried
+    async fn run(
+        source: &mut YtdlSource,
+        result: impl Fn(&str, usize) -> Result<()>,
+        alternate: Option<&str>,
+    ) -> (Result<()>, Vec<(String, usize)>, usize) {
+        let calls = Mutex::new(vec![]);
+        let finds = Mutex::new(0);
+        let result = source
+            .retry_with(
+                |uri, strategy| {
+                    let i = STRATEGIES.iter().position(|s| s.args() == strategy.args());
+                    let i = i.unwrap();
+                    calls.lock().unwrap().push((uri.clone(), i));
+                    let r = result(&uri, i);
+                    async move { r }
+                },
+                |_, _| {
+                    *finds.lock().unwrap() += 1;
+                    async move { alternate.map(str::to_owned) }
+                },
+            )
+            .await;
+        let finds = *finds.lock().unwrap();
+        (result, calls.into_inner().unwrap(), finds)
+    }
+
+    fn parsed_text(result: Result<()>) -> String {
+        match result {
+            Err(Error::Json { parsed_text, .. }) => parsed_text,
+            _ => panic!("expected a JSON error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_strategies() {
+        let mut source = source("a");
+        let (result, calls, finds) = run(
+            &mut source,
+            |_, i| match i {
+                0 | 1 => Err(json_error("ERROR: Signature extraction failed")),
+                _ => Ok(()),
+            },
+            None,
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(
+            vec![
+                ("a".to_owned(), 0),
+                ("a".to_owned(), 1),
+                ("a".to_owned(), 2)
+            ],
+            calls
+        );
+        assert_eq!(0, finds);
+
+        // The strategy that worked is tried first next time
+        assert_eq!(2, source.strategy);
+        let (_, calls, _) = run(&mut source, |_, _| Ok(()), None).await;
+        assert_eq!(vec![("a".to_owned(), 2)], calls);
+    }
+
+    #[tokio::test]
+    async fn test_retry_stops_on_non_retryable() {
+        let mut source = source("a");
+        let (result, calls, finds) = run(&mut source, |_, _| Err(json_error("")), None).await;
+        assert!(matches!(result, Err(Error::Json { .. })));
+        assert_eq!(1, calls.len());
+        assert_eq!(0, finds);
+        assert!(source.alternate.get().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_retry_alternate() {
+        let mut source = source("a");
+        let (result, calls, finds) = run(
+            &mut source,
+            |uri, _| match uri {
+                "a" => Err(json_error("ERROR: Video unavailable")),
+                _ => Ok(()),
+            },
+            Some("b"),
+        )
+        .await;
+        assert!(result.is_ok());
+        assert_eq!(vec![("a".to_owned(), 0), ("b".to_owned(), 0)], calls);
+        assert_eq!(1, finds);
+        assert_eq!(
+            Some(&AlternateUpload {
+                url: "b".to_owned(),
+                reason: ExtractError::Unavailable,
+            }),
+            source.alternate.get()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retry_alternate_once() {
+        let mut source = source("a");
+        let (result, calls, finds) = run(
+            &mut source,
+            |uri, _| match uri {
+                "a" => Err(json_error("ERROR: Sign in to confirm your age")),
+                _ => Err(json_error("ERROR: Private video")),
+            },
+            Some("b"),
+        )
+        .await;
+
+        // Age restrictions are retried with every strategy, and the original error is returned
+        assert_eq!("ERROR: Sign in to confirm your age", parsed_text(result));
+        assert_eq!(STRATEGIES.len() + 1, calls.len());
+        assert_eq!(1, finds);
+
+        let (result, calls, finds) = run(
+            &mut source,
+            |_, _| Err(json_error("ERROR: Private video")),
+            Some("c"),
+        )
+        .await;
+        assert_eq!("ERROR: Private video", parsed_text(result));
+        assert_eq!(vec![("b".to_owned(), 0)], calls);
+        assert_eq!(0, finds);
+    }
+}
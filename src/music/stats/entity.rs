@@ -0,0 +1,25 @@
+use sea_orm::prelude::*;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "music_play")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i64,
+    pub guild_id: String,
+    /// User who requested the track
+    pub user_id: Option<String>,
+    pub title: String,
+    pub artist: Option<String>,
+    pub source_url: Option<String>,
+    /// Seconds the track was played for
+    pub play_seconds: i64,
+    /// Stopped or skipped before the end
+    pub skipped: bool,
+    /// Unix timestamp in seconds
+    pub ended_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
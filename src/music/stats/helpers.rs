@@ -0,0 +1,210 @@
+use poise::serenity_prelude::{GuildId, UserId};
+use sea_orm::sea_query::Expr;
+use sea_orm::ActiveValue::Set;
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, Condition, DatabaseConnection, EntityTrait, FromQueryResult,
+    QueryFilter, QueryOrder, QuerySelect, Select,
+};
+
+use super::entity;
+use crate::music::database::{self, parse_id, stringify};
+use crate::music::MusicError;
+
+/// Number of entries in each leaderboard
+const MAX_ENTRIES: u64 = 10;
+
+pub async fn create_table(db: &DatabaseConnection) -> anyhow::Result<()> {
+    database::create_table(db, entity::Entity).await?;
+    Ok(())
+}
+
+/// A track that stopped playing
+pub struct Play {
+    pub guild_id: GuildId,
+    pub user_id: Option<UserId>,
+    pub title: String,
+    pub artist: Option<String>,
+    pub source_url: Option<String>,
+    pub play_seconds: i64,
+    pub skipped: bool,
+    pub ended_at: i64,
+}
+
+pub async fn insert_play(play: Play) -> Result<(), MusicError> {
+    let db = database::connection()?;
+
+    let model = entity::ActiveModel {
+        guild_id: Set(stringify(play.guild_id.0)),
+        user_id: Set(play.user_id.map(|u| stringify(u.0))),
+        title: Set(play.title),
+        artist: Set(play.artist),
+        source_url: Set(play.source_url),
+        play_seconds: Set(play.play_seconds),
+        skipped: Set(play.skipped),
+        ended_at: Set(play.ended_at),
+        ..Default::default()
+    };
+    model
+        .insert(db)
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?;
+
+    Ok(())
+}
+
+#[derive(Debug, FromQueryResult)]
+pub struct TrackCount {
+    pub title: String,
+    pub artist: Option<String>,
+    pub plays: i64,
+}
+
+#[derive(Debug, FromQueryResult)]
+pub struct NameCount {
+    pub name: String,
+    pub plays: i64,
+}
+
+#[derive(Debug, Default, FromQueryResult)]
+pub struct Totals {
+    pub plays: i64,
+    pub play_seconds: i64,
+    pub skips: i64,
+}
+
+/// Leaderboards and totals of the plays in a guild
+#[derive(Debug)]
+pub struct PlayStats {
+    pub top_tracks: Vec<TrackCount>,
+    pub top_artists: Vec<NameCount>,
+    /// Empty for the stats of a single user
+    pub top_requesters: Vec<NameCount>,
+    pub totals: Totals,
+}
+
+/// Stats of the plays in a guild since a Unix timestamp, only counting tracks requested by
+/// `user_id` if given
+pub async fn play_stats(
+    guild_id: GuildId,
+    user_id: Option<UserId>,
+    since: Option<i64>,
+) -> Result<PlayStats, MusicError> {
+    let db = database::connection()?;
+    let condition = plays_condition(guild_id, user_id, since);
+
+    let top_tracks = top_tracks_query(condition.clone())
+        .into_model::<TrackCount>()
+        .all(db)
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?;
+    let top_artists = top_query(entity::Column::Artist, condition.clone())
+        .into_model::<NameCount>()
+        .all(db)
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?;
+    let top_requesters = match user_id {
+        Some(_) => vec![],
+        None => top_query(entity::Column::UserId, condition.clone())
+            .into_model::<NameCount>()
+            .all(db)
+            .await
+            .map_err(|e| MusicError::Internal(e.into()))?
+            .into_iter()
+            .filter_map(|c| {
+                // Show requesters as decimal IDs so they can be mentioned
+                let id = parse_id(&c.name)?;
+                Some(NameCount {
+                    name: id.to_string(),
+                    plays: c.plays,
+                })
+            })
+            .collect(),
+    };
+    let totals = totals_query(condition)
+        .into_model::<Totals>()
+        .one(db)
+        .await
+        .map_err(|e| MusicError::Internal(e.into()))?
+        .unwrap_or_default();
+
+    Ok(PlayStats {
+        top_tracks,
+        top_artists,
+        top_requesters,
+        totals,
+    })
+}
+
+fn plays_condition(guild_id: GuildId, user_id: Option<UserId>, since: Option<i64>) -> Condition {
+    let mut condition = Condition::all().add(entity::Column::GuildId.eq(stringify(guild_id.0)));
+    if let Some(user_id) = user_id {
+        condition = condition.add(entity::Column::UserId.eq(stringify(user_id.0)));
+    }
+    if let Some(since) = since {
+        condition = condition.add(entity::Column::EndedAt.gte(since));
+    }
+    condition
+}
+
+fn top_tracks_query(condition: Condition) -> Select<entity::Entity> {
+    entity::Entity::find()
+        .select_only()
+        .column(entity::Column::Title)
+        .column(entity::Column::Artist)
+        .column_as(Expr::col(entity::Column::Id).count(), "plays")
+        .filter(condition)
+        .group_by(entity::Column::Title)
+        .group_by(entity::Column::Artist)
+        .order_by_desc(Expr::cust("plays"))
+        .order_by_asc(entity::Column::Title)
+        .limit(MAX_ENTRIES)
+}
+
+/// Most frequent values of a column
+fn top_query(column: entity::Column, condition: Condition) -> Select<entity::Entity> {
+    entity::Entity::find()
+        .select_only()
+        .column_as(column, "name")
+        .column_as(Expr::col(entity::Column::Id).count(), "plays")
+        .filter(condition)
+        .filter(column.is_not_null())
+        .group_by(column)
+        .order_by_desc(Expr::cust("plays"))
+        .order_by_asc(column)
+        .limit(MAX_ENTRIES)
+}
+
+fn totals_query(condition: Condition) -> Select<entity::Entity> {
+    // Sums are cast since Postgres sums integers as numeric
+    entity::Entity::find()
+        .select_only()
+        .column_as(Expr::col(entity::Column::Id).count(), "plays")
+        .column_as(
+            Expr::cust("CAST(COALESCE(SUM(play_seconds), 0) AS BIGINT)"),
+            "play_seconds",
+        )
+        .column_as(
+            Expr::cust("CAST(COALESCE(SUM(CASE WHEN skipped THEN 1 ELSE 0 END), 0) AS BIGINT)"),
+            "skips",
+        )
+        .filter(condition)
+}
+
+#[cfg(test)]
+mod test {
+    use sea_orm::{DbBackend, QueryTrait};
+
+    use super::*;
+
+    #[test]
+    fn test_top_query() {
+        let condition = plays_condition(GuildId(255), Some(UserId(16)), Some(100));
+        let sql = top_query(entity::Column::Artist, condition)
+            .build(DbBackend::Postgres)
+            .to_string();
+        assert_eq!(
+            sql,
+            r#"SELECT "music_play"."artist" AS "name", COUNT("id") AS "plays" FROM "music_play" WHERE "music_play"."guild_id" = 'ff' AND "music_play"."user_id" = '10' AND "music_play"."ended_at" >= 100 AND "music_play"."artist" IS NOT NULL GROUP BY "music_play"."artist" ORDER BY plays DESC, "music_play"."artist" ASC LIMIT 10"#
+        );
+    }
+}
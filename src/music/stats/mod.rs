@@ -0,0 +1,112 @@
+mod entity;
+mod helpers;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use time::OffsetDateTime;
+
+pub use helpers::{create_table, play_stats, PlayStats, Totals};
+
+use super::bus::{spawn_subscriber, MusicEvent};
+use super::error::log_error;
+use super::queue::Requester;
+use helpers::{insert_play, Play};
+
+/// Set once the recorder is subscribed, since the bot can become ready more than once
+static RECORDER_STARTED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, poise::ChoiceParameter)]
+pub enum StatsPeriod {
+    #[name = "Week"]
+    Week,
+    #[name = "Month"]
+    Month,
+    #[default]
+    #[name = "All time"]
+    All,
+}
+
+impl StatsPeriod {
+    /// Unix timestamp the period starts at, or `None` for all time
+    pub fn since(&self, now: i64) -> Option<i64> {
+        const DAY: i64 = 24 * 60 * 60;
+        match self {
+            Self::Week => Some(now - 7 * DAY),
+            Self::Month => Some(now - 30 * DAY),
+            Self::All => None,
+        }
+    }
+}
+
+impl Totals {
+    /// Percentage of plays that were skipped or stopped before the end
+    pub fn skip_rate(&self) -> f64 {
+        if self.plays == 0 {
+            return 0.0;
+        }
+        self.skips as f64 / self.plays as f64 * 100.0
+    }
+
+    pub fn listening_hours(&self) -> f64 {
+        self.play_seconds as f64 / 3600.0
+    }
+}
+
+/// Record every track that was played in a guild
+pub fn start_stats_recorder() {
+    if RECORDER_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    spawn_subscriber("stats", move |event| async move {
+        let (guild_id, track, play_time, finished) = match event {
+            MusicEvent::TrackEnded {
+                guild_id,
+                track,
+                play_time,
+                finished,
+            } if play_time > Duration::ZERO => (guild_id, track, play_time, finished),
+            _ => return,
+        };
+        let user_id = track.typemap().read().await.get::<Requester>().copied();
+        let metadata = track.metadata();
+        let play = Play {
+            guild_id,
+            user_id,
+            title: metadata
+                .title
+                .clone()
+                .unwrap_or_else(|| "Unknown".to_owned()),
+            artist: metadata.artist.clone(),
+            source_url: metadata.source_url.clone(),
+            play_seconds: play_time.as_secs() as i64,
+            skipped: !finished,
+            ended_at: OffsetDateTime::now_utc().unix_timestamp(),
+        };
+        log_error(guild_id, insert_play(play).await);
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stats_period() {
+        assert_eq!(StatsPeriod::Week.since(1_000_000), Some(395_200));
+        assert_eq!(StatsPeriod::Month.since(3_000_000), Some(408_000));
+        assert_eq!(StatsPeriod::All.since(1_000_000), None);
+    }
+
+    #[test]
+    fn test_skip_rate() {
+        let totals = Totals {
+            plays: 8,
+            play_seconds: 5400,
+            skips: 2,
+        };
+        assert_eq!(totals.skip_rate(), 25.0);
+        assert_eq!(totals.listening_hours(), 1.5);
+        assert_eq!(Totals::default().skip_rate(), 0.0);
+    }
+}
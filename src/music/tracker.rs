@@ -0,0 +1,220 @@
+use std::collections::{HashMap, HashSet};
+
+use poise::serenity_prelude as serenity;
+use serenity::{ChannelId, GuildId, TypeMapKey, UserId, VoiceState};
+
+/// Voice channel the bot is in and who else is listening, for each guild
+pub struct VoiceTrackerMap;
+
+impl TypeMapKey for VoiceTrackerMap {
+    type Value = HashMap<GuildId, GuildVoice>;
+}
+
+#[derive(Debug, Default)]
+pub struct GuildVoice {
+    pub(super) channel_id: Option<ChannelId>,
+    /// Non-bot users in the same channel as the bot
+    pub(super) listeners: HashSet<UserId>,
+}
+
+/// How a voice state update changed the bot's situation
+#[derive(Debug, PartialEq, Eq)]
+pub enum VoiceChange {
+    Unchanged,
+    /// Bot joined or was moved to a channel
+    Moved {
+        alone: bool,
+    },
+    /// Bot was disconnected, kicked, or its channel was deleted
+    Disconnected,
+    /// Last listener left the bot's channel
+    Alone,
+    /// First listener joined the bot's channel
+    NotAlone,
+}
+
+impl GuildVoice {
+    /// Apply a voice state update of a user
+    ///
+    /// `channel_listeners` is only called when the bot itself moves to a new channel
+    fn update(
+        &mut self,
+        is_self: bool,
+        is_bot: bool,
+        user_id: UserId,
+        channel_id: Option<ChannelId>,
+        channel_listeners: impl FnOnce(ChannelId) -> HashSet<UserId>,
+    ) -> VoiceChange {
+        if is_self {
+            if self.channel_id == channel_id {
+                return VoiceChange::Unchanged;
+            }
+            self.channel_id = channel_id;
+            return match channel_id {
+                Some(c) => {
+                    self.listeners = channel_listeners(c);
+                    VoiceChange::Moved {
+                        alone: self.listeners.is_empty(),
+                    }
+                }
+                None => {
+                    self.listeners.clear();
+                    VoiceChange::Disconnected
+                }
+            };
+        }
+
+        let listening = !is_bot && self.channel_id.is_some() && channel_id == self.channel_id;
+        let changed = if listening {
+            self.listeners.insert(user_id)
+        } else {
+            self.listeners.remove(&user_id)
+        };
+
+        match (changed, self.listeners.len()) {
+            (true, 0) => VoiceChange::Alone,
+            (true, 1) if listening => VoiceChange::NotAlone,
+            _ => VoiceChange::Unchanged,
+        }
+    }
+
+    /// Forget the bot's channel, e.g. when it was deleted
+    fn disconnect(&mut self) -> VoiceChange {
+        if self.channel_id.take().is_some() {
+            self.listeners.clear();
+            VoiceChange::Disconnected
+        } else {
+            VoiceChange::Unchanged
+        }
+    }
+}
+
+/// Update the tracker with a voice state update
+pub async fn track_voice_state(ctx: &serenity::Context, voice_state: &VoiceState) -> VoiceChange {
+    let guild_id = match voice_state.guild_id {
+        Some(g) => g,
+        None => return VoiceChange::Unchanged,
+    };
+    let bot_user_id = ctx.cache.current_user_id();
+    let is_self = voice_state.user_id == bot_user_id;
+    let user_is_bot = is_bot(ctx, voice_state);
+
+    let mut data = ctx.data.write().await;
+    let map = match data.get_mut::<VoiceTrackerMap>() {
+        Some(m) => m,
+        None => return VoiceChange::Unchanged,
+    };
+    map.entry(guild_id).or_default().update(
+        is_self,
+        user_is_bot,
+        voice_state.user_id,
+        voice_state.channel_id,
+        |channel_id| {
+            ctx.cache
+                .guild_field(guild_id, |g| {
+                    g.voice_states
+                        .values()
+                        .filter(|vs| vs.channel_id == Some(channel_id))
+                        .filter(|vs| vs.user_id != bot_user_id && !is_bot(ctx, vs))
+                        .map(|vs| vs.user_id)
+                        .collect()
+                })
+                .unwrap_or_default()
+        },
+    )
+}
+
+/// Update the tracker when a channel is deleted
+pub async fn track_channel_delete(
+    ctx: &serenity::Context,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> VoiceChange {
+    let mut data = ctx.data.write().await;
+    match data
+        .get_mut::<VoiceTrackerMap>()
+        .and_then(|map| map.get_mut(&guild_id))
+    {
+        Some(voice) if voice.channel_id == Some(channel_id) => voice.disconnect(),
+        _ => VoiceChange::Unchanged,
+    }
+}
+
+fn is_bot(ctx: &serenity::Context, voice_state: &VoiceState) -> bool {
+    match &voice_state.member {
+        Some(member) => member.user.bot,
+        None => ctx
+            .cache
+            .user(voice_state.user_id)
+            .is_some_and(|user| user.bot),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BOT_CHANNEL: ChannelId = ChannelId(1);
+    const OTHER_CHANNEL: ChannelId = ChannelId(2);
+
+    fn no_listeners(_: ChannelId) -> HashSet<UserId> {
+        HashSet::new()
+    }
+
+    #[test]
+    fn test_listeners_join_and_leave() {
+        let mut voice = GuildVoice::default();
+        assert_eq!(
+            VoiceChange::Moved { alone: true },
+            voice.update(true, true, UserId(0), Some(BOT_CHANNEL), no_listeners)
+        );
+
+        let user = |voice: &mut GuildVoice, id, channel| {
+            voice.update(false, false, UserId(id), channel, no_listeners)
+        };
+        assert_eq!(
+            VoiceChange::NotAlone,
+            user(&mut voice, 1, Some(BOT_CHANNEL))
+        );
+        assert_eq!(
+            VoiceChange::Unchanged,
+            user(&mut voice, 2, Some(BOT_CHANNEL))
+        );
+        assert_eq!(
+            VoiceChange::Unchanged,
+            user(&mut voice, 3, Some(OTHER_CHANNEL))
+        );
+        assert_eq!(VoiceChange::Unchanged, user(&mut voice, 1, None));
+        assert_eq!(VoiceChange::Alone, user(&mut voice, 2, Some(OTHER_CHANNEL)));
+
+        // Other bots are not listeners
+        assert_eq!(
+            VoiceChange::Unchanged,
+            voice.update(false, true, UserId(4), Some(BOT_CHANNEL), no_listeners)
+        );
+    }
+
+    #[test]
+    fn test_bot_moved_and_disconnected() {
+        let mut voice = GuildVoice::default();
+        voice.update(true, true, UserId(0), Some(BOT_CHANNEL), no_listeners);
+        voice.update(false, false, UserId(1), Some(BOT_CHANNEL), no_listeners);
+
+        assert_eq!(
+            VoiceChange::Moved { alone: false },
+            voice.update(true, true, UserId(0), Some(OTHER_CHANNEL), |_| {
+                HashSet::from([UserId(3)])
+            })
+        );
+        assert_eq!(
+            VoiceChange::Alone,
+            voice.update(false, false, UserId(3), None, no_listeners)
+        );
+
+        assert_eq!(
+            VoiceChange::Disconnected,
+            voice.update(true, true, UserId(0), None, no_listeners)
+        );
+        assert_eq!(VoiceChange::Unchanged, voice.disconnect());
+    }
+}
@@ -1,12 +1,15 @@
 use std::sync::Arc;
 
 use poise::serenity_prelude as serenity;
-use serenity::{async_trait, ChannelId, Mutex};
-use songbird::Call;
+use serenity::{async_trait, ChannelId, GuildId, Mutex, UserId};
+use songbird::{Call, Songbird};
 
 use super::error::MusicError;
+use super::tracker::VoiceTrackerMap;
 use crate::PoiseContext;
 
+const DJ_ROLE_NAME: &str = "DJ";
+
 #[async_trait]
 pub trait CanJoinVoice {
     async fn join_voice(&self) -> Result<Arc<Mutex<Call>>, MusicError>;
@@ -20,24 +23,34 @@ impl CanJoinVoice for PoiseContext<'_> {
             .await
             .ok_or(MusicError::GetVoice)?;
         let channel_id = get_channel_id(self).await?;
+        check_voice_channel(self, &manager, guild_id, channel_id).await?;
 
-        // Join voice channel
-        let (handler_lock, error) = manager.join(guild_id, channel_id).await;
-        if error.is_err() {
-            let _ = dbg!(error);
-            let _ = manager.leave(guild_id).await;
-            return Err(MusicError::JoinVoice);
-        }
-
-        // Automatically deafen
-        {
-            let mut handler = handler_lock.lock().await;
-            let _ = handler.deafen(true).await;
-        }
-        Ok(handler_lock)
+        join_channel(&manager, guild_id, channel_id).await
     }
 }
 
+/// Join a voice channel and deafen the bot
+pub async fn join_channel(
+    manager: &Songbird,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> Result<Arc<Mutex<Call>>, MusicError> {
+    // Join voice channel
+    let (handler_lock, error) = manager.join(guild_id, channel_id).await;
+    if error.is_err() {
+        let _ = dbg!(error);
+        let _ = manager.leave(guild_id).await;
+        return Err(MusicError::JoinVoice);
+    }
+
+    // Automatically deafen
+    {
+        let mut handler = handler_lock.lock().await;
+        let _ = handler.deafen(true).await;
+    }
+    Ok(handler_lock)
+}
+
 #[async_trait]
 pub trait CanGetVoice {
     async fn get_voice(&self) -> Result<Arc<Mutex<Call>>, MusicError>;
@@ -46,16 +59,17 @@ pub trait CanGetVoice {
 #[async_trait]
 impl CanGetVoice for PoiseContext<'_> {
     async fn get_voice(&self) -> Result<Arc<Mutex<Call>>, MusicError> {
-        get_channel_id(self).await?;
+        let channel_id = get_channel_id(self).await?;
         let guild_id = self.guild_id().ok_or(MusicError::GetVoice)?;
         let manager = songbird::get(self.serenity_context())
             .await
             .ok_or(MusicError::GetVoice)?;
+        check_voice_channel(self, &manager, guild_id, channel_id).await?;
         Ok(manager.get_or_insert(guild_id))
     }
 }
 
-async fn get_channel_id(ctx: &PoiseContext<'_>) -> Result<ChannelId, MusicError> {
+pub async fn get_channel_id(ctx: &PoiseContext<'_>) -> Result<ChannelId, MusicError> {
     let channel_id = ctx
         .guild()
         .ok_or(MusicError::GetVoice)?
@@ -66,3 +80,89 @@ async fn get_channel_id(ctx: &PoiseContext<'_>) -> Result<ChannelId, MusicError>
 
     Ok(channel_id)
 }
+
+/// Voice channel the bot is currently in
+pub async fn get_bot_channel_id(ctx: &serenity::Context, guild_id: GuildId) -> Option<ChannelId> {
+    ctx.data
+        .read()
+        .await
+        .get::<VoiceTrackerMap>()?
+        .get(&guild_id)?
+        .channel_id
+}
+
+/// Only allow controlling the bot from another voice channel if the bot is idle, nobody else is
+/// listening, or the author is a DJ
+async fn check_voice_channel(
+    ctx: &PoiseContext<'_>,
+    manager: &Songbird,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> Result<(), MusicError> {
+    let busy = busy_in_other_channel(ctx.serenity_context(), manager, guild_id, channel_id).await;
+    if busy && !is_dj(ctx).await {
+        return Err(MusicError::DifferentVoiceChannel);
+    }
+
+    Ok(())
+}
+
+/// Whether the bot is playing to listeners in a voice channel other than `channel_id`
+pub async fn busy_in_other_channel(
+    ctx: &serenity::Context,
+    manager: &Songbird,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+) -> bool {
+    match get_bot_channel_id(ctx, guild_id).await {
+        Some(c) if c != channel_id => (),
+        _ => return false,
+    }
+
+    let idle = match manager.get(guild_id) {
+        Some(handler_lock) => handler_lock.lock().await.queue().is_empty(),
+        None => true,
+    };
+    !idle && num_listeners(ctx, guild_id).await > 0
+}
+
+/// Number of users other than bots in the same voice channel as the bot
+async fn num_listeners(ctx: &serenity::Context, guild_id: GuildId) -> usize {
+    ctx.data
+        .read()
+        .await
+        .get::<VoiceTrackerMap>()
+        .and_then(|map| map.get(&guild_id))
+        .map_or(0, |voice| voice.listeners.len())
+}
+
+/// Users other than bots in the same voice channel as the bot
+pub async fn listeners(ctx: &serenity::Context, guild_id: GuildId) -> Vec<UserId> {
+    ctx.data
+        .read()
+        .await
+        .get::<VoiceTrackerMap>()
+        .and_then(|map| map.get(&guild_id))
+        .map(|voice| voice.listeners.iter().copied().collect())
+        .unwrap_or_default()
+}
+
+/// Users with a role named "DJ" or permission to move members may always control the bot
+async fn is_dj(ctx: &PoiseContext<'_>) -> bool {
+    let (guild, member) = match (ctx.guild(), ctx.author_member().await) {
+        (Some(g), Some(m)) => (g, m),
+        _ => return false,
+    };
+
+    let has_dj_role = member.roles.iter().any(|id| {
+        guild
+            .roles
+            .get(id)
+            .is_some_and(|role| role.name.eq_ignore_ascii_case(DJ_ROLE_NAME))
+    });
+    let can_move_members = member
+        .permissions(ctx.serenity_context())
+        .is_ok_and(|p| p.move_members());
+
+    has_dj_role || can_move_members
+}
@@ -0,0 +1,194 @@
+use poise::serenity_prelude::GuildId;
+use serde::Serialize;
+use serde_json::{json, Value};
+use songbird::input::Metadata;
+use songbird::tracks::{PlayMode, TrackHandle};
+
+use crate::music::bus::MusicEvent;
+use crate::music::list::track_number;
+use crate::music::queue::Requester;
+
+/// A track as returned by the API, ids are strings since they don't fit in a JavaScript number
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct TrackJson {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub url: Option<String>,
+    pub thumbnail: Option<String>,
+    /// Duration in seconds
+    pub duration: Option<u64>,
+    pub requester_id: Option<String>,
+}
+
+impl TrackJson {
+    pub fn from_metadata(metadata: &Metadata) -> Self {
+        Self {
+            title: metadata.track.clone().or_else(|| metadata.title.clone()),
+            artist: metadata.artist.clone(),
+            url: metadata.source_url.clone(),
+            thumbnail: metadata.thumbnail.clone(),
+            duration: metadata.duration.map(|d| d.as_secs()),
+            requester_id: None,
+        }
+    }
+
+    pub async fn from_track(track: &TrackHandle) -> Self {
+        let requester_id = track
+            .typemap()
+            .read()
+            .await
+            .get::<Requester>()
+            .map(|u| u.to_string());
+        Self {
+            requester_id,
+            ..Self::from_metadata(track.metadata())
+        }
+    }
+}
+
+/// Playback state of a guild
+#[derive(Debug, Serialize)]
+pub struct QueueJson {
+    pub guild_id: String,
+    pub voice_channel_id: Option<String>,
+    pub paused: bool,
+    /// Position of the current track in seconds
+    pub position: Option<u64>,
+    /// The current track is the first track
+    pub tracks: Vec<TrackJson>,
+}
+
+impl QueueJson {
+    pub async fn new(
+        guild_id: GuildId,
+        voice_channel_id: Option<String>,
+        tracks: &[TrackHandle],
+    ) -> Self {
+        let info = match tracks.first() {
+            Some(t) => t.get_info().await.ok(),
+            None => None,
+        };
+        let mut json_tracks = Vec::with_capacity(tracks.len());
+        for track in tracks {
+            json_tracks.push(TrackJson::from_track(track).await);
+        }
+        Self {
+            guild_id: guild_id.to_string(),
+            voice_channel_id,
+            paused: info.as_ref().is_some_and(|i| i.playing == PlayMode::Pause),
+            position: info.map(|i| i.position.as_secs()),
+            tracks: json_tracks,
+        }
+    }
+}
+
+/// Event sent over the WebSocket, tagged with its type in snake case
+pub async fn event_json(event: &MusicEvent) -> Value {
+    let guild_id = event.guild_id().to_string();
+    match event {
+        MusicEvent::TrackQueued {
+            track, queue_len, ..
+        } => json!({
+            "type": "track_queued",
+            "guild_id": guild_id,
+            "track": TrackJson::from_track(track).await,
+            "queue_len": queue_len,
+        }),
+        MusicEvent::TrackStarted {
+            track, queue_len, ..
+        } => json!({
+            "type": "track_started",
+            "guild_id": guild_id,
+            "track": TrackJson::from_track(track).await,
+            "queue_len": queue_len,
+        }),
+        MusicEvent::TrackPaused { track, auto, .. } => json!({
+            "type": "track_paused",
+            "guild_id": guild_id,
+            "track": TrackJson::from_track(track).await,
+            "auto": auto,
+        }),
+        MusicEvent::TrackResumed { track, auto, .. } => json!({
+            "type": "track_resumed",
+            "guild_id": guild_id,
+            "track": TrackJson::from_track(track).await,
+            "auto": auto,
+        }),
+        MusicEvent::TrackSkipped { track, .. } => json!({
+            "type": "track_skipped",
+            "guild_id": guild_id,
+            "track": TrackJson::from_track(track).await,
+        }),
+        MusicEvent::TrackRemoved { track, .. } => json!({
+            "type": "track_removed",
+            "guild_id": guild_id,
+            "track": TrackJson::from_track(track).await,
+        }),
+        MusicEvent::TrackMoved {
+            track, from, to, ..
+        } => json!({
+            "type": "track_moved",
+            "guild_id": guild_id,
+            "track": TrackJson::from_track(track).await,
+            "from": track_number(*from),
+            "to": track_number(*to),
+        }),
+        MusicEvent::TrackEnded {
+            track,
+            play_time,
+            finished,
+            ..
+        } => json!({
+            "type": "track_ended",
+            "guild_id": guild_id,
+            "track": TrackJson::from_track(track).await,
+            "play_time": play_time.as_secs(),
+            "finished": finished,
+        }),
+        MusicEvent::QueueCleared { .. } => json!({
+            "type": "queue_cleared",
+            "guild_id": guild_id,
+        }),
+        MusicEvent::VoiceJoined { channel_id, .. } => json!({
+            "type": "voice_joined",
+            "guild_id": guild_id,
+            "channel_id": channel_id.to_string(),
+        }),
+        MusicEvent::VoiceLeft { .. } => json!({
+            "type": "voice_left",
+            "guild_id": guild_id,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_event_json() {
+        let event = MusicEvent::QueueCleared {
+            guild_id: GuildId(u64::MAX),
+        };
+        assert_eq!(
+            event_json(&event).await,
+            json!({"type": "queue_cleared", "guild_id": "18446744073709551615"})
+        );
+    }
+
+    #[test]
+    fn test_track_from_metadata() {
+        let metadata = Metadata {
+            title: Some("Video title".to_owned()),
+            track: Some("Song".to_owned()),
+            artist: Some("Artist".to_owned()),
+            duration: Some(Duration::from_millis(61500)),
+            ..Default::default()
+        };
+        let track = TrackJson::from_metadata(&metadata);
+        assert_eq!(track.title.as_deref(), Some("Song"));
+        assert_eq!(track.duration, Some(61));
+    }
+}
@@ -0,0 +1,155 @@
+mod json;
+mod routes;
+mod socket;
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{delete, get, post};
+use axum::{Json, Router};
+use poise::serenity_prelude as serenity;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use super::error::MusicError;
+use crate::Data;
+
+/// Set once the server is started, since the bot can become ready more than once
+static SERVER_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Shared by all requests
+#[derive(Clone)]
+pub struct WebState {
+    ctx: serenity::Context,
+    /// Token required to control playback, which is disabled without one
+    token: Option<String>,
+    prefetch_time: Duration,
+    crossfade_time: Duration,
+}
+
+/// Serve the dashboard and API if an address is configured
+pub fn start_web_server(ctx: &serenity::Context, data: &Data) {
+    let address = match data.web_address {
+        Some(a) => a,
+        None => return,
+    };
+    if SERVER_STARTED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    let state = WebState {
+        ctx: ctx.clone(),
+        token: data.web_token.clone(),
+        prefetch_time: data.prefetch_time,
+        crossfade_time: data.crossfade_time,
+    };
+    tokio::spawn(async move {
+        if let Err(e) = serve(address, state).await {
+            eprintln!("Web server error: {e}");
+        }
+    });
+}
+
+async fn serve(address: SocketAddr, state: WebState) -> anyhow::Result<()> {
+    axum::Server::try_bind(&address)?
+        .serve(router(state).into_make_service())
+        .await?;
+    Ok(())
+}
+
+fn router(state: WebState) -> Router {
+    Router::new()
+        .route("/", get(|| async { Html(include_str!("dashboard.html")) }))
+        .route("/api/guilds", get(routes::guilds))
+        .route(
+            "/api/guilds/:guild_id/queue",
+            get(routes::queue).post(routes::enqueue),
+        )
+        .route("/api/guilds/:guild_id/queue/move", post(routes::move_track))
+        .route("/api/guilds/:guild_id/queue/:index", delete(routes::remove))
+        .route("/api/guilds/:guild_id/history", get(routes::history))
+        .route("/api/guilds/:guild_id/skip", post(routes::skip))
+        .route("/api/guilds/:guild_id/pause", post(routes::pause))
+        .route("/api/guilds/:guild_id/resume", post(routes::resume))
+        .route("/api/events", get(socket::events))
+        .with_state(state)
+}
+
+/// Error response of the API
+#[derive(Debug)]
+pub enum ApiError {
+    Music(MusicError),
+    NotConnected,
+    NoTextChannel,
+    Unauthorized,
+}
+
+impl From<MusicError> for ApiError {
+    fn from(e: MusicError) -> Self {
+        Self::Music(e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            Self::Music(MusicError::Internal(e)) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+            }
+            Self::Music(e) => (StatusCode::BAD_REQUEST, e.to_string()),
+            Self::NotConnected => (
+                StatusCode::CONFLICT,
+                "the bot is not in a voice channel".to_owned(),
+            ),
+            Self::NoTextChannel => (
+                StatusCode::CONFLICT,
+                "no text channel to announce tracks in, set a music channel".to_owned(),
+            ),
+            Self::Unauthorized => (
+                StatusCode::UNAUTHORIZED,
+                "missing or invalid token".to_owned(),
+            ),
+        };
+        (status, Json(json!({ "error": message }))).into_response()
+    }
+}
+
+/// Only requests with the configured bearer token may control playback
+fn authorize(headers: &HeaderMap, token: Option<&str>) -> Result<(), ApiError> {
+    let token = token.ok_or(ApiError::Unauthorized)?;
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+    // Digests are compared in constant time, so neither the token nor its length leaks
+    match given {
+        Some(t) if bool::from(Sha256::digest(t).ct_eq(&Sha256::digest(token))) => Ok(()),
+        _ => Err(ApiError::Unauthorized),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use axum::http::HeaderValue;
+
+    use super::*;
+
+    #[test]
+    fn test_authorize() {
+        let mut headers = HeaderMap::new();
+        assert!(authorize(&headers, Some("secret")).is_err());
+
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer secret"),
+        );
+        assert!(authorize(&headers, Some("secret")).is_ok());
+        assert!(authorize(&headers, Some("other")).is_err());
+        // Playback can't be controlled without a configured token
+        assert!(authorize(&headers, None).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
+use axum::http::HeaderMap;
+use axum::Json;
+use poise::serenity_prelude::{ChannelId, GuildId, Mutex};
+use serde::Deserialize;
+use songbird::Call;
+
+use super::json::{QueueJson, TrackJson};
+use super::{authorize, ApiError, WebState};
+use crate::music::error::MusicError;
+use crate::music::history::guild_history;
+use crate::music::list::track_index;
+use crate::music::playlist::resolve_queries;
+use crate::music::queue::{
+    add_tracks_to_channel, move_guild_track, pause_track, remove_guild_tracks, resume_track,
+    skip_track, RequestChannel, TrackRequest,
+};
+use crate::music::settings::get_settings;
+use crate::music::voice::get_bot_channel_id;
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+#[derive(Deserialize)]
+pub struct EnqueueBody {
+    /// URL or YouTube Music search
+    query: String,
+}
+
+#[derive(Deserialize)]
+pub struct MoveBody {
+    from: usize,
+    to: usize,
+}
+
+/// Playback state of every guild the bot is in a voice channel of
+pub async fn guilds(State(state): State<WebState>) -> ApiResult<Vec<QueueJson>> {
+    let manager = songbird::get(&state.ctx)
+        .await
+        .ok_or(MusicError::GetVoice)?;
+    let mut guilds = vec![];
+    for guild_id in state.ctx.cache.guilds() {
+        if let Some(call) = manager.get(guild_id) {
+            guilds.push(queue_json(&state, guild_id, &call).await);
+        }
+    }
+    Ok(Json(guilds))
+}
+
+pub async fn queue(
+    State(state): State<WebState>,
+    Path(guild_id): Path<u64>,
+) -> ApiResult<QueueJson> {
+    let guild_id = GuildId(guild_id);
+    let json = match get_call(&state, guild_id).await {
+        Ok(call) => queue_json(&state, guild_id, &call).await,
+        Err(_) => QueueJson::new(guild_id, None, &[]).await,
+    };
+    Ok(Json(json))
+}
+
+pub async fn history(
+    State(state): State<WebState>,
+    Path(guild_id): Path<u64>,
+) -> ApiResult<Vec<TrackJson>> {
+    let history = guild_history(&state.ctx, GuildId(guild_id)).await;
+    Ok(Json(history.iter().map(TrackJson::from_metadata).collect()))
+}
+
+/// Add a URL, playlist or search result to the queue of the voice channel the bot is in
+pub async fn enqueue(
+    State(state): State<WebState>,
+    Path(guild_id): Path<u64>,
+    headers: HeaderMap,
+    Json(body): Json<EnqueueBody>,
+) -> ApiResult<QueueJson> {
+    authorize(&headers, state.token.as_deref())?;
+    let guild_id = GuildId(guild_id);
+    let voice_channel_id = get_bot_channel_id(&state.ctx, guild_id)
+        .await
+        .ok_or(ApiError::NotConnected)?;
+    let request = TrackRequest {
+        ctx: state.ctx.clone(),
+        guild_id,
+        channel_id: announcement_channel(&state, guild_id).await?,
+        author_id: state.ctx.cache.current_user_id(),
+        prefetch_time: state.prefetch_time,
+        crossfade_time: state.crossfade_time,
+    };
+
+    let queries = resolve_queries(&body.query).await?;
+    add_tracks_to_channel(&request, voice_channel_id, queries).await?;
+
+    let call = get_call(&state, guild_id).await?;
+    Ok(Json(queue_json(&state, guild_id, &call).await))
+}
+
+/// Move a track, using the same numbers as the list command
+pub async fn move_track(
+    State(state): State<WebState>,
+    Path(guild_id): Path<u64>,
+    headers: HeaderMap,
+    Json(body): Json<MoveBody>,
+) -> ApiResult<QueueJson> {
+    authorize(&headers, state.token.as_deref())?;
+    let guild_id = GuildId(guild_id);
+    let call = get_call(&state, guild_id).await?;
+    let (from, to) = match (track_index(body.from), track_index(body.to)) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return Err(MusicError::BadIndex.into()),
+    };
+    move_guild_track(&state.ctx, guild_id, &call, from, to).await?;
+
+    Ok(Json(queue_json(&state, guild_id, &call).await))
+}
+
+/// Remove a track, using the same numbers as the list command
+pub async fn remove(
+    State(state): State<WebState>,
+    Path((guild_id, index)): Path<(u64, usize)>,
+    headers: HeaderMap,
+) -> ApiResult<QueueJson> {
+    authorize(&headers, state.token.as_deref())?;
+    let guild_id = GuildId(guild_id);
+    let call = get_call(&state, guild_id).await?;
+    let idx = track_index(index).ok_or(MusicError::BadIndex)?;
+    let removed = remove_guild_tracks(&state.ctx, guild_id, &call, idx, idx).await?;
+    if removed.is_empty() {
+        return Err(MusicError::BadIndex.into());
+    }
+
+    Ok(Json(queue_json(&state, guild_id, &call).await))
+}
+
+pub async fn skip(
+    State(state): State<WebState>,
+    Path(guild_id): Path<u64>,
+    headers: HeaderMap,
+) -> ApiResult<QueueJson> {
+    authorize(&headers, state.token.as_deref())?;
+    let guild_id = GuildId(guild_id);
+    let call = get_call(&state, guild_id).await?;
+    skip_track(guild_id, &*call.lock().await)?;
+
+    Ok(Json(queue_json(&state, guild_id, &call).await))
+}
+
+pub async fn pause(
+    State(state): State<WebState>,
+    Path(guild_id): Path<u64>,
+    headers: HeaderMap,
+) -> ApiResult<QueueJson> {
+    authorize(&headers, state.token.as_deref())?;
+    let guild_id = GuildId(guild_id);
+    let call = get_call(&state, guild_id).await?;
+    pause_track(guild_id, &*call.lock().await).await?;
+
+    Ok(Json(queue_json(&state, guild_id, &call).await))
+}
+
+pub async fn resume(
+    State(state): State<WebState>,
+    Path(guild_id): Path<u64>,
+    headers: HeaderMap,
+) -> ApiResult<QueueJson> {
+    authorize(&headers, state.token.as_deref())?;
+    let guild_id = GuildId(guild_id);
+    let call = get_call(&state, guild_id).await?;
+    resume_track(&mut *call.lock().await).await?;
+
+    Ok(Json(queue_json(&state, guild_id, &call).await))
+}
+
+async fn get_call(state: &WebState, guild_id: GuildId) -> Result<Arc<Mutex<Call>>, ApiError> {
+    songbird::get(&state.ctx)
+        .await
+        .and_then(|manager| manager.get(guild_id))
+        .ok_or(ApiError::NotConnected)
+}
+
+async fn queue_json(state: &WebState, guild_id: GuildId, call: &Mutex<Call>) -> QueueJson {
+    let tracks = call.lock().await.queue().current_queue();
+    let voice_channel_id = get_bot_channel_id(&state.ctx, guild_id)
+        .await
+        .map(|c| c.to_string());
+    QueueJson::new(guild_id, voice_channel_id, &tracks).await
+}
+
+/// Tracks added from the dashboard are announced in the music channel, or where the current
+/// track was requested
+async fn announcement_channel(state: &WebState, guild_id: GuildId) -> Result<ChannelId, ApiError> {
+    if let Some(channel_id) = get_settings(&state.ctx, guild_id).await.music_channel {
+        return Ok(channel_id);
+    }
+    let current = get_call(state, guild_id)
+        .await?
+        .lock()
+        .await
+        .queue()
+        .current()
+        .ok_or(ApiError::NoTextChannel)?;
+    let channel_id = current
+        .typemap()
+        .read()
+        .await
+        .get::<RequestChannel>()
+        .copied();
+    channel_id.ok_or(ApiError::NoTextChannel)
+}
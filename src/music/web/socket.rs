@@ -0,0 +1,50 @@
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::Query;
+use axum::response::Response;
+use poise::serenity_prelude::GuildId;
+use serde::Deserialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use super::json::event_json;
+use crate::music::bus::subscribe;
+
+#[derive(Deserialize)]
+pub struct EventsQuery {
+    /// Only send events of this guild
+    guild_id: Option<u64>,
+}
+
+/// Stream music events as JSON text messages
+pub async fn events(ws: WebSocketUpgrade, Query(query): Query<EventsQuery>) -> Response {
+    ws.on_upgrade(move |socket| stream_events(socket, query.guild_id.map(GuildId)))
+}
+
+async fn stream_events(mut socket: WebSocket, guild_id: Option<GuildId>) {
+    let mut events = subscribe();
+    loop {
+        tokio::select! {
+            event = events.recv() => {
+                let event = match event {
+                    Ok(e) => e,
+                    // Clients refetch the queue after any event, so missed events can be skipped
+                    Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                };
+                if guild_id.is_some_and(|g| g != event.guild_id()) {
+                    continue;
+                }
+                let text = event_json(&event).await.to_string();
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+            message = socket.recv() => {
+                // Only close messages are expected from clients
+                match message {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => (),
+                }
+            }
+        }
+    }
+}
@@ -4,6 +4,7 @@ use anyhow::Result;
 use regex::Regex;
 use serde::Deserialize;
 
+use crate::music::cache::{self, video_key, LOUDNESS_TTL};
 use crate::music::error::MusicError;
 use crate::CLIENT;
 
@@ -33,7 +34,18 @@ struct AudioConfig {
 }
 
 pub async fn get_loudness(url: &str) -> f32 {
-    get_loudness_helper(url).await.unwrap_or(1.0)
+    let key = video_key("loudness", url);
+    if let Some(volume) = cache::get(&key).await {
+        return volume;
+    }
+
+    match get_loudness_helper(url).await {
+        Ok(volume) => {
+            cache::put(&key, &volume, LOUDNESS_TTL).await;
+            volume
+        }
+        Err(_) => 1.0,
+    }
 }
 
 async fn get_loudness_helper(url: &str) -> Result<f32> {
@@ -47,7 +59,7 @@ async fn get_loudness_helper(url: &str) -> Result<f32> {
 
 async fn query_youtube_db(url: &str) -> Result<f32> {
     // Query YouTube
-    let text = { CLIENT.get(url).send().await?.text().await? };
+    let text = { CLIENT.next().get(url).send().await?.text().await? };
 
     // Extract JSON string
     let json_str = {
@@ -1,5 +1,4 @@
 pub mod loudness;
 pub mod music;
 pub mod music_autocomplete;
-pub mod playlist;
 pub mod sponsorblock;
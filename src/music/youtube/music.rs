@@ -1,18 +1,50 @@
+use std::net::IpAddr;
+use std::time::Duration;
+
+use pyo3::ffi::c_str;
 use pyo3::prelude::*;
-use serde::Deserialize;
+use pyo3::sync::PyOnceLock;
+use pyo3::types::PyDict;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use songbird::input::Metadata;
 
+use crate::music::cache::{self, search_key, METADATA_TTL, SEARCH_TTL};
 use crate::music::error::MusicError;
+use crate::network::network;
+
+/// Number of album results offered to choose from
+const MAX_ALBUM_RESULTS: usize = 5;
 
 enum SearchType {
     Song,
     Album,
+    Artist,
 }
 
 impl SearchType {
+    fn name(&self) -> &'static str {
+        match self {
+            SearchType::Song => "song",
+            SearchType::Album => "album",
+            SearchType::Artist => "artist",
+        }
+    }
+
     fn result_type(&self) -> impl Iterator<Item = &&'static str> {
         match self {
             SearchType::Song => ["song", "video"].iter(),
             SearchType::Album => ["album"].iter(),
+            SearchType::Artist => ["artist"].iter(),
+        }
+    }
+
+    /// Search filter limiting results to this type
+    fn filter(&self) -> Option<&'static str> {
+        match self {
+            SearchType::Song => None,
+            SearchType::Album => Some("albums"),
+            SearchType::Artist => Some("artists"),
         }
     }
 }
@@ -26,10 +58,136 @@ struct YTMusicSearchResult {
     browse_id: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+/// An album or single found by a search or on an artist's page
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumResult {
+    pub title: String,
+    /// Album, Single or EP
+    #[serde(rename = "type")]
+    pub album_type: Option<String>,
+    pub year: Option<String>,
+    #[serde(default)]
+    pub artists: Vec<ArtistRef>,
+    pub browse_id: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ArtistRef {
+    pub name: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Thumbnail {
+    pub url: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Album {
+    pub title: String,
+    pub year: Option<String>,
+    #[serde(default)]
+    pub artists: Vec<ArtistRef>,
+    pub track_count: Option<usize>,
+    #[serde(default)]
+    pub thumbnails: Vec<Thumbnail>,
+    #[serde(default)]
+    pub tracks: Vec<MusicTrack>,
+}
+
+/// A song of an album or an artist's top songs
+#[derive(Clone, Debug, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
-struct YTMusicAlbum {
-    audio_playlist_id: String,
+pub struct MusicTrack {
+    /// Missing if the track can't be played
+    pub video_id: Option<String>,
+    pub title: String,
+    #[serde(default)]
+    pub artists: Vec<ArtistRef>,
+    #[serde(rename = "duration_seconds")]
+    pub duration_seconds: Option<u64>,
+    #[serde(default)]
+    pub thumbnails: Vec<Thumbnail>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Artist {
+    pub name: String,
+    #[serde(default)]
+    pub thumbnails: Vec<Thumbnail>,
+    pub songs: Option<ArtistSection<MusicTrack>>,
+    pub albums: Option<ArtistSection<AlbumResult>>,
+    pub singles: Option<ArtistSection<AlbumResult>>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ArtistSection<T> {
+    // A plain default would require `T: Default`
+    #[serde(default = "Vec::new")]
+    pub results: Vec<T>,
+}
+
+impl Artist {
+    pub fn songs(&self) -> &[MusicTrack] {
+        self.songs.as_ref().map_or(&[], |s| &s.results)
+    }
+
+    pub fn albums(&self) -> &[AlbumResult] {
+        self.albums.as_ref().map_or(&[], |s| &s.results)
+    }
+
+    pub fn singles(&self) -> &[AlbumResult] {
+        self.singles.as_ref().map_or(&[], |s| &s.results)
+    }
+}
+
+/// Names of artists joined for display
+pub fn artist_names(artists: &[ArtistRef]) -> Option<String> {
+    if artists.is_empty() {
+        return None;
+    }
+    let names: Vec<_> = artists.iter().map(|a| a.name.as_str()).collect();
+    Some(names.join(", "))
+}
+
+impl MusicTrack {
+    /// Track metadata known before the track is resolved, `None` if it can't be played
+    pub fn metadata(&self, album: Option<&Album>) -> Option<Metadata> {
+        let video_id = self.video_id.as_ref()?;
+        let artists = if self.artists.is_empty() {
+            album.map_or(&self.artists, |a| &a.artists)
+        } else {
+            &self.artists
+        };
+        let thumbnail = self
+            .thumbnails
+            .last()
+            .or_else(|| album.and_then(|a| a.thumbnails.last()));
+        Some(Metadata {
+            title: Some(self.title.clone()),
+            artist: artist_names(artists),
+            duration: self.duration_seconds.map(Duration::from_secs),
+            source_url: Some(format!("https://music.youtube.com/watch?v={}", video_id)),
+            thumbnail: thumbnail.map(|t| t.url.clone()),
+            channels: Some(2),
+            ..Default::default()
+        })
+    }
+}
+
+impl Album {
+    /// Metadata of the playable tracks, or only the tracks at the given indices
+    pub fn track_metadata(&self, indices: Option<&[usize]>) -> Vec<Metadata> {
+        let tracks: Vec<_> = match indices {
+            Some(indices) => indices.iter().filter_map(|&i| self.tracks.get(i)).collect(),
+            None => self.tracks.iter().collect(),
+        };
+        tracks
+            .into_iter()
+            .filter_map(|t| t.metadata(Some(self)))
+            .collect()
+    }
 }
 
 pub async fn yt_music_song_search(query: String) -> Result<String, MusicError> {
@@ -38,44 +196,66 @@ pub async fn yt_music_song_search(query: String) -> Result<String, MusicError> {
     Ok(url)
 }
 
-pub async fn yt_music_album_search(query: String) -> Result<String, MusicError> {
-    let browse_id = search_id(query, SearchType::Album).await?;
-    let playlist_id = playlist_id(browse_id).await?;
-    let url = format!("https://www.youtube.com/playlist?list={}", playlist_id);
-    Ok(url)
+/// Search YouTube Music for albums, singles and EPs
+pub async fn yt_music_album_results(query: String) -> Result<Vec<AlbumResult>, MusicError> {
+    let key = search_key("album_results", &query);
+    if let Some(results) = cache::get(&key).await {
+        return Ok(results);
+    }
+
+    let results: Vec<serde_json::Value> =
+        call_ytmusic("search", query, SearchType::Album.filter()).await?;
+    let results: Vec<AlbumResult> = results
+        .into_iter()
+        .filter(|r| r.get("resultType").and_then(|t| t.as_str()) == Some("album"))
+        .filter_map(|r| serde_json::from_value(r).ok())
+        .filter(|r: &AlbumResult| r.browse_id.is_some())
+        .take(MAX_ALBUM_RESULTS)
+        .collect();
+    if results.is_empty() {
+        return Err(MusicError::NoResults);
+    }
+    cache::put(&key, &results, SEARCH_TTL).await;
+
+    Ok(results)
+}
+
+/// Tracks and details of an album
+pub async fn yt_music_album(browse_id: String) -> Result<Album, MusicError> {
+    let key = format!("album:{}", browse_id);
+    if let Some(album) = cache::get(&key).await {
+        return Ok(album);
+    }
+
+    let album: Album = call_ytmusic("get_album", browse_id, None).await?;
+    cache::put(&key, &album, METADATA_TTL).await;
+
+    Ok(album)
+}
+
+/// Top songs, albums and singles of the first artist found
+pub async fn yt_music_artist(query: String) -> Result<Artist, MusicError> {
+    let channel_id = search_id(query, SearchType::Artist).await?;
+    let key = format!("artist:{}", channel_id);
+    if let Some(artist) = cache::get(&key).await {
+        return Ok(artist);
+    }
+
+    let artist: Artist = call_ytmusic("get_artist", channel_id, None).await?;
+    cache::put(&key, &artist, SEARCH_TTL).await;
+
+    Ok(artist)
 }
 
 /// Search YouTube Music and return the first valid result
 async fn search_id(query: String, search_type: SearchType) -> Result<String, MusicError> {
-    // Use Python ytmusicapi library
-    let search_results_json: Result<anyhow::Result<String>, _> =
-        tokio::task::spawn_blocking(move || {
-            Python::initialize();
-            Python::attach(|py| {
-                // Import Python modules
-                let ytmusic = PyModule::import(py, "ytmusicapi")?;
-                let json = PyModule::import(py, "json")?;
-
-                // Search YouTube Music
-                let search = ytmusic
-                    .call_method0("YTMusic")?
-                    .call_method1("search", (query,))?;
-
-                // Convert to JSON for so Rust can use it
-                let search_json = json.call_method1("dumps", (search,))?;
-                let ret: String = search_json.extract()?;
-                Ok(ret)
-            })
-        })
-        .await;
-
-    let search_results_json: String = search_results_json
-        .map_err(|e| MusicError::Internal(e.into()))?
-        .map_err(MusicError::Internal)?;
+    let key = search_key(search_type.name(), &query);
+    if let Some(id) = cache::get(&key).await {
+        return Ok(id);
+    }
 
-    // Convert JSON to Rust struct
     let search_results: Vec<YTMusicSearchResult> =
-        serde_json::from_str(&search_results_json).map_err(|e| MusicError::Internal(e.into()))?;
+        call_ytmusic("search", query, search_type.filter()).await?;
 
     // Choose the first result, giving top result priority
     let result = search_results
@@ -96,6 +276,7 @@ async fn search_id(query: String, search_type: SearchType) -> Result<String, Mus
         .next()
         .ok_or(MusicError::NoResults)?
         .clone();
+    cache::put(&key, &result, SEARCH_TTL).await;
 
     Ok(result)
 }
@@ -114,7 +295,7 @@ fn check_results<'a>(
                     return Some(id);
                 }
             }
-            SearchType::Album => {
+            SearchType::Album | SearchType::Artist => {
                 if let Some(id) = &result.browse_id {
                     return Some(id);
                 }
@@ -124,37 +305,81 @@ fn check_results<'a>(
     None
 }
 
-async fn playlist_id(browse_id: String) -> Result<String, MusicError> {
-    let album_results_json: Result<anyhow::Result<String>, _> =
-        tokio::task::spawn_blocking(move || {
-            Python::initialize();
-            Python::attach(|py| {
-                // Import Python modules
-                let ytmusic = PyModule::import(py, "ytmusicapi")?;
-                let json = PyModule::import(py, "json")?;
-
-                // Search YouTube Music
-                let search = ytmusic
-                    .call_method0("YTMusic")?
-                    .call_method1("get_album", (browse_id,))?;
-
-                // Convert to JSON for so Rust can use it
-                let search_json = json.call_method1("dumps", (search,))?;
-                let ret: String = search_json.extract()?;
-                Ok(ret)
-            })
+/// Call a method of the Python ytmusicapi library and convert its result
+async fn call_ytmusic<T: DeserializeOwned>(
+    method: &'static str,
+    arg: String,
+    filter: Option<&'static str>,
+) -> Result<T, MusicError> {
+    let results_json: Result<anyhow::Result<String>, _> = tokio::task::spawn_blocking(move || {
+        Python::initialize();
+        Python::attach(|py| {
+            // Import Python modules
+            let json = PyModule::import(py, "json")?;
+
+            let kwargs = PyDict::new(py);
+            if let Some(filter) = filter {
+                kwargs.set_item("filter", filter)?;
+            }
+            let results = ytmusic(py)?.call_method(method, (arg,), Some(&kwargs))?;
+
+            // Convert to JSON for so Rust can use it
+            let results_json = json.call_method1("dumps", (results,))?;
+            let ret: String = results_json.extract()?;
+            Ok(ret)
         })
-        .await;
+    })
+    .await;
 
-    let album_results_json: String = album_results_json
+    let results_json: String = results_json
         .map_err(|e| MusicError::Internal(e.into()))?
         .map_err(MusicError::Internal)?;
 
     // Convert JSON to Rust struct
-    let album_results: YTMusicAlbum =
-        serde_json::from_str(&album_results_json).map_err(|e| MusicError::Internal(e.into()))?;
+    serde_json::from_str(&results_json).map_err(|e| MusicError::Internal(e.into()))
+}
+
+/// YTMusic clients for each source address, or a single client if none are configured
+static YTMUSIC_CLIENTS: PyOnceLock<Vec<(Option<IpAddr>, Py<PyAny>)>> = PyOnceLock::new();
+
+/// YTMusic client with the configured cookies, proxy and next source address
+///
+/// Clients are created once and reused, since each one starts a session and reads the cookies file
+fn ytmusic(py: Python<'_>) -> PyResult<Bound<'_, PyAny>> {
+    let network = network();
+    let clients = YTMUSIC_CLIENTS.get_or_try_init(py, || {
+        let session = PyModule::from_code(
+            py,
+            c_str!(include_str!("session.py")),
+            c_str!("session.py"),
+            c_str!("session"),
+        )?;
+        let addresses = match network.source_addresses.as_slice() {
+            [] => vec![None],
+            addresses => addresses.iter().copied().map(Some).collect(),
+        };
+        addresses
+            .into_iter()
+            .map(|address| {
+                let client = session.call_method1(
+                    "ytmusic",
+                    (
+                        network.cookies_file.clone(),
+                        network.proxy.clone(),
+                        address.map(|a| a.to_string()),
+                    ),
+                )?;
+                Ok((address, client.unbind()))
+            })
+            .collect::<PyResult<Vec<_>>>()
+    })?;
 
-    Ok(album_results.audio_playlist_id)
+    let address = network.source_address();
+    let (_, client) = clients
+        .iter()
+        .find(|(a, _)| *a == address)
+        .unwrap_or(&clients[0]);
+    Ok(client.bind(py).clone())
 }
 
 #[cfg(test)]
@@ -173,15 +398,50 @@ mod test {
 
     #[tokio::test]
     async fn album_search() {
+        let results = yt_music_album_results("dreamcatcher raid of dream".to_string())
+            .await
+            .unwrap();
+        let album = yt_music_album(results[0].browse_id.clone().unwrap())
+            .await
+            .unwrap();
+        assert!(!album.track_metadata(None).is_empty());
         assert!(
-            yt_music_album_search("dreamcatcher raid of dream".to_string())
+            yt_music_album_results("dreamcatcher summer holiday".to_string())
                 .await
                 .is_ok()
         );
-        assert!(
-            yt_music_album_search("dreamcatcher summer holiday".to_string())
-                .await
-                .is_ok()
+    }
+
+    #[test]
+    fn test_album_track_metadata() {
+        let album: Album = serde_json::from_str(
+            r#"{
+                "title": "Raid of Dream",
+                "year": "2019",
+                "artists": [{"name": "Dreamcatcher", "id": "UC1"}],
+                "trackCount": 3,
+                "thumbnails": [{"url": "small"}, {"url": "large"}],
+                "tracks": [
+                    {"videoId": "a", "title": "Intro", "artists": [], "duration_seconds": 60},
+                    {"videoId": null, "title": "Unavailable", "artists": []},
+                    {"videoId": "c", "title": "Deja Vu", "artists": [{"name": "Dreamcatcher"}, {"name": "Other"}]}
+                ]
+            }"#,
+        )
+        .unwrap();
+        let metadata = album.track_metadata(None);
+        assert_eq!(2, metadata.len());
+        assert_eq!(Some("Dreamcatcher"), metadata[0].artist.as_deref());
+        assert_eq!(Some(Duration::from_secs(60)), metadata[0].duration);
+        assert_eq!(Some("large"), metadata[0].thumbnail.as_deref());
+        assert_eq!(
+            Some("https://music.youtube.com/watch?v=c"),
+            metadata[1].source_url.as_deref()
         );
+        assert_eq!(Some("Dreamcatcher, Other"), metadata[1].artist.as_deref());
+
+        let subset = album.track_metadata(Some(&[2, 1, 9]));
+        assert_eq!(1, subset.len());
+        assert_eq!(Some("Deja Vu"), subset[0].title.as_deref());
     }
 }
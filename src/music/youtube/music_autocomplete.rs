@@ -84,6 +84,7 @@ async fn autocomplete_ytmusic_helper(partial: &str) -> Result<Vec<String>> {
         ..Default::default()
     };
     let suggestions = CLIENT
+        .next()
         .post(AUTOCOMPLETE_ENDPOINT)
         .header("Accept", "*/*")
         .header("Content-Type", "application/json")
@@ -0,0 +1,38 @@
+from http.cookiejar import MozillaCookieJar
+
+import requests
+from requests.adapters import HTTPAdapter
+from ytmusicapi import YTMusic
+
+
+class SourceAddressAdapter(HTTPAdapter):
+    """Sends requests from a local address"""
+
+    def __init__(self, source_address, **kwargs):
+        self.source_address = source_address
+        super().__init__(**kwargs)
+
+    def init_poolmanager(self, *args, **kwargs):
+        kwargs["source_address"] = (self.source_address, 0)
+        super().init_poolmanager(*args, **kwargs)
+
+    def proxy_manager_for(self, *args, **kwargs):
+        kwargs["source_address"] = (self.source_address, 0)
+        return super().proxy_manager_for(*args, **kwargs)
+
+
+def ytmusic(cookies_file, proxy, source_address):
+    """YTMusic client using the bot's network options"""
+    session = requests.Session()
+    if cookies_file:
+        jar = MozillaCookieJar(cookies_file)
+        jar.load(ignore_discard=True, ignore_expires=True)
+        session.cookies = jar
+    if proxy:
+        # SOCKS proxies need the PySocks package
+        session.proxies = {"http": proxy, "https": proxy}
+    if source_address:
+        adapter = SourceAddressAdapter(source_address)
+        session.mount("http://", adapter)
+        session.mount("https://", adapter)
+    return YTMusic(requests_session=session)
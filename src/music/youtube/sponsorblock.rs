@@ -4,6 +4,7 @@ use poise::serenity_prelude::TypeMapKey;
 use regex::Regex;
 use serde::Deserialize;
 
+use crate::music::cache::{self, video_key, SKIPS_TTL};
 use crate::CLIENT;
 
 static YT_ID_RE: LazyLock<Regex> =
@@ -15,6 +16,12 @@ impl TypeMapKey for SBDuration {
     type Value = Option<Duration>;
 }
 
+pub struct SBSegments;
+
+impl TypeMapKey for SBSegments {
+    type Value = Vec<(Duration, Duration)>;
+}
+
 #[derive(Debug, Deserialize)]
 struct Segments {
     segment: (f64, f64),
@@ -26,12 +33,20 @@ pub async fn get_skips(url: &str) -> Vec<(Duration, Duration)> {
         None => return vec![],
     };
 
-    let segments = match get_segments(id).await {
+    let key = video_key("skips", url);
+    let segments = match cache::get(&key).await {
         Some(s) => s,
-        None => return vec![],
+        None => {
+            let segments = match get_segments(id).await {
+                Some(s) => postprocess_segments(s),
+                None => return vec![],
+            };
+            cache::put(&key, &segments, SKIPS_TTL).await;
+            segments
+        }
     };
 
-    postprocess_segments(segments)
+    segments
         .into_iter()
         .map(|(a, b)| (Duration::from_secs_f64(a), Duration::from_secs_f64(b)))
         .collect()
@@ -45,11 +60,16 @@ async fn get_segments(id: &str) -> Option<Vec<(f64, f64)>> {
     static URL: &str = "https://sponsor.ajay.app/api/skipSegments";
 
     let resp = CLIENT
+        .next()
         .get(URL)
         .query(&[("videoID", id), ("category", "music_offtopic")])
         .send()
         .await
         .ok()?;
+    // Videos without segments are not found
+    if resp.status() == reqwest::StatusCode::NOT_FOUND {
+        return Some(vec![]);
+    }
 
     let segments = resp
         .json::<Vec<Segments>>()
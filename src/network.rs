@@ -0,0 +1,207 @@
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use anyhow::Result;
+use reqwest::cookie::Jar;
+use reqwest::{Client, Proxy};
+use url::Url;
+
+/// Options used by every outbound request, the defaults until `init` is called
+static NETWORK: OnceLock<NetworkOptions> = OnceLock::new();
+
+/// How yt-dlp, the HTTP client and ytmusicapi connect to the internet
+#[derive(Debug, Default)]
+pub struct NetworkOptions {
+    /// Cookies in the Netscape format, which can be exported from a browser
+    pub cookies_file: Option<String>,
+    /// HTTP or SOCKS proxy URL
+    pub proxy: Option<String>,
+    /// Local addresses to send requests from, taking turns to spread out rate limits
+    pub source_addresses: Vec<IpAddr>,
+    next_address: AtomicUsize,
+}
+
+impl NetworkOptions {
+    pub fn new(
+        cookies_file: Option<String>,
+        proxy: Option<String>,
+        source_addresses: Vec<IpAddr>,
+    ) -> Self {
+        Self {
+            cookies_file,
+            proxy,
+            source_addresses,
+            next_address: AtomicUsize::new(0),
+        }
+    }
+
+    /// Next address of the pool, `None` to let the OS choose
+    pub fn source_address(&self) -> Option<IpAddr> {
+        if self.source_addresses.is_empty() {
+            return None;
+        }
+        let i = self.next_address.fetch_add(1, Ordering::Relaxed);
+        Some(self.source_addresses[i % self.source_addresses.len()])
+    }
+
+    /// yt-dlp arguments applying these options
+    pub fn ytdl_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if let Some(cookies_file) = &self.cookies_file {
+            args.push("--cookies".to_owned());
+            args.push(cookies_file.clone());
+        }
+        if let Some(proxy) = &self.proxy {
+            args.push("--proxy".to_owned());
+            args.push(proxy.clone());
+        }
+        if let Some(address) = self.source_address() {
+            args.push("--source-address".to_owned());
+            args.push(address.to_string());
+        }
+        args
+    }
+
+    /// HTTP clients for each source address, or a single client without any
+    fn http_clients(&self) -> Result<Vec<Client>> {
+        let jar = match &self.cookies_file {
+            Some(path) => Some(Arc::new(read_cookies(&std::fs::read_to_string(path)?))),
+            None => None,
+        };
+        let addresses = if self.source_addresses.is_empty() {
+            vec![None]
+        } else {
+            self.source_addresses.iter().copied().map(Some).collect()
+        };
+
+        addresses
+            .into_iter()
+            .map(|address| {
+                let mut builder = Client::builder()
+                    .use_rustls_tls()
+                    .timeout(Duration::from_secs(5))
+                    .local_address(address);
+                if let Some(jar) = &jar {
+                    builder = builder.cookie_provider(jar.clone());
+                }
+                if let Some(proxy) = &self.proxy {
+                    builder = builder.proxy(Proxy::all(proxy)?);
+                }
+                Ok(builder.build()?)
+            })
+            .collect()
+    }
+}
+
+/// Use these options for all outbound requests, which must happen before any are made
+pub fn init(options: NetworkOptions) {
+    let _ = NETWORK.set(options);
+}
+
+pub fn network() -> &'static NetworkOptions {
+    NETWORK.get_or_init(NetworkOptions::default)
+}
+
+/// HTTP clients sharing the configured network options, used in turn
+pub struct ClientPool {
+    clients: Vec<Client>,
+    next: AtomicUsize,
+}
+
+impl ClientPool {
+    pub fn new(options: &NetworkOptions) -> Result<Self> {
+        Ok(Self {
+            clients: options.http_clients()?,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    /// Client of the next source address
+    pub fn next(&self) -> &Client {
+        let i = self.next.fetch_add(1, Ordering::Relaxed);
+        &self.clients[i % self.clients.len()]
+    }
+}
+
+/// Load cookies from a Netscape format cookies file, skipping invalid lines
+fn read_cookies(text: &str) -> Jar {
+    let jar = Jar::default();
+    for line in text.lines() {
+        // curl and yt-dlp mark HttpOnly cookies with a prefix that would otherwise be a comment
+        let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+        if line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<_> = line.trim_end_matches('\r').split('\t').collect();
+        let [domain, _, path, secure, _, name, value] = fields[..] else {
+            continue;
+        };
+        let url = match Url::parse(&format!(
+            "https://{}{}",
+            domain.trim_start_matches('.'),
+            path
+        )) {
+            Ok(url) => url,
+            Err(_) => continue,
+        };
+
+        let mut cookie = format!("{}={}; Domain={}; Path={}", name, value, domain, path);
+        if secure == "TRUE" {
+            cookie.push_str("; Secure");
+        }
+        jar.add_cookie_str(&cookie, &url);
+    }
+    jar
+}
+
+#[cfg(test)]
+mod test {
+    use reqwest::cookie::CookieStore;
+
+    use super::*;
+
+    #[test]
+    fn test_ytdl_args() {
+        assert!(NetworkOptions::default().ytdl_args().is_empty());
+
+        let options = NetworkOptions::new(
+            Some("cookies.txt".to_owned()),
+            Some("socks5://127.0.0.1:1080".to_owned()),
+            vec!["10.0.0.1".parse().unwrap(), "10.0.0.2".parse().unwrap()],
+        );
+        assert_eq!(
+            options.ytdl_args(),
+            [
+                "--cookies",
+                "cookies.txt",
+                "--proxy",
+                "socks5://127.0.0.1:1080",
+                "--source-address",
+                "10.0.0.1"
+            ]
+        );
+        // The next invocation uses the next address
+        assert_eq!(options.ytdl_args()[5], "10.0.0.2");
+        assert_eq!(options.ytdl_args()[5], "10.0.0.1");
+    }
+
+    #[test]
+    fn test_read_cookies() {
+        let jar = read_cookies(
+            "# Netscape HTTP Cookie File\n\
+             .youtube.com\tTRUE\t/\tTRUE\t0\tPREF\tf6=40000000\n\
+             #HttpOnly_.youtube.com\tTRUE\t/\tTRUE\t0\tVISITOR_INFO1_LIVE\tabc\n\
+             invalid line\n",
+        );
+        let url = Url::parse("https://music.youtube.com/youtubei/v1/search").unwrap();
+        let cookies = jar.cookies(&url).unwrap();
+        let cookies = cookies.to_str().unwrap();
+        assert!(cookies.contains("PREF=f6=40000000"));
+        assert!(cookies.contains("VISITOR_INFO1_LIVE=abc"));
+
+        let other = Url::parse("https://sponsor.ajay.app/api/skipSegments").unwrap();
+        assert!(jar.cookies(&other).is_none());
+    }
+}
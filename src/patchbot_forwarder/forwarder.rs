@@ -53,7 +53,7 @@ pub async fn forward(
 
     // Replace Patchbot's tracking URL with the final redirect
     let redirected_url: Option<String> = if let Some(ref url) = embed.url {
-        if let Ok(resp) = CLIENT.get(url).send().await {
+        if let Ok(resp) = CLIENT.next().get(url).send().await {
             Some(resp.url().as_str().to_owned())
         } else {
             Some(url.clone())